@@ -0,0 +1,87 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use winston_transport::{
+    batch_transport::{BatchConfigBuilder, IntoBatchedTransport},
+    threaded_transport::{IntoThreadedTransport, OverflowPolicy, RingBufferConfig},
+    LogInfo, Transport,
+};
+
+/// A sink that only reads the records, so either handoff can be used
+struct CountingTransport {
+    count: AtomicUsize,
+    borrows: bool,
+}
+
+impl Transport for CountingTransport {
+    fn log(&self, _info: LogInfo) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn log_batch(&self, logs: Vec<LogInfo>) {
+        self.count.fetch_add(logs.len(), Ordering::Relaxed);
+    }
+
+    fn log_batch_ref(&self, logs: &[LogInfo]) {
+        self.count.fetch_add(logs.len(), Ordering::Relaxed);
+    }
+
+    fn borrows_batches(&self) -> bool {
+        self.borrows
+    }
+}
+
+fn counting(borrows: bool) -> CountingTransport {
+    CountingTransport {
+        count: AtomicUsize::new(0),
+        borrows,
+    }
+}
+
+const RECORDS: usize = 10_000;
+
+fn bench_batch_handoff(c: &mut Criterion) {
+    let mut group = c.benchmark_group("batch_handoff");
+    group.throughput(Throughput::Elements(RECORDS as u64));
+
+    for borrows in [false, true] {
+        let name = if borrows { "reused" } else { "owned" };
+
+        group.bench_with_input(
+            BenchmarkId::new("batched", name),
+            &borrows,
+            |b, &borrows| {
+                let config = BatchConfigBuilder::new().max_batch_size(256).build();
+                let transport = counting(borrows).into_batched_with_config(config);
+                b.iter(|| {
+                    for _ in 0..RECORDS {
+                        transport.log(LogInfo::new("info", "benchmark message"));
+                    }
+                    transport.flush().unwrap();
+                });
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("ring_buffer", name),
+            &borrows,
+            |b, &borrows| {
+                let transport = counting(borrows).into_threaded_ring(RingBufferConfig {
+                    capacity: 16_384,
+                    max_batch: 512,
+                    overflow: OverflowPolicy::Block,
+                });
+                b.iter(|| {
+                    for _ in 0..RECORDS {
+                        transport.log(LogInfo::new("info", "benchmark message"));
+                    }
+                    transport.flush().unwrap();
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_batch_handoff);
+criterion_main!(benches);
@@ -0,0 +1,96 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use winston_transport::{
+    threaded_transport::{IntoThreadedTransport, OverflowPolicy, RingBufferConfig},
+    LogInfo, Transport,
+};
+
+/// A sink that only counts records, so the benchmark measures the handoff.
+/// It borrows batches, so the ring buffer mode reuses its batch buffer.
+struct CountingTransport {
+    count: AtomicUsize,
+}
+
+impl Transport for CountingTransport {
+    fn log(&self, _info: LogInfo) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn log_batch(&self, logs: Vec<LogInfo>) {
+        self.count.fetch_add(logs.len(), Ordering::Relaxed);
+    }
+
+    fn log_batch_ref(&self, logs: &[LogInfo]) {
+        self.count.fetch_add(logs.len(), Ordering::Relaxed);
+    }
+
+    fn borrows_batches(&self) -> bool {
+        true
+    }
+}
+
+fn counting() -> CountingTransport {
+    CountingTransport {
+        count: AtomicUsize::new(0),
+    }
+}
+
+const RECORDS: usize = 10_000;
+
+fn bench_threaded_throughput(c: &mut Criterion) {
+    let mut group = c.benchmark_group("threaded_throughput");
+    group.throughput(Throughput::Elements(RECORDS as u64));
+
+    for producers in [1, 4] {
+        let per_producer = RECORDS / producers;
+
+        group.bench_with_input(
+            BenchmarkId::new("channel", producers),
+            &producers,
+            |b, &producers| {
+                let transport = counting().into_threaded();
+                b.iter(|| {
+                    std::thread::scope(|scope| {
+                        for _ in 0..producers {
+                            scope.spawn(|| {
+                                for _ in 0..per_producer {
+                                    transport.log(LogInfo::new("info", "benchmark message"));
+                                }
+                            });
+                        }
+                    });
+                    transport.flush().unwrap();
+                });
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("ring_buffer", producers),
+            &producers,
+            |b, &producers| {
+                let transport = counting().into_threaded_ring(RingBufferConfig {
+                    capacity: 16_384,
+                    max_batch: 512,
+                    overflow: OverflowPolicy::Block,
+                });
+                b.iter(|| {
+                    std::thread::scope(|scope| {
+                        for _ in 0..producers {
+                            scope.spawn(|| {
+                                for _ in 0..per_producer {
+                                    transport.log(LogInfo::new("info", "benchmark message"));
+                                }
+                            });
+                        }
+                    });
+                    transport.flush().unwrap();
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_threaded_throughput);
+criterion_main!(benches);
@@ -1,14 +1,125 @@
-use crate::{log_query::LogQuery, Transport};
+mod adaptive;
+mod grouping;
+mod spool;
+
+pub use adaptive::AdaptiveConfig;
+pub use grouping::KeyExtractor;
+pub use spool::SpoolConfig;
+
+use crate::{
+    clock::{Clock, ClockListener, SystemClock},
+    executor::{ExecutorTask, SharedExecutor, TaskStatus, WorkerHandle, TASK_BUDGET},
+    log_query::LogQuery,
+    receipt::{self, FlushHandle, Receipt, ReceiptSender},
+    stats::{AliveGuard, StatsRecorder, TransportStats},
+    Transport,
+};
+use adaptive::AdaptiveTuner;
 use logform::{Format, LogInfo};
+use spool::{Spool, SpoolEntry};
 use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, Write},
     marker::PhantomData,
-    sync::{mpsc::Sender, Arc},
-    thread::{self, JoinHandle},
+    sync::{
+        mpsc::{Receiver, Sender, TryRecvError},
+        Arc,
+    },
+    thread,
     time::{Duration, Instant},
 };
 
-/// Configuration for batch behavior
+/// A spool and the records it held when opened
+type OpenedSpool = (Spool, Vec<SpoolEntry>);
+
+/// Estimates how many bytes a record will occupy in the sink's payload
+pub trait SizeEstimator: Send + Sync {
+    fn estimate(&self, info: &LogInfo) -> usize;
+}
+
+impl<F> SizeEstimator for F
+where
+    F: Fn(&LogInfo) -> usize + Send + Sync,
+{
+    fn estimate(&self, info: &LogInfo) -> usize {
+        self(info)
+    }
+}
+
+/// Estimates a record's size as the length of its serialized JSON
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonSizeEstimator;
+
+impl SizeEstimator for JsonSizeEstimator {
+    fn estimate(&self, info: &LogInfo) -> usize {
+        // Count the bytes instead of collecting them into a buffer
+        struct Counter(usize);
+
+        impl Write for Counter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0 += buf.len();
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut counter = Counter(0);
+        let _ = serde_json::to_writer(&mut counter, info);
+        counter.0
+    }
+}
+
+/// Outcome of delivering one batch to the inner transport
 #[derive(Debug, Clone)]
+pub enum BatchOutcome {
+    /// The batch was handed to the inner transport and flushed successfully
+    Delivered { batch_id: u64, size: usize },
+    /// Flushing the inner transport failed; `records` holds the whole batch
+    Failed {
+        batch_id: u64,
+        error: String,
+        records: Vec<LogInfo>,
+    },
+}
+
+/// Receives the outcome of every delivered batch, on the batch worker
+pub trait BatchObserver: Send + Sync {
+    fn on_batch(&self, outcome: BatchOutcome);
+}
+
+impl<F> BatchObserver for F
+where
+    F: Fn(BatchOutcome) + Send + Sync,
+{
+    fn on_batch(&self, outcome: BatchOutcome) {
+        self(outcome)
+    }
+}
+
+impl BatchObserver for Sender<BatchOutcome> {
+    fn on_batch(&self, outcome: BatchOutcome) {
+        // The receiving side may have gone away, which is not the worker's concern
+        let _ = self.send(outcome);
+    }
+}
+
+/// How `BatchedTransport::query` treats records that are still pending
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueryMode {
+    /// Flush the pending batches, then query the inner transport
+    #[default]
+    Flush,
+    /// Merge matching pending records into the inner transport's results
+    /// without flushing
+    MergePending,
+}
+
+/// Configuration for batch behavior
+#[derive(Clone)]
 pub struct BatchConfig {
     /// Maximum number of logs to batch before flushing
     pub max_batch_size: usize,
@@ -16,6 +127,35 @@ pub struct BatchConfig {
     pub max_batch_time: Duration,
     /// Whether to flush immediately on Drop
     pub flush_on_drop: bool,
+    /// Maximum estimated size in bytes of a batch; batches are cut before they
+    /// would exceed it
+    pub max_batch_bytes: Option<usize>,
+    /// Estimates record sizes for `max_batch_bytes`
+    pub size_estimator: Arc<dyn SizeEstimator>,
+    /// Tunes batch size and time to the inner transport's throughput, starting
+    /// from `max_batch_size` and `max_batch_time`
+    pub adaptive: Option<AdaptiveConfig>,
+    /// Appends records to a write-ahead spool on disk before `log()` returns
+    pub spool: Option<SpoolConfig>,
+    /// Notified of every batch's outcome, for example to persist, alert on or
+    /// resubmit failed records
+    pub observer: Option<Arc<dyn BatchObserver>>,
+    /// Keeps a separate batch per key, each with its own size and time
+    /// triggers, so every delivered batch shares one key
+    pub group_by: Option<Arc<dyn KeyExtractor>>,
+    /// Maximum number of keys with a pending batch; a new key beyond it first
+    /// delivers the batch that has been open the longest
+    pub max_open_keys: usize,
+    /// Delivers every pending record as soon as one at this level or a more
+    /// severe one arrives
+    pub flush_on_level: Option<String>,
+    /// Severity of each level for `flush_on_level`, lower being more severe;
+    /// defaults to the syslog levels
+    pub levels: HashMap<String, usize>,
+    /// Whether queries flush pending records or merge them into the results
+    pub query_mode: QueryMode,
+    /// Time source for the batch timers, replaceable with a `TestClock`
+    pub clock: Arc<dyn Clock>,
 }
 
 impl Default for BatchConfig {
@@ -24,15 +164,49 @@ impl Default for BatchConfig {
             max_batch_size: 100,
             max_batch_time: Duration::from_millis(500),
             flush_on_drop: true,
+            max_batch_bytes: None,
+            size_estimator: Arc::new(JsonSizeEstimator),
+            adaptive: None,
+            spool: None,
+            observer: None,
+            group_by: None,
+            max_open_keys: 1000,
+            flush_on_level: None,
+            levels: logform::config::syslog::levels(),
+            query_mode: QueryMode::Flush,
+            clock: Arc::new(SystemClock),
         }
     }
 }
 
+impl fmt::Debug for BatchConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BatchConfig")
+            .field("max_batch_size", &self.max_batch_size)
+            .field("max_batch_time", &self.max_batch_time)
+            .field("flush_on_drop", &self.flush_on_drop)
+            .field("max_batch_bytes", &self.max_batch_bytes)
+            .field("adaptive", &self.adaptive)
+            .field("spool", &self.spool)
+            .field("observer", &self.observer.is_some())
+            .field("group_by", &self.group_by.is_some())
+            .field("max_open_keys", &self.max_open_keys)
+            .field("flush_on_level", &self.flush_on_level)
+            .field("levels", &self.levels)
+            .field("query_mode", &self.query_mode)
+            .finish_non_exhaustive()
+    }
+}
+
 /// Internal message types for the batch thread
 #[derive(Debug)]
 enum BatchMessage {
-    Log(LogInfo),
-    Flush(Sender<Result<(), String>>),
+    Log(LogInfo, Option<ReceiptSender>),
+    /// A record written to the spool, kept in memory unless it was spilled
+    Spooled(SpoolEntry, Option<LogInfo>, Option<ReceiptSender>),
+    Flush(ReceiptSender),
+    /// The clock moved; fire due batch timers and then complete the receipt
+    Tick(ReceiptSender),
     Query(
         LogQuery,
         std::sync::mpsc::Sender<Result<Vec<LogInfo>, String>>,
@@ -43,10 +217,15 @@ enum BatchMessage {
 /// A transport wrapper that batches log messages before sending them to the underlying transport
 pub struct BatchedTransport<T: Transport + Send + 'static> {
     sender: std::sync::mpsc::Sender<BatchMessage>,
-    thread_handle: Option<JoinHandle<()>>,
+    // Holds a sender too, so it is also declared before `worker`
+    _clock_listener: Arc<ClockListener>,
+    // Declared after `sender` so a shared task sees the channel disconnect when dropped
+    worker: Option<WorkerHandle>,
     level: Option<String>,
     format: Option<Arc<dyn Format<Input = LogInfo> + Send + Sync>>,
     config: BatchConfig,
+    stats: Arc<StatsRecorder>,
+    spool: Option<Arc<Spool>>,
     _phantom: PhantomData<T>,
 }
 
@@ -56,24 +235,45 @@ impl<T: Transport + Send + 'static> BatchedTransport<T> {
         Self::with_config(transport, BatchConfig::default())
     }
 
-    /// Creates a new BatchedTransport with custom configuration
+    /// Creates a new BatchedTransport with custom configuration. If the spool
+    /// cannot be opened, the error is reported and records are not spooled.
     pub fn with_config(transport: T, config: BatchConfig) -> Self {
+        let spool = Self::open_spool_or_report(&config);
+        Self::start(transport, config, spool)
+    }
+
+    /// Creates a new BatchedTransport with custom configuration, failing if
+    /// the spool cannot be opened
+    pub fn try_with_config(transport: T, config: BatchConfig) -> Result<Self, String> {
+        let spool = Self::open_spool(&config)?;
+        Ok(Self::start(transport, config, spool))
+    }
+
+    fn start(transport: T, config: BatchConfig, spool: Option<OpenedSpool>) -> Self {
         let level = transport.get_level().cloned();
         let format = transport.get_format();
 
         let (sender, receiver) = std::sync::mpsc::channel();
-        let batch_config = config.clone();
+        let stats = Arc::new(StatsRecorder::new());
+        let spool = Self::replay_spool(spool, &sender, &stats);
+        let worker = BatchWorker::new(transport, config.clone(), stats.clone(), spool.clone());
 
         let thread_handle = thread::spawn(move || {
-            Self::run_batch_thread(transport, receiver, batch_config);
+            Self::run_batch_thread(worker, receiver);
         });
 
+        let worker = WorkerHandle::Thread(thread_handle);
+        let clock_listener = Self::subscribe_clock(&config, &sender, &worker);
+
         Self {
             sender,
-            thread_handle: Some(thread_handle),
+            _clock_listener: clock_listener,
+            worker: Some(worker),
             level,
             format,
             config,
+            stats,
+            spool,
             _phantom: PhantomData,
         }
     }
@@ -84,64 +284,124 @@ impl<T: Transport + Send + 'static> BatchedTransport<T> {
         let format = transport.get_format();
 
         let (sender, receiver) = std::sync::mpsc::channel();
-        let batch_config = config.clone();
+        let stats = Arc::new(StatsRecorder::new());
+        let spool = Self::replay_spool(Self::open_spool_or_report(&config), &sender, &stats);
+        let worker = BatchWorker::new(transport, config.clone(), stats.clone(), spool.clone());
 
         let thread_handle = thread::Builder::new()
             .name(thread_name)
             .spawn(move || {
-                Self::run_batch_thread(transport, receiver, batch_config);
+                Self::run_batch_thread(worker, receiver);
             })
             .expect("Failed to spawn batch transport thread");
 
+        let worker = WorkerHandle::Thread(thread_handle);
+        let clock_listener = Self::subscribe_clock(&config, &sender, &worker);
+
         Self {
             sender,
-            thread_handle: Some(thread_handle),
+            _clock_listener: clock_listener,
+            worker: Some(worker),
             level,
             format,
             config,
+            stats,
+            spool,
             _phantom: PhantomData,
         }
     }
 
-    /// The main batching loop running on the background thread
-    fn run_batch_thread(
-        transport: T,
-        receiver: std::sync::mpsc::Receiver<BatchMessage>,
-        config: BatchConfig,
-    ) {
-        let mut batch = Vec::new();
-        let mut last_flush = Instant::now();
-
-        // Helper function to flush the current batch
-        let flush_batch = |batch: &mut Vec<LogInfo>| {
-            if !batch.is_empty() {
-                // Log each item in the batch
-                /*for log_info in batch.drain(..) {
-                    transport.log(log_info);
-                }*/
-                // Drain the batch and pass the collected Vec to log_batch
-                transport.log_batch(batch.drain(..).collect());
-                // Flush the underlying transport
-                let _ = transport.flush();
-            }
+    /// Creates a BatchedTransport that runs on a shared executor instead of its
+    /// own thread
+    pub fn with_executor(transport: T, config: BatchConfig, executor: &SharedExecutor) -> Self {
+        let level = transport.get_level().cloned();
+        let format = transport.get_format();
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let stats = Arc::new(StatsRecorder::new());
+        let spool = Self::replay_spool(Self::open_spool_or_report(&config), &sender, &stats);
+
+        let task_handle = executor.spawn(BatchTask {
+            worker: BatchWorker::new(transport, config.clone(), stats.clone(), spool.clone()),
+            receiver,
+        });
+
+        let worker = WorkerHandle::Shared(task_handle);
+        let clock_listener = Self::subscribe_clock(&config, &sender, &worker);
+
+        Self {
+            sender,
+            _clock_listener: clock_listener,
+            worker: Some(worker),
+            level,
+            format,
+            config,
+            stats,
+            spool,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Opens the configured spool, if any, with the records left over from a
+    /// previous run
+    fn open_spool(config: &BatchConfig) -> Result<Option<OpenedSpool>, String> {
+        let Some(spool_config) = config.spool.clone() else {
+            return Ok(None);
         };
+        let dir = spool_config.dir.clone();
+        Spool::open(spool_config)
+            .map(Some)
+            .map_err(|e| format!("Failed to open spool directory {}: {}", dir.display(), e))
+    }
 
-        loop {
-            // Calculate timeout for receiving messages
-            let time_since_last_flush = last_flush.elapsed();
-            let timeout = if batch.is_empty() {
-                // If no logs pending, wait indefinitely
-                None
-            } else if time_since_last_flush >= config.max_batch_time {
-                // Time to flush, no wait
-                Some(Duration::from_millis(0))
-            } else {
-                // Wait for remaining time
-                Some(config.max_batch_time - time_since_last_flush)
-            };
+    fn open_spool_or_report(config: &BatchConfig) -> Option<OpenedSpool> {
+        Self::open_spool(config).unwrap_or_else(|e| {
+            report!("{}; records will not be spooled", e);
+            None
+        })
+    }
+
+    /// Queues the records left over from a previous run ahead of any new ones
+    fn replay_spool(
+        spool: Option<OpenedSpool>,
+        sender: &Sender<BatchMessage>,
+        stats: &StatsRecorder,
+    ) -> Option<Arc<Spool>> {
+        let (spool, replay) = spool?;
+        for entry in replay {
+            let _ = sender.send(BatchMessage::Spooled(entry, None, None));
+            stats.record_enqueued();
+        }
+        Some(Arc::new(spool))
+    }
+
+    /// Lets a virtual clock wake the worker whenever it is advanced, and waits
+    /// for the due batch timers to fire
+    fn subscribe_clock(
+        config: &BatchConfig,
+        sender: &Sender<BatchMessage>,
+        worker: &WorkerHandle,
+    ) -> Arc<ClockListener> {
+        let sender = sender.clone();
+        let waker = worker.waker();
+        let listener: Arc<ClockListener> = Arc::new(move || {
+            let (receipt_sender, receipt) = receipt::receipt();
+            if sender.send(BatchMessage::Tick(receipt_sender)).is_ok() {
+                if let Some(waker) = &waker {
+                    waker.wake();
+                }
+                let _ = receipt.wait();
+            }
+        });
+        config.clock.subscribe(Arc::downgrade(&listener));
+        listener
+    }
 
+    /// The main batching loop running on the background thread
+    fn run_batch_thread(mut worker: BatchWorker<T>, receiver: Receiver<BatchMessage>) {
+        loop {
             // Try to receive a message with timeout
-            let message_result = if let Some(timeout) = timeout {
+            let message_result = if let Some(timeout) = worker.timeout() {
                 receiver.recv_timeout(timeout)
             } else {
                 receiver
@@ -150,52 +410,44 @@ impl<T: Transport + Send + 'static> BatchedTransport<T> {
             };
 
             match message_result {
-                Ok(BatchMessage::Log(info)) => {
-                    batch.push(info);
-
-                    // Check if we should flush due to batch size
-                    if batch.len() >= config.max_batch_size {
-                        flush_batch(&mut batch);
-                        last_flush = Instant::now();
+                Ok(message) => {
+                    if !worker.handle_message(message) {
+                        break;
                     }
                 }
-                Ok(BatchMessage::Flush(response_sender)) => {
-                    flush_batch(&mut batch);
-                    last_flush = Instant::now();
-                    let _ = response_sender.send(Ok(()));
-                }
-                Ok(BatchMessage::Query(query, response_sender)) => {
-                    // For queries, we need to flush pending logs first
-                    flush_batch(&mut batch);
-                    last_flush = Instant::now();
-
-                    let result = transport.query(&query);
-                    let _ = response_sender.send(result);
-                }
-                Ok(BatchMessage::Shutdown) => {
-                    // Flush any remaining logs before shutting down
-                    flush_batch(&mut batch);
-                    break;
-                }
                 Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
-                    // Timeout occurred, flush if we have logs and enough time has passed
-                    if !batch.is_empty() && last_flush.elapsed() >= config.max_batch_time {
-                        flush_batch(&mut batch);
-                        last_flush = Instant::now();
-                    }
+                    worker.on_timeout();
                 }
                 Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
                     // Channel disconnected, flush and exit
-                    flush_batch(&mut batch);
+                    let _ = worker.flush_batch();
                     break;
                 }
             }
         }
     }
 
+    /// Sends a message to the batch worker and wakes it if it runs on an executor
+    fn send(&self, message: BatchMessage) -> Result<(), ()> {
+        let is_log = matches!(message, BatchMessage::Log(..) | BatchMessage::Spooled(..));
+        if self.sender.send(message).is_err() {
+            if is_log {
+                self.stats.record_dropped();
+            }
+            return Err(());
+        }
+        if is_log {
+            self.stats.record_enqueued();
+        }
+        if let Some(worker) = &self.worker {
+            worker.wake();
+        }
+        Ok(())
+    }
+
     /// Gracefully shuts down the batching thread
     pub fn shutdown(mut self) -> Result<(), String> {
-        if let Some(handle) = self.thread_handle.take() {
+        if let Some(handle) = self.worker.take() {
             self.sender
                 .send(BatchMessage::Shutdown)
                 .map_err(|_| "Failed to send shutdown signal")?;
@@ -205,6 +457,59 @@ impl<T: Transport + Send + 'static> BatchedTransport<T> {
         Ok(())
     }
 
+    /// Queues a record for batching, writing it to the spool first if one is
+    /// configured
+    fn enqueue(&self, info: LogInfo, receipt: Option<ReceiptSender>) {
+        let Some(spool) = &self.spool else {
+            // Non-blocking send - logs are queued for batching
+            let _ = self.send(BatchMessage::Log(info, receipt));
+            return;
+        };
+
+        // Past the memory limit only the record's position on disk is queued
+        let in_memory = spool.reserve_memory();
+        let mut receipt = receipt;
+        let appended = spool.append(&info, |entry| {
+            let record = in_memory.then_some(info.clone());
+            self.send(BatchMessage::Spooled(entry, record, receipt.take()))
+        });
+        if !matches!(appended, Ok(Ok(()))) && in_memory {
+            spool.release_memory();
+        }
+        if let Err(e) = appended {
+            report!("Dropping record: {}", e);
+            self.stats.record_dropped();
+            if let Some(receipt) = receipt {
+                receipt.complete(Err(e));
+            }
+        }
+    }
+
+    /// Logs a record and returns a receipt that completes once the batch
+    /// holding it has been delivered and the wrapped transport flushed
+    pub fn log_with_receipt(&self, info: LogInfo) -> Receipt {
+        let (receipt_sender, receipt) = receipt::receipt();
+        self.enqueue(info, Some(receipt_sender));
+        receipt
+    }
+
+    /// Requests a flush of the pending batches without waiting for it,
+    /// returning a handle that completes with the flush result
+    pub fn flush_async(&self) -> FlushHandle {
+        let (receipt_sender, handle) = receipt::receipt();
+        if self.send(BatchMessage::Flush(receipt_sender)).is_err() {
+            return Receipt::ready(Err(
+                "Failed to send flush message to batch thread".to_string()
+            ));
+        }
+        handle
+    }
+
+    /// Returns a snapshot of the queue and delivery statistics
+    pub fn stats(&self) -> TransportStats {
+        self.stats.snapshot()
+    }
+
     /// Gets the current batch configuration
     pub fn config(&self) -> &BatchConfig {
         &self.config
@@ -213,20 +518,11 @@ impl<T: Transport + Send + 'static> BatchedTransport<T> {
 
 impl<T: Transport + Send + 'static> Transport for BatchedTransport<T> {
     fn log(&self, info: LogInfo) {
-        // Non-blocking send - logs are queued for batching
-        let _ = self.sender.send(BatchMessage::Log(info));
+        self.enqueue(info, None);
     }
 
     fn flush(&self) -> Result<(), String> {
-        let (response_sender, response_receiver) = std::sync::mpsc::channel();
-
-        self.sender
-            .send(BatchMessage::Flush(response_sender))
-            .map_err(|_| "Failed to send flush message to batch thread")?;
-
-        response_receiver
-            .recv()
-            .map_err(|_| "Failed to receive flush response from background thread")?
+        self.flush_async().wait()
     }
 
     fn get_level(&self) -> Option<&String> {
@@ -240,8 +536,7 @@ impl<T: Transport + Send + 'static> Transport for BatchedTransport<T> {
     fn query(&self, options: &LogQuery) -> Result<Vec<LogInfo>, String> {
         let (response_sender, response_receiver) = std::sync::mpsc::channel();
 
-        self.sender
-            .send(BatchMessage::Query(options.clone(), response_sender))
+        self.send(BatchMessage::Query(options.clone(), response_sender))
             .map_err(|_| "Failed to send query message to batch thread")?;
 
         response_receiver
@@ -253,7 +548,7 @@ impl<T: Transport + Send + 'static> Transport for BatchedTransport<T> {
 impl<T: Transport + Send + 'static> Drop for BatchedTransport<T> {
     fn drop(&mut self) {
         if self.config.flush_on_drop {
-            if let Some(handle) = self.thread_handle.take() {
+            if let Some(handle) = self.worker.take() {
                 let _ = self.sender.send(BatchMessage::Shutdown);
                 let _ = handle.join();
             }
@@ -281,16 +576,536 @@ pub trait IntoBatchedTransport: Transport + Send + Sized + 'static {
     ) -> BatchedTransport<Self> {
         BatchedTransport::with_thread_name(self, config, thread_name)
     }
+
+    /// Wraps this transport in a BatchedTransport with default configuration,
+    /// running on a shared executor
+    fn into_batched_on(self, executor: &SharedExecutor) -> BatchedTransport<Self> {
+        BatchedTransport::with_executor(self, BatchConfig::default(), executor)
+    }
+
+    /// Wraps this transport in a BatchedTransport with custom configuration,
+    /// running on a shared executor
+    fn into_batched_with_config_on(
+        self,
+        config: BatchConfig,
+        executor: &SharedExecutor,
+    ) -> BatchedTransport<Self> {
+        BatchedTransport::with_executor(self, config, executor)
+    }
 }
 
 // Implement for all transports
 impl<T: Transport + Send + 'static> IntoBatchedTransport for T {}
 
+/// Records waiting to be delivered together
+struct PendingBatch {
+    records: Vec<LogInfo>,
+    // Estimated size of the records, tracked only with `max_batch_bytes`
+    bytes: usize,
+    // When the oldest pending record joined the batch
+    started: Option<Instant>,
+    last_flush: Instant,
+    // Sequence numbers of the oldest and newest spooled records in the batch
+    first_seq: Option<u64>,
+    last_seq: Option<u64>,
+    // Receipts of records in the batch, completed once it is delivered
+    receipts: Vec<ReceiptSender>,
+}
+
+impl PendingBatch {
+    fn new(now: Instant, records: Vec<LogInfo>) -> Self {
+        Self {
+            records,
+            bytes: 0,
+            started: None,
+            last_flush: now,
+            first_seq: None,
+            last_seq: None,
+            receipts: Vec::new(),
+        }
+    }
+}
+
+/// Upper bound on emptied buffers kept around for reuse
+const MAX_SPARE_BUFFERS: usize = 16;
+
+/// Batching state shared by the dedicated thread loop and the executor task
+struct BatchWorker<T: Transport> {
+    transport: T,
+    config: BatchConfig,
+    // One batch per key with `group_by`, otherwise a single batch under `None`
+    batches: HashMap<Option<String>, PendingBatch>,
+    // Total number of records across all pending batches
+    pending: usize,
+    // Emptied buffers of batches delivered by reference, kept for reuse when
+    // the inner transport borrows batches
+    spare_buffers: Vec<Vec<LogInfo>>,
+    tuner: Option<AdaptiveTuner>,
+    next_batch_id: u64,
+    spool: Option<Arc<Spool>>,
+    // Sequence number of the newest spooled record delivered so far
+    delivered_seq: Option<u64>,
+    // Severity at or below which a record flushes everything pending
+    flush_severity: Option<usize>,
+    stats: Arc<StatsRecorder>,
+    _alive: AliveGuard,
+}
+
+impl<T: Transport> BatchWorker<T> {
+    fn new(
+        transport: T,
+        config: BatchConfig,
+        stats: Arc<StatsRecorder>,
+        spool: Option<Arc<Spool>>,
+    ) -> Self {
+        let tuner = config.adaptive.clone().map(|adaptive| {
+            AdaptiveTuner::new(adaptive, config.max_batch_size, config.max_batch_time)
+        });
+        stats.set_batch_limits(
+            tuner
+                .as_ref()
+                .map_or(config.max_batch_size, AdaptiveTuner::batch_size),
+            tuner
+                .as_ref()
+                .map_or(config.max_batch_time, AdaptiveTuner::batch_time),
+        );
+
+        let flush_severity = config.flush_on_level.as_ref().and_then(|level| {
+            let severity = severity(&config.levels, level);
+            if severity.is_none() {
+                report!(
+                    "flush_on_level {:?} is not a known level, ignoring it",
+                    level
+                );
+            }
+            severity
+        });
+
+        Self {
+            transport,
+            config,
+            batches: HashMap::new(),
+            pending: 0,
+            spare_buffers: Vec::new(),
+            tuner,
+            next_batch_id: 0,
+            spool,
+            delivered_seq: None,
+            flush_severity,
+            stats: stats.clone(),
+            _alive: AliveGuard(stats),
+        }
+    }
+
+    /// Flushes every pending batch to the underlying transport, returning the
+    /// first failure. Failures also reach the stats and the observer, so only
+    /// an explicit flush needs to pass the result on
+    fn flush_batch(&mut self) -> Result<(), String> {
+        // Oldest batch first, so records keep their order across keys
+        let mut keys: Vec<(Option<Instant>, Option<String>)> = self
+            .batches
+            .iter()
+            .map(|(key, batch)| (batch.started, key.clone()))
+            .collect();
+        keys.sort();
+
+        let mut result = Ok(());
+        for (_, key) in keys {
+            let flushed = self.flush_group(&key);
+            if result.is_ok() {
+                result = flushed;
+            }
+        }
+        result
+    }
+
+    /// Flushes the batch pending under `key`, returning the inner transport's
+    /// flush result
+    fn flush_group(&mut self, key: &Option<String>) -> Result<(), String> {
+        // Grouped batches are dropped once delivered so they stop counting
+        // towards `max_open_keys`
+        let batch = if self.config.group_by.is_some() {
+            self.batches.remove(key)
+        } else {
+            let now = self.config.clock.now();
+            let spare = self.spare_buffers.pop().unwrap_or_default();
+            self.batches.get_mut(key).map(|batch| {
+                let fresh = PendingBatch::new(now, spare);
+                std::mem::replace(batch, fresh)
+            })
+        };
+        let Some(batch) = batch else {
+            return Ok(());
+        };
+        if batch.records.is_empty() {
+            return Ok(());
+        }
+
+        let records = batch.records.len();
+        self.pending -= records;
+        let batch_id = self.next_batch_id;
+        self.next_batch_id += 1;
+        // Kept only so a failed batch can be handed back to the observer
+        let retained = self.config.observer.as_ref().map(|_| batch.records.clone());
+
+        let started = self.config.clock.now();
+        if self.transport.borrows_batches() {
+            let mut buffer = batch.records;
+            self.transport.log_batch_ref(&buffer);
+            buffer.clear();
+            if self.spare_buffers.len() < MAX_SPARE_BUFFERS {
+                self.spare_buffers.push(buffer);
+            }
+        } else {
+            self.transport.log_batch(batch.records);
+        }
+        // Flush the underlying transport
+        let result = self.transport.flush();
+        let finished = self.config.clock.now();
+        let delivery = finished.saturating_duration_since(started);
+        self.stats.record_flush(records, delivery, result.is_ok());
+        self.stats.set_in_flight(self.pending);
+
+        if let (Some(spool), Some(last_seq)) = (&self.spool, batch.last_seq) {
+            // A failed batch stays on disk for the next start, and no longer
+            // holds back the deletion of later segments
+            if result.is_err() {
+                spool.fail(batch.first_seq.unwrap_or(last_seq), last_seq);
+            }
+            self.delivered_seq = self.delivered_seq.max(Some(last_seq));
+            // Records older than any still pending elsewhere are all handled
+            let oldest_pending = self
+                .batches
+                .values()
+                .filter_map(|batch| batch.first_seq)
+                .min();
+            let acked = match oldest_pending {
+                Some(oldest) => oldest.checked_sub(1).min(self.delivered_seq),
+                None => self.delivered_seq,
+            };
+            if let Some(seq) = acked {
+                spool.ack(seq);
+            }
+        }
+
+        if let Some(tuner) = &mut self.tuner {
+            let end_to_end = batch.started.map_or(delivery, |batch_started| {
+                finished.saturating_duration_since(batch_started)
+            });
+            tuner.observe(records, delivery, end_to_end, result.is_ok());
+            self.stats
+                .set_batch_limits(tuner.batch_size(), tuner.batch_time());
+        }
+
+        for receipt in batch.receipts {
+            receipt.complete(result.clone());
+        }
+
+        if let (Some(observer), Some(retained)) = (&self.config.observer, retained) {
+            observer.on_batch(match &result {
+                Ok(()) => BatchOutcome::Delivered {
+                    batch_id,
+                    size: records,
+                },
+                Err(error) => BatchOutcome::Failed {
+                    batch_id,
+                    error: error.clone(),
+                    records: retained,
+                },
+            });
+        }
+        result
+    }
+
+    /// The batch size currently in effect
+    fn max_batch_size(&self) -> usize {
+        self.tuner
+            .as_ref()
+            .map_or(self.config.max_batch_size, AdaptiveTuner::batch_size)
+    }
+
+    /// The batch time currently in effect
+    fn max_batch_time(&self) -> Duration {
+        self.tuner
+            .as_ref()
+            .map_or(self.config.max_batch_time, AdaptiveTuner::batch_time)
+    }
+
+    /// How long to wait for the next message before the time trigger fires
+    fn timeout(&self) -> Option<Duration> {
+        self.deadline()
+            .and_then(|deadline| self.config.clock.wait_time(deadline))
+    }
+
+    /// The real instant at which an executor should run the task again
+    fn wake_at(&self) -> Option<Instant> {
+        self.timeout().map(|timeout| Instant::now() + timeout)
+    }
+
+    /// When the next pending batch has to be flushed, if there is one
+    fn deadline(&self) -> Option<Instant> {
+        // If no logs pending, wait indefinitely
+        let max_batch_time = self.max_batch_time();
+        self.batches
+            .values()
+            .filter(|batch| !batch.records.is_empty())
+            .map(|batch| batch.last_flush + max_batch_time)
+            .min()
+    }
+
+    /// Flushes the batches that have been pending longer than `max_batch_time`
+    fn on_timeout(&mut self) {
+        let max_batch_time = self.max_batch_time();
+        let now = self.config.clock.now();
+        let due: Vec<Option<String>> = self
+            .batches
+            .iter()
+            .filter(|(_, batch)| {
+                !batch.records.is_empty()
+                    && now.saturating_duration_since(batch.last_flush) >= max_batch_time
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in due {
+            let _ = self.flush_group(&key);
+        }
+    }
+
+    /// Adds a record to its pending batch, flushing on the size and byte limits
+    /// and on `flush_on_level`
+    fn add_record(&mut self, info: LogInfo, seq: Option<u64>, receipt: Option<ReceiptSender>) {
+        let urgent = self.flush_severity.is_some_and(|threshold| {
+            severity(&self.config.levels, &info.level).is_some_and(|severity| severity <= threshold)
+        });
+        let key = self
+            .config
+            .group_by
+            .as_ref()
+            .and_then(|group_by| group_by.key(&info));
+
+        if !self.batches.contains_key(&key)
+            && self.batches.len() >= self.config.max_open_keys.max(1)
+        {
+            // Make room by delivering the batch that has been open the longest
+            let oldest = self
+                .batches
+                .iter()
+                .min_by_key(|(_, batch)| batch.started)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                let _ = self.flush_group(&oldest);
+            }
+        }
+
+        let size = self
+            .config
+            .max_batch_bytes
+            .map(|max_bytes| {
+                let size = self.config.size_estimator.estimate(&info);
+                if size > max_bytes {
+                    report!(
+                        "Record of {} bytes exceeds max_batch_bytes of {}, delivering it on its own",
+                        size, max_bytes
+                    );
+                    self.stats.record_oversized();
+                }
+                size
+            })
+            .unwrap_or(0);
+
+        // Cut the batch before it would exceed the byte limit
+        let over_bytes = self.batches.get(&key).is_some_and(|batch| {
+            !batch.records.is_empty()
+                && self
+                    .config
+                    .max_batch_bytes
+                    .is_some_and(|max_bytes| batch.bytes + size > max_bytes)
+        });
+        if over_bytes {
+            let _ = self.flush_group(&key);
+        }
+
+        let now = self.config.clock.now();
+        let spare_buffers = &mut self.spare_buffers;
+        let batch = self
+            .batches
+            .entry(key.clone())
+            .or_insert_with(|| PendingBatch::new(now, spare_buffers.pop().unwrap_or_default()));
+        if batch.records.is_empty() {
+            batch.started = Some(now);
+        }
+        batch.records.push(info);
+        batch.bytes += size;
+        if seq.is_some() {
+            batch.first_seq = batch.first_seq.or(seq);
+            batch.last_seq = seq;
+        }
+        batch.receipts.extend(receipt);
+        let batch_len = batch.records.len();
+        let bytes_full = self
+            .config
+            .max_batch_bytes
+            .is_some_and(|max_bytes| batch.bytes >= max_bytes);
+
+        self.pending += 1;
+        self.stats.set_in_flight(self.pending);
+
+        // Check if we should flush due to batch size or byte limit
+        if batch_len >= self.max_batch_size() || bytes_full {
+            let _ = self.flush_group(&key);
+        }
+
+        if urgent {
+            let _ = self.flush_batch();
+        }
+    }
+
+    /// Answers a query from the inner transport's results merged with the
+    /// matching pending records, leaving the batches untouched
+    fn query_with_pending(&self, query: &LogQuery) -> Result<Vec<LogInfo>, String> {
+        let start = query.start.unwrap_or(0);
+
+        // Paging and projection only make sense after merging, so the inner
+        // transport is asked for whole records up to the end of the page
+        let mut inner_query = query.clone();
+        inner_query.start = Some(0);
+        inner_query.limit = query.limit.map(|limit| start.saturating_add(limit));
+        inner_query.fields.clear();
+        let mut results = self.transport.query(&inner_query)?;
+
+        let mut batches: Vec<&PendingBatch> = self.batches.values().collect();
+        batches.sort_by_key(|batch| batch.started);
+        results.extend(
+            batches
+                .into_iter()
+                .flat_map(|batch| batch.records.iter())
+                .filter(|info| query.matches(info))
+                .cloned(),
+        );
+
+        query.sort(&mut results);
+        let mut results: Vec<LogInfo> = results
+            .into_iter()
+            .skip(start)
+            .take(query.limit.unwrap_or(usize::MAX))
+            .collect();
+        query.retain_fields(&mut results);
+        Ok(results)
+    }
+
+    /// Processes a single message, returning false once the worker should stop
+    fn handle_message(&mut self, message: BatchMessage) -> bool {
+        match message {
+            BatchMessage::Log(info, receipt) => {
+                self.stats.record_dequeued(1);
+                self.add_record(info, None, receipt);
+            }
+            BatchMessage::Spooled(entry, record, receipt) => {
+                self.stats.record_dequeued(1);
+                let Some(spool) = &self.spool else {
+                    return true;
+                };
+                let info = match record {
+                    Some(info) => {
+                        spool.release_memory();
+                        info
+                    }
+                    None => match spool.read(&entry) {
+                        Ok(info) => info,
+                        Err(e) => {
+                            report!("Skipping spooled record {}: {}", entry.seq, e);
+                            if let Some(receipt) = receipt {
+                                receipt.complete(Err(e));
+                            }
+                            return true;
+                        }
+                    },
+                };
+                self.add_record(info, Some(entry.seq), receipt);
+            }
+            BatchMessage::Flush(receipt) => {
+                receipt.complete(self.flush_batch());
+            }
+            BatchMessage::Tick(receipt) => {
+                self.on_timeout();
+                receipt.complete(Ok(()));
+            }
+            BatchMessage::Query(query, response_sender) => {
+                let result = match self.config.query_mode {
+                    QueryMode::Flush => {
+                        // For queries, we need to flush pending logs first
+                        let _ = self.flush_batch();
+                        self.transport.query(&query)
+                    }
+                    QueryMode::MergePending => self.query_with_pending(&query),
+                };
+                let _ = response_sender.send(result);
+            }
+            BatchMessage::Shutdown => {
+                // Flush any remaining logs before shutting down
+                let _ = self.flush_batch();
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Looks up a level's severity, ignoring case
+fn severity(levels: &HashMap<String, usize>, level: &str) -> Option<usize> {
+    levels.get(level).copied().or_else(|| {
+        levels
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(level))
+            .map(|(_, severity)| *severity)
+    })
+}
+
+/// Drives a BatchedTransport's queue and batch timer on a shared executor
+struct BatchTask<T: Transport> {
+    worker: BatchWorker<T>,
+    receiver: Receiver<BatchMessage>,
+}
+
+impl<T: Transport> ExecutorTask for BatchTask<T> {
+    fn run(&mut self) -> TaskStatus {
+        for _ in 0..TASK_BUDGET {
+            match self.receiver.try_recv() {
+                Ok(message) => {
+                    if !self.worker.handle_message(message) {
+                        return TaskStatus::Finished;
+                    }
+                }
+                Err(TryRecvError::Empty) => {
+                    self.worker.on_timeout();
+                    return TaskStatus::Idle(self.worker.wake_at());
+                }
+                Err(TryRecvError::Disconnected) => {
+                    let _ = self.worker.flush_batch();
+                    return TaskStatus::Finished;
+                }
+            }
+        }
+        TaskStatus::Busy
+    }
+}
+
 /// Builder for creating BatchConfig
 pub struct BatchConfigBuilder {
     max_batch_size: usize,
     max_batch_time: Duration,
     flush_on_drop: bool,
+    max_batch_bytes: Option<usize>,
+    size_estimator: Arc<dyn SizeEstimator>,
+    adaptive: Option<AdaptiveConfig>,
+    spool: Option<SpoolConfig>,
+    observer: Option<Arc<dyn BatchObserver>>,
+    group_by: Option<Arc<dyn KeyExtractor>>,
+    max_open_keys: usize,
+    flush_on_level: Option<String>,
+    levels: HashMap<String, usize>,
+    query_mode: QueryMode,
+    clock: Arc<dyn Clock>,
 }
 
 impl BatchConfigBuilder {
@@ -300,6 +1115,17 @@ impl BatchConfigBuilder {
             max_batch_size: default.max_batch_size,
             max_batch_time: default.max_batch_time,
             flush_on_drop: default.flush_on_drop,
+            max_batch_bytes: default.max_batch_bytes,
+            size_estimator: default.size_estimator,
+            adaptive: default.adaptive,
+            spool: default.spool,
+            observer: default.observer,
+            group_by: default.group_by,
+            max_open_keys: default.max_open_keys,
+            flush_on_level: default.flush_on_level,
+            levels: default.levels,
+            query_mode: default.query_mode,
+            clock: default.clock,
         }
     }
 
@@ -318,11 +1144,86 @@ impl BatchConfigBuilder {
         self
     }
 
+    pub fn max_batch_bytes(mut self, bytes: usize) -> Self {
+        self.max_batch_bytes = Some(bytes);
+        self
+    }
+
+    pub fn size_estimator<E>(mut self, estimator: E) -> Self
+    where
+        E: SizeEstimator + 'static,
+    {
+        self.size_estimator = Arc::new(estimator);
+        self
+    }
+
+    pub fn adaptive(mut self, adaptive: AdaptiveConfig) -> Self {
+        self.adaptive = Some(adaptive);
+        self
+    }
+
+    pub fn spool(mut self, spool: SpoolConfig) -> Self {
+        self.spool = Some(spool);
+        self
+    }
+
+    pub fn observer<O>(mut self, observer: O) -> Self
+    where
+        O: BatchObserver + 'static,
+    {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    pub fn group_by<K>(mut self, extractor: K) -> Self
+    where
+        K: KeyExtractor + 'static,
+    {
+        self.group_by = Some(Arc::new(extractor));
+        self
+    }
+
+    pub fn max_open_keys(mut self, max_open_keys: usize) -> Self {
+        self.max_open_keys = max_open_keys;
+        self
+    }
+
+    pub fn flush_on_level<S: Into<String>>(mut self, level: S) -> Self {
+        self.flush_on_level = Some(level.into());
+        self
+    }
+
+    pub fn levels(mut self, levels: HashMap<String, usize>) -> Self {
+        self.levels = levels;
+        self
+    }
+
+    pub fn query_mode(mut self, mode: QueryMode) -> Self {
+        self.query_mode = mode;
+        self
+    }
+
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
     pub fn build(self) -> BatchConfig {
         BatchConfig {
             max_batch_size: self.max_batch_size,
             max_batch_time: self.max_batch_time,
             flush_on_drop: self.flush_on_drop,
+            max_batch_bytes: self.max_batch_bytes,
+            size_estimator: self.size_estimator,
+            adaptive: self.adaptive,
+            spool: self.spool,
+            observer: self.observer,
+            group_by: self.group_by,
+            max_open_keys: self.max_open_keys,
+            flush_on_level: self.flush_on_level,
+            levels: self.levels,
+            query_mode: self.query_mode,
+            clock: self.clock,
         }
     }
 }
@@ -330,6 +1231,7 @@ impl BatchConfigBuilder {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{clock::TestClock, query_dsl::dlc::alpha::a::field_path::FieldPath};
     use std::{
         sync::{Arc, Mutex},
         time::Duration,
@@ -339,6 +1241,9 @@ mod tests {
     struct MockTransport {
         messages: Arc<Mutex<Vec<String>>>,
         log_calls: Arc<Mutex<Vec<Instant>>>,
+        batch_sizes: Arc<Mutex<Vec<usize>>>,
+        fail_flush: Arc<Mutex<bool>>,
+        records: Arc<Mutex<Vec<LogInfo>>>,
     }
 
     impl MockTransport {
@@ -346,9 +1251,16 @@ mod tests {
             Self {
                 messages: Arc::new(Mutex::new(Vec::new())),
                 log_calls: Arc::new(Mutex::new(Vec::new())),
+                batch_sizes: Arc::new(Mutex::new(Vec::new())),
+                fail_flush: Arc::new(Mutex::new(false)),
+                records: Arc::new(Mutex::new(Vec::new())),
             }
         }
 
+        fn get_batch_sizes(&self) -> Vec<usize> {
+            self.batch_sizes.lock().unwrap().clone()
+        }
+
         fn get_messages(&self) -> Vec<String> {
             self.messages.lock().unwrap().clone()
         }
@@ -360,13 +1272,44 @@ mod tests {
 
     impl Transport for MockTransport {
         fn log(&self, info: LogInfo) {
-            self.messages.lock().unwrap().push(info.message);
+            self.messages.lock().unwrap().push(info.message.clone());
             self.log_calls.lock().unwrap().push(Instant::now());
+            self.records.lock().unwrap().push(info);
+        }
+
+        fn log_batch(&self, logs: Vec<LogInfo>) {
+            self.batch_sizes.lock().unwrap().push(logs.len());
+            for log_info in logs {
+                self.log(log_info);
+            }
         }
 
         fn flush(&self) -> Result<(), String> {
+            if *self.fail_flush.lock().unwrap() {
+                return Err("sink unavailable".to_string());
+            }
             Ok(())
         }
+
+        fn query(&self, query: &LogQuery) -> Result<Vec<LogInfo>, String> {
+            let mut results: Vec<LogInfo> = self
+                .records
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|info| query.matches(info))
+                .cloned()
+                .collect();
+            query.sort(&mut results);
+            let start = query.start.unwrap_or(0);
+            let mut results: Vec<LogInfo> = results
+                .into_iter()
+                .skip(start)
+                .take(query.limit.unwrap_or(usize::MAX))
+                .collect();
+            query.retain_fields(&mut results);
+            Ok(results)
+        }
     }
 
     #[test]
@@ -400,10 +1343,12 @@ mod tests {
     fn test_time_trigger() {
         let mock = MockTransport::new();
         let mock_clone = mock.clone();
+        let clock = Arc::new(TestClock::new());
 
         let config = BatchConfigBuilder::new()
             .max_batch_size(100) // Large size so time triggers first
             .max_batch_time(Duration::from_millis(50))
+            .clock(clock.clone())
             .build();
 
         let batched = mock.into_batched_with_config(config);
@@ -412,9 +1357,12 @@ mod tests {
         batched.log(LogInfo::new("INFO", "Message 1"));
         batched.log(LogInfo::new("INFO", "Message 2"));
 
-        // Wait for time trigger
-        std::thread::sleep(Duration::from_millis(100));
+        // Just short of max_batch_time nothing is delivered
+        clock.advance(Duration::from_millis(49));
+        assert!(mock_clone.get_messages().is_empty());
 
+        // Reaching it exactly fires the time trigger
+        clock.advance(Duration::from_millis(1));
         let messages = mock_clone.get_messages();
         assert_eq!(messages.len(), 2);
         assert_eq!(messages[0], "Message 1");
@@ -443,4 +1391,540 @@ mod tests {
         assert_eq!(messages.len(), 1);
         assert_eq!(messages[0], "Message 1");
     }
+
+    #[test]
+    fn test_stats() {
+        let mock = MockTransport::new();
+
+        let config = BatchConfigBuilder::new()
+            .max_batch_size(3)
+            .max_batch_time(Duration::from_secs(10))
+            .build();
+
+        let batched = mock.into_batched_with_config(config);
+
+        for i in 0..4 {
+            batched.log(LogInfo::new("INFO", format!("Message {}", i)));
+        }
+        batched.flush().unwrap();
+
+        let stats = batched.stats();
+        assert_eq!(stats.queued, 0);
+        assert_eq!(stats.in_flight_batch, 0);
+        assert_eq!(stats.logged_total, 4);
+        assert_eq!(stats.flushed_total, 4);
+        assert_eq!(stats.failed_total, 0);
+        assert_eq!(stats.batch_size.count, 2);
+        assert_eq!(stats.batch_size.sum, 4.0);
+        assert!(stats.last_flush.is_some());
+        assert!(stats.worker_alive);
+    }
+
+    #[test]
+    fn test_batch_bytes_limit() {
+        let mock = MockTransport::new();
+        let mock_clone = mock.clone();
+
+        let config = BatchConfigBuilder::new()
+            .max_batch_size(100)
+            .max_batch_time(Duration::from_secs(10))
+            .max_batch_bytes(10)
+            .size_estimator(|info: &LogInfo| info.message.len())
+            .build();
+
+        let batched = mock.into_batched_with_config(config);
+
+        // 4 + 4 fits, the third record would exceed 10 bytes
+        batched.log(LogInfo::new("INFO", "aaaa"));
+        batched.log(LogInfo::new("INFO", "bbbb"));
+        batched.log(LogInfo::new("INFO", "cccc"));
+        // Exactly reaches the limit together with the pending "cccc"
+        batched.log(LogInfo::new("INFO", "dddddd"));
+        // Oversized on its own
+        batched.log(LogInfo::new("INFO", "eeeeeeeeeeee"));
+        batched.log(LogInfo::new("INFO", "ff"));
+        batched.flush().unwrap();
+
+        assert_eq!(mock_clone.get_batch_sizes(), vec![2, 2, 1, 1]);
+        assert_eq!(
+            mock_clone.get_messages(),
+            vec!["aaaa", "bbbb", "cccc", "dddddd", "eeeeeeeeeeee", "ff"]
+        );
+        assert_eq!(batched.stats().oversized_total, 1);
+    }
+
+    #[test]
+    fn test_json_size_estimator() {
+        let info = LogInfo::new("info", "hello");
+        let expected = serde_json::to_vec(&info).unwrap().len();
+        assert_eq!(JsonSizeEstimator.estimate(&info), expected);
+    }
+
+    #[test]
+    fn test_adaptive_limits_reported_in_stats() {
+        let mock = MockTransport::new();
+
+        let config = BatchConfigBuilder::new()
+            .max_batch_size(4)
+            .max_batch_time(Duration::from_secs(10))
+            .adaptive(AdaptiveConfig {
+                min_batch_size: 2,
+                max_batch_size: 8,
+                min_batch_time: Duration::from_millis(100),
+                max_batch_time: Duration::from_secs(20),
+                target_latency: Duration::from_secs(60),
+                max_error_rate: 0.5,
+            })
+            .build();
+
+        let batched = mock.into_batched_with_config(config);
+        assert_eq!(batched.stats().batch_size_limit, Some(4));
+
+        for i in 0..4 {
+            batched.log(LogInfo::new("INFO", format!("Message {}", i)));
+        }
+        batched.flush().unwrap();
+
+        // The first successful batch lets the tuner grow within its bounds
+        let stats = batched.stats();
+        assert_eq!(stats.batch_size_limit, Some(5));
+        assert!(stats.batch_time_limit.unwrap() > Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_spool_replay_and_cleanup() {
+        let dir = std::env::temp_dir().join(format!(
+            "winston_transport_batch_spool_{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut spool_config = SpoolConfig::new(&dir);
+        // Every record past the first is spilled and read back from disk
+        spool_config.max_memory_records = 1;
+
+        // Leave records behind as if the process had crashed before delivering them
+        {
+            let (spool, _) = Spool::open(spool_config.clone()).unwrap();
+            spool
+                .append(&LogInfo::new("INFO", "Crashed 1"), |_| ())
+                .unwrap();
+            spool
+                .append(&LogInfo::new("INFO", "Crashed 2"), |_| ())
+                .unwrap();
+        }
+
+        let mock = MockTransport::new();
+        let mock_clone = mock.clone();
+        let config = BatchConfigBuilder::new()
+            .max_batch_size(2)
+            .max_batch_time(Duration::from_secs(10))
+            .spool(spool_config)
+            .build();
+        let batched = mock.into_batched_with_config(config);
+
+        for i in 0..3 {
+            batched.log(LogInfo::new("INFO", format!("Message {}", i)));
+        }
+        batched.flush().unwrap();
+
+        assert_eq!(
+            mock_clone.get_messages(),
+            vec![
+                "Crashed 1",
+                "Crashed 2",
+                "Message 0",
+                "Message 1",
+                "Message 2"
+            ]
+        );
+        assert_eq!(batched.stats().logged_total, 5);
+        // Everything was delivered, so no segment is left to replay
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        drop(batched);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_spool_keeps_only_failed_batches() {
+        let dir = std::env::temp_dir().join(format!(
+            "winston_transport_batch_spool_failed_{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut spool_config = SpoolConfig::new(&dir);
+        // One record per segment
+        spool_config.max_segment_bytes = 1;
+
+        let mock = MockTransport::new();
+        let mock_clone = mock.clone();
+        let fail_flush = mock.fail_flush.clone();
+        let config = BatchConfigBuilder::new()
+            .max_batch_size(10)
+            .max_batch_time(Duration::from_secs(10))
+            .spool(spool_config.clone())
+            .build();
+        let batched = mock.into_batched_with_config(config);
+
+        *fail_flush.lock().unwrap() = true;
+        batched.log(LogInfo::new("INFO", "Failed 1"));
+        batched.log(LogInfo::new("INFO", "Failed 2"));
+        assert!(batched.flush().is_err());
+
+        // Later batches are still cleaned up once delivered
+        *fail_flush.lock().unwrap() = false;
+        for i in 0..4 {
+            batched.log(LogInfo::new("INFO", format!("Message {}", i)));
+        }
+        batched.flush().unwrap();
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+        drop(batched);
+
+        // The failed batch is replayed on the next start
+        let mock = MockTransport::new();
+        let replayed = mock.clone();
+        let config = BatchConfigBuilder::new().spool(spool_config).build();
+        let batched = mock.into_batched_with_config(config);
+        batched.flush().unwrap();
+        assert_eq!(replayed.get_messages(), vec!["Failed 1", "Failed 2"]);
+        assert_eq!(mock_clone.get_messages().len(), 6);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        drop(batched);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_spool_that_cannot_be_opened() {
+        // A directory cannot be created inside a regular file
+        let file = std::env::temp_dir().join(format!(
+            "winston_transport_batch_spool_file_{}",
+            std::process::id()
+        ));
+        std::fs::write(&file, b"").unwrap();
+        let config = || {
+            BatchConfigBuilder::new()
+                .spool(SpoolConfig::new(file.join("spool")))
+                .build()
+        };
+
+        let error = BatchedTransport::try_with_config(MockTransport::new(), config())
+            .err()
+            .unwrap();
+        assert!(error.starts_with("Failed to open spool directory"));
+
+        // The infallible constructor runs without a spool
+        let mock = MockTransport::new();
+        let batched = BatchedTransport::with_config(mock.clone(), config());
+        batched.log(LogInfo::new("INFO", "Unspooled"));
+        batched.flush().unwrap();
+        assert_eq!(mock.get_messages(), vec!["Unspooled"]);
+
+        drop(batched);
+        std::fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn test_batch_outcomes() {
+        let mock = MockTransport::new();
+        let fail_flush = mock.fail_flush.clone();
+        let (outcome_sender, outcomes) = std::sync::mpsc::channel();
+
+        let config = BatchConfigBuilder::new()
+            .max_batch_size(2)
+            .max_batch_time(Duration::from_secs(10))
+            .observer(outcome_sender)
+            .build();
+        let batched = mock.into_batched_with_config(config);
+
+        batched.log(LogInfo::new("INFO", "Message 1"));
+        batched.log(LogInfo::new("INFO", "Message 2"));
+        batched.flush().unwrap();
+
+        *fail_flush.lock().unwrap() = true;
+        batched.log(LogInfo::new("INFO", "Message 3"));
+        assert_eq!(batched.flush(), Err("sink unavailable".to_string()));
+
+        match outcomes.recv().unwrap() {
+            BatchOutcome::Delivered { batch_id, size } => {
+                assert_eq!(batch_id, 0);
+                assert_eq!(size, 2);
+            }
+            outcome => panic!("Unexpected outcome: {:?}", outcome),
+        }
+        match outcomes.recv().unwrap() {
+            BatchOutcome::Failed {
+                batch_id,
+                error,
+                records,
+            } => {
+                assert_eq!(batch_id, 1);
+                assert_eq!(error, "sink unavailable");
+                assert_eq!(records.len(), 1);
+                assert_eq!(records[0].message, "Message 3");
+            }
+            outcome => panic!("Unexpected outcome: {:?}", outcome),
+        }
+        assert_eq!(batched.stats().failed_total, 1);
+    }
+
+    #[test]
+    fn test_group_by_key() {
+        let mock = MockTransport::new();
+        let mock_clone = mock.clone();
+
+        let config = BatchConfigBuilder::new()
+            .max_batch_size(2)
+            .max_batch_time(Duration::from_secs(10))
+            .group_by(FieldPath::from("meta.tenant"))
+            .build();
+        let batched = mock.into_batched_with_config(config);
+
+        batched.log(LogInfo::new("INFO", "a1").with_meta("tenant", "a"));
+        batched.log(LogInfo::new("INFO", "b1").with_meta("tenant", "b"));
+        batched.log(LogInfo::new("INFO", "a2").with_meta("tenant", "a"));
+        batched.log(LogInfo::new("INFO", "none"));
+        batched.flush().unwrap();
+
+        // Tenant "a" filled its batch first, the rest go out oldest first on flush
+        assert_eq!(mock_clone.get_messages(), vec!["a1", "a2", "b1", "none"]);
+        assert_eq!(mock_clone.get_batch_sizes(), vec![2, 1, 1]);
+    }
+
+    #[test]
+    fn test_max_open_keys() {
+        let mock = MockTransport::new();
+        let mock_clone = mock.clone();
+
+        let config = BatchConfigBuilder::new()
+            .max_batch_size(10)
+            .max_batch_time(Duration::from_secs(10))
+            .group_by(|info: &LogInfo| Some(info.message[..1].to_string()))
+            .max_open_keys(2)
+            .build();
+        let batched = mock.into_batched_with_config(config);
+
+        batched.log(LogInfo::new("INFO", "a1"));
+        batched.log(LogInfo::new("INFO", "b1"));
+        batched.log(LogInfo::new("INFO", "a2"));
+        // A third key delivers the oldest open batch to make room
+        batched.log(LogInfo::new("INFO", "c1"));
+        batched.flush().unwrap();
+
+        assert_eq!(mock_clone.get_messages(), vec!["a1", "a2", "b1", "c1"]);
+        assert_eq!(mock_clone.get_batch_sizes(), vec![2, 1, 1]);
+    }
+
+    #[test]
+    fn test_flush_on_level() {
+        let mock = MockTransport::new();
+        let mock_clone = mock.clone();
+        // Never reaches max_batch_time, so only the level can trigger a batch
+        let clock = Arc::new(TestClock::new());
+
+        let config = BatchConfigBuilder::new()
+            .max_batch_size(100)
+            .max_batch_time(Duration::from_secs(10))
+            .flush_on_level("error")
+            .clock(clock.clone())
+            .build();
+        let batched = mock.into_batched_with_config(config);
+
+        batched.log(LogInfo::new("info", "Message 1"));
+        batched.log(LogInfo::new("warning", "Message 2"));
+        batched.log(LogInfo::new("debug", "Message 3"));
+        // Advancing waits for the worker to catch up
+        clock.advance(Duration::from_millis(1));
+        assert!(mock_clone.get_messages().is_empty());
+
+        // Severity is looked up case-insensitively, and crit is above error
+        let receipt = batched.log_with_receipt(LogInfo::new("CRIT", "Message 4"));
+        // Only a level-triggered batch completes it; the bound turns a
+        // regression into a failure rather than a hang
+        let delivered = receipt.wait_timeout(Duration::from_secs(5));
+        assert_eq!(delivered, Some(Ok(())));
+        assert_eq!(
+            mock_clone.get_messages(),
+            vec!["Message 1", "Message 2", "Message 3", "Message 4"]
+        );
+        assert_eq!(mock_clone.get_batch_sizes(), vec![4]);
+
+        batched.log(LogInfo::new("info", "Message 5"));
+        let receipt = batched.log_with_receipt(LogInfo::new("error", "Message 6"));
+        let delivered = receipt.wait_timeout(Duration::from_secs(5));
+        assert_eq!(delivered, Some(Ok(())));
+        assert_eq!(mock_clone.get_batch_sizes(), vec![4, 2]);
+        assert_eq!(mock_clone.get_messages()[4..], ["Message 5", "Message 6"]);
+    }
+
+    #[test]
+    fn test_query_merges_pending_records() {
+        let mock = MockTransport::new();
+        let mock_clone = mock.clone();
+
+        let config = BatchConfigBuilder::new()
+            .max_batch_size(2)
+            .max_batch_time(Duration::from_secs(10))
+            .query_mode(QueryMode::MergePending)
+            .build();
+        let batched = mock.into_batched_with_config(config);
+
+        for (i, level) in ["info", "error", "info", "error", "error"]
+            .iter()
+            .enumerate()
+        {
+            batched.log(
+                LogInfo::new(*level, format!("Message {}", i))
+                    .with_meta(
+                        "timestamp",
+                        (chrono::Utc::now() - chrono::Duration::seconds(10 - i as i64))
+                            .to_rfc3339(),
+                    )
+                    .with_meta("request", i),
+            );
+        }
+
+        // Newest first, skipping one and taking two across delivered and pending
+        let query = LogQuery::new()
+            .levels(vec!["error"])
+            .order("desc")
+            .start(1)
+            .limit(2);
+        let results = batched.query(&query).unwrap();
+        let messages: Vec<&str> = results.iter().map(|info| info.message.as_str()).collect();
+        assert_eq!(messages, vec!["Message 3", "Message 1"]);
+
+        // Projection away from the timestamp still sorts delivered and pending
+        // records together, and shapes them alike
+        let query = LogQuery::new()
+            .levels(vec!["error"])
+            .order("desc")
+            .fields(vec!["request"]);
+        let results = batched.query(&query).unwrap();
+        let messages: Vec<&str> = results.iter().map(|info| info.message.as_str()).collect();
+        assert_eq!(messages, vec!["Message 4", "Message 3", "Message 1"]);
+        assert!(results
+            .iter()
+            .all(|info| info.meta.keys().collect::<Vec<_>>() == ["request"]));
+
+        // The pending record was not flushed to answer the query
+        assert_eq!(mock_clone.get_messages().len(), 4);
+    }
+
+    #[test]
+    fn test_log_with_receipt() {
+        let mock = MockTransport::new();
+        let fail_flush = mock.fail_flush.clone();
+
+        let config = BatchConfigBuilder::new()
+            .max_batch_size(100)
+            .max_batch_time(Duration::from_secs(10))
+            .build();
+        let batched = mock.into_batched_with_config(config);
+
+        let receipt = batched.log_with_receipt(LogInfo::new("INFO", "Audit 1"));
+        // Still pending in the batch
+        assert_eq!(receipt.wait_timeout(Duration::from_millis(50)), None);
+
+        let handle = batched.flush_async();
+        assert_eq!(handle.wait(), Ok(()));
+        assert_eq!(receipt.try_wait(), Some(Ok(())));
+
+        *fail_flush.lock().unwrap() = true;
+        let receipt = batched.log_with_receipt(LogInfo::new("INFO", "Audit 2"));
+        assert!(batched.flush_async().wait().is_err());
+        assert_eq!(receipt.wait(), Err("sink unavailable".to_string()));
+    }
+
+    #[test]
+    fn test_adaptive_time_with_test_clock() {
+        let mock = MockTransport::new();
+        let mock_clone = mock.clone();
+        let clock = Arc::new(TestClock::new());
+
+        let config = BatchConfigBuilder::new()
+            .max_batch_size(100)
+            .max_batch_time(Duration::from_millis(100))
+            .adaptive(AdaptiveConfig {
+                min_batch_size: 10,
+                max_batch_size: 1000,
+                min_batch_time: Duration::from_millis(10),
+                max_batch_time: Duration::from_secs(1),
+                target_latency: Duration::from_millis(200),
+                max_error_rate: 0.5,
+            })
+            .clock(clock.clone())
+            .build();
+        let batched = mock.into_batched_with_config(config);
+
+        // Records waited 300ms end to end, over the 200ms target
+        batched.log(LogInfo::new("INFO", "Message 1"));
+        clock.advance(Duration::from_millis(300));
+        assert_eq!(mock_clone.get_messages().len(), 1);
+        assert_eq!(
+            batched.stats().batch_time_limit,
+            Some(Duration::from_millis(75))
+        );
+
+        // The shortened batch time is what the next timer uses
+        batched.log(LogInfo::new("INFO", "Message 2"));
+        clock.advance(Duration::from_millis(74));
+        assert_eq!(mock_clone.get_messages().len(), 1);
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(mock_clone.get_messages().len(), 2);
+    }
+
+    #[test]
+    fn test_reuse_buffers() {
+        /// Reads batches by reference, counting how they were handed over
+        #[derive(Clone, Default)]
+        struct BorrowingTransport {
+            borrowed: Arc<Mutex<Vec<String>>>,
+            owned_batches: Arc<Mutex<usize>>,
+        }
+
+        impl Transport for BorrowingTransport {
+            fn log(&self, info: LogInfo) {
+                self.log_batch(vec![info]);
+            }
+
+            fn log_batch(&self, _logs: Vec<LogInfo>) {
+                *self.owned_batches.lock().unwrap() += 1;
+            }
+
+            fn log_batch_ref(&self, logs: &[LogInfo]) {
+                let mut borrowed = self.borrowed.lock().unwrap();
+                borrowed.extend(logs.iter().map(|info| info.message.clone()));
+            }
+
+            fn borrows_batches(&self) -> bool {
+                true
+            }
+        }
+
+        let sink = BorrowingTransport::default();
+        let config = BatchConfigBuilder::new()
+            .max_batch_size(2)
+            .max_batch_time(Duration::from_secs(10))
+            .build();
+        let batched = sink.clone().into_batched_with_config(config);
+
+        for i in 0..5 {
+            batched.log(LogInfo::new("INFO", format!("Message {}", i)));
+        }
+        batched.flush().unwrap();
+
+        // Every batch was borrowed, none cloned into `log_batch`
+        assert_eq!(*sink.owned_batches.lock().unwrap(), 0);
+        assert_eq!(
+            *sink.borrowed.lock().unwrap(),
+            vec![
+                "Message 0",
+                "Message 1",
+                "Message 2",
+                "Message 3",
+                "Message 4"
+            ]
+        );
+    }
 }
@@ -0,0 +1,233 @@
+//! Adaptive tuning of batch size and batch time for `BatchedTransport`.
+//!
+//! After every delivered batch the tuner looks at how long the inner transport
+//! took per record, whether the delivery failed, and how long the oldest record
+//! waited end to end. Batches grow while per-record latency keeps falling and
+//! shrink when it rises, when errors pile up, or when the end-to-end latency
+//! overshoots the target.
+
+use std::time::Duration;
+
+/// Bounds and target for adaptive batching
+#[derive(Debug, Clone)]
+pub struct AdaptiveConfig {
+    /// Smallest batch size the tuner may pick
+    pub min_batch_size: usize,
+    /// Largest batch size the tuner may pick
+    pub max_batch_size: usize,
+    /// Shortest batch time the tuner may pick
+    pub min_batch_time: Duration,
+    /// Longest batch time the tuner may pick
+    pub max_batch_time: Duration,
+    /// End-to-end latency, from a record joining a batch to its delivery,
+    /// that the tuner aims to stay under
+    pub target_latency: Duration,
+    /// Smoothed share of failed deliveries above which batches shrink
+    pub max_error_rate: f64,
+}
+
+impl Default for AdaptiveConfig {
+    fn default() -> Self {
+        Self {
+            min_batch_size: 10,
+            max_batch_size: 5000,
+            min_batch_time: Duration::from_millis(50),
+            max_batch_time: Duration::from_secs(5),
+            target_latency: Duration::from_secs(1),
+            max_error_rate: 0.1,
+        }
+    }
+}
+
+/// Weight of the newest observation in the moving averages
+const SMOOTHING: f64 = 0.2;
+
+/// Tracks delivery outcomes and derives the current batch size and time
+#[derive(Debug)]
+pub(crate) struct AdaptiveTuner {
+    config: AdaptiveConfig,
+    batch_size: usize,
+    batch_time: Duration,
+    // Smoothed delivery time per record, in seconds
+    per_record_latency: Option<f64>,
+    error_rate: f64,
+}
+
+impl AdaptiveTuner {
+    /// Starts from the given values, clamped to the configured bounds
+    pub(crate) fn new(config: AdaptiveConfig, batch_size: usize, batch_time: Duration) -> Self {
+        let mut tuner = Self {
+            config,
+            batch_size,
+            batch_time,
+            per_record_latency: None,
+            error_rate: 0.0,
+        };
+        tuner.clamp();
+        tuner
+    }
+
+    pub(crate) fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    pub(crate) fn batch_time(&self) -> Duration {
+        self.batch_time
+    }
+
+    /// Feeds the outcome of one delivered batch into the tuner
+    pub(crate) fn observe(
+        &mut self,
+        records: usize,
+        delivery: Duration,
+        end_to_end: Duration,
+        success: bool,
+    ) {
+        if records == 0 {
+            return;
+        }
+
+        let failure = if success { 0.0 } else { 1.0 };
+        self.error_rate = self.error_rate * (1.0 - SMOOTHING) + failure * SMOOTHING;
+
+        let per_record = delivery.as_secs_f64() / records as f64;
+        let previous = self.per_record_latency;
+        self.per_record_latency = Some(match previous {
+            Some(average) => average * (1.0 - SMOOTHING) + per_record * SMOOTHING,
+            None => per_record,
+        });
+
+        if !success || self.error_rate > self.config.max_error_rate {
+            // Back off hard while the sink is failing
+            self.batch_size /= 2;
+        } else if end_to_end > self.config.target_latency {
+            // Records wait too long, deliver smaller batches sooner
+            self.batch_size = self.batch_size * 3 / 4;
+            self.batch_time = self.batch_time * 3 / 4;
+        } else if previous.is_none_or(|average| per_record <= average) {
+            // Larger batches are amortizing better, keep growing
+            self.batch_size += (self.batch_size / 10).max(1);
+            if end_to_end * 2 < self.config.target_latency {
+                self.batch_time = self.batch_time * 5 / 4;
+            }
+        } else {
+            self.batch_size -= self.batch_size / 10;
+        }
+
+        self.clamp();
+    }
+
+    fn clamp(&mut self) {
+        let min_size = self.config.min_batch_size.max(1);
+        let max_size = self.config.max_batch_size.max(min_size);
+        self.batch_size = self.batch_size.clamp(min_size, max_size);
+
+        let max_time = self.config.max_batch_time.max(self.config.min_batch_time);
+        self.batch_time = self.batch_time.clamp(self.config.min_batch_time, max_time);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tuner() -> AdaptiveTuner {
+        AdaptiveTuner::new(
+            AdaptiveConfig {
+                min_batch_size: 10,
+                max_batch_size: 200,
+                min_batch_time: Duration::from_millis(10),
+                max_batch_time: Duration::from_millis(1000),
+                target_latency: Duration::from_millis(500),
+                max_error_rate: 0.3,
+            },
+            100,
+            Duration::from_millis(100),
+        )
+    }
+
+    #[test]
+    fn test_grows_while_per_record_latency_falls() {
+        let mut tuner = tuner();
+        tuner.observe(
+            100,
+            Duration::from_millis(100),
+            Duration::from_millis(50),
+            true,
+        );
+        tuner.observe(
+            110,
+            Duration::from_millis(88),
+            Duration::from_millis(50),
+            true,
+        );
+
+        assert!(tuner.batch_size() > 110);
+        assert!(tuner.batch_time() > Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_shrinks_when_per_record_latency_rises() {
+        let mut tuner = tuner();
+        tuner.observe(
+            100,
+            Duration::from_millis(100),
+            Duration::from_millis(50),
+            true,
+        );
+        let grown = tuner.batch_size();
+        tuner.observe(
+            grown,
+            Duration::from_millis(500),
+            Duration::from_millis(50),
+            true,
+        );
+
+        assert!(tuner.batch_size() < grown);
+    }
+
+    #[test]
+    fn test_shrinks_on_failure_within_bounds() {
+        let mut tuner = tuner();
+        for _ in 0..10 {
+            tuner.observe(
+                100,
+                Duration::from_millis(10),
+                Duration::from_millis(50),
+                false,
+            );
+        }
+
+        assert_eq!(tuner.batch_size(), 10);
+    }
+
+    #[test]
+    fn test_shrinks_time_when_over_target_latency() {
+        let mut tuner = tuner();
+        tuner.observe(
+            100,
+            Duration::from_millis(10),
+            Duration::from_millis(900),
+            true,
+        );
+
+        assert_eq!(tuner.batch_size(), 75);
+        assert_eq!(tuner.batch_time(), Duration::from_millis(75));
+    }
+
+    #[test]
+    fn test_respects_upper_bounds() {
+        let mut tuner = tuner();
+        for _ in 0..100 {
+            tuner.observe(
+                100,
+                Duration::from_millis(1),
+                Duration::from_millis(1),
+                true,
+            );
+        }
+
+        assert_eq!(tuner.batch_size(), 200);
+        assert_eq!(tuner.batch_time(), Duration::from_millis(1000));
+    }
+}
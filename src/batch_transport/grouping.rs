@@ -0,0 +1,52 @@
+//! Key extraction for keyed batching, where every batch handed to the inner
+//! transport holds records that share a key such as a tenant or stream name.
+
+use crate::query_dsl::dlc::alpha::a::field_path::FieldPath;
+use logform::LogInfo;
+use serde_json::Value;
+
+/// Derives the key a record is grouped under
+///
+/// Records without a key are batched together in a group of their own.
+pub trait KeyExtractor: Send + Sync {
+    fn key(&self, info: &LogInfo) -> Option<String>;
+}
+
+impl<F> KeyExtractor for F
+where
+    F: Fn(&LogInfo) -> Option<String> + Send + Sync,
+{
+    fn key(&self, info: &LogInfo) -> Option<String> {
+        self(info)
+    }
+}
+
+/// Groups by the value at a path such as `meta.tenant`, with strings used as
+/// they are and other values in their JSON form
+impl KeyExtractor for FieldPath {
+    fn key(&self, info: &LogInfo) -> Option<String> {
+        match self.extract(&info.to_value())? {
+            Value::Null => None,
+            Value::String(key) => Some(key),
+            other => Some(other.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_path_key() {
+        let path = FieldPath::from("meta.tenant");
+
+        let info = LogInfo::new("info", "hello").with_meta("tenant", "acme");
+        assert_eq!(path.key(&info), Some("acme".to_string()));
+
+        let info = LogInfo::new("info", "hello").with_meta("tenant", 42);
+        assert_eq!(path.key(&info), Some("42".to_string()));
+
+        assert_eq!(path.key(&LogInfo::new("info", "hello")), None);
+    }
+}
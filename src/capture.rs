@@ -0,0 +1,385 @@
+//! Capture of the process's own stdout and stderr (Linux only).
+//!
+//! `OutputCapture` points file descriptors 1 and/or 2 at a pipe and reads it
+//! on a background thread, so output printed directly by third-party code
+//! becomes records on a `Transport`. Lines are framed and logged through a
+//! `TransportWriter`, with a `stream` meta field naming the source. Dropping
+//! the capture restores the original descriptors and logs any partial line.
+//!
+//! Descriptors are process-wide: each stream can be captured by only one
+//! `OutputCapture` at a time, and the reader thread only finishes once every
+//! copy of the pipe is closed, including copies inherited by child processes.
+//!
+//! The crate's own diagnostics and `ConsoleTransport`'s default streams are
+//! written to the original descriptors while captured, so a transport that
+//! prints does not feed its output back into the capture. Other code that
+//! writes to stdout or stderr from inside the transport still does.
+
+use crate::{transport_adapters::AsTransportWriter, LogInfo, Transport};
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    mem::ManuallyDrop,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+static STDOUT_CAPTURED: AtomicBool = AtomicBool::new(false);
+static STDERR_CAPTURED: AtomicBool = AtomicBool::new(false);
+
+/// The saved descriptors of the captured streams, indexed by `fd - 1`. Each is
+/// cleared under the lock before the stream is restored and the copy closed.
+static ORIGINALS: Mutex<[Option<RawFd>; 2]> = Mutex::new([None, None]);
+
+/// Writes to the original of `fd` if it is being captured, or returns `None`
+pub(crate) fn write_original(fd: RawFd, buf: &[u8]) -> Option<io::Result<()>> {
+    let originals = ORIGINALS.lock().unwrap_or_else(|e| e.into_inner());
+    let original = originals[(fd - 1) as usize]?;
+    // SAFETY: the descriptor stays open while the lock is held, and is not
+    // closed when the borrowed file goes away
+    let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(original) });
+    Some(file.write_all(buf))
+}
+
+fn set_original(fd: RawFd, original: Option<RawFd>) {
+    ORIGINALS.lock().unwrap_or_else(|e| e.into_inner())[(fd - 1) as usize] = original;
+}
+
+/// Which streams to capture and how to log them
+#[derive(Debug, Clone)]
+pub struct CaptureConfig {
+    /// Capture file descriptor 1
+    pub stdout: bool,
+    /// Capture file descriptor 2
+    pub stderr: bool,
+    /// Also copy captured output to the original descriptors
+    pub tee: bool,
+    /// Level of records read from stdout
+    pub stdout_level: String,
+    /// Level of records read from stderr
+    pub stderr_level: String,
+    /// Lines longer than this many bytes are split into several records
+    pub max_line_length: usize,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            stdout: true,
+            stderr: true,
+            tee: false,
+            stdout_level: "info".to_string(),
+            stderr_level: "warn".to_string(),
+            max_line_length: 64 * 1024,
+        }
+    }
+}
+
+struct CapturedStream {
+    fd: RawFd,
+    flag: &'static AtomicBool,
+    original: OwnedFd,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl CapturedStream {
+    fn start<T: Transport + 'static>(
+        fd: RawFd,
+        name: &'static str,
+        level: String,
+        transport: Arc<T>,
+        config: &CaptureConfig,
+    ) -> Result<Self, String> {
+        let flag = if fd == libc::STDOUT_FILENO {
+            &STDOUT_CAPTURED
+        } else {
+            &STDERR_CAPTURED
+        };
+        if flag.swap(true, Ordering::AcqRel) {
+            return Err(format!("{} is already being captured", name));
+        }
+
+        let setup = || -> io::Result<(OwnedFd, File, Option<File>)> {
+            let original = dup_cloexec(fd)?;
+            let tee = if config.tee {
+                Some(File::from(original.try_clone()?))
+            } else {
+                None
+            };
+
+            let mut fds = [0; 2];
+            // SAFETY: `fds` has room for the two descriptors pipe2 writes
+            if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: pipe2 succeeded, so both descriptors are open and ours
+            let (read_end, write_end) =
+                unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+
+            flush_std(fd);
+            // SAFETY: both descriptors are open; dup2 only replaces `fd`
+            if unsafe { libc::dup2(write_end.as_raw_fd(), fd) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok((original, File::from(read_end), tee))
+        };
+        let (original, pipe, tee) = setup().map_err(|e| {
+            flag.store(false, Ordering::Release);
+            format!("Failed to capture {}: {}", name, e)
+        })?;
+
+        let max_line_length = config.max_line_length;
+        let reader = thread::Builder::new()
+            .name(format!("capture-{}", name))
+            .spawn(move || read_pipe(pipe, tee, &*transport, name, level, max_line_length))
+            .map_err(|e| {
+                restore(fd, &original);
+                flag.store(false, Ordering::Release);
+                format!("Failed to spawn capture thread: {}", e)
+            })?;
+        set_original(fd, Some(original.as_raw_fd()));
+
+        Ok(Self {
+            fd,
+            flag,
+            original,
+            reader: Some(reader),
+        })
+    }
+}
+
+impl Drop for CapturedStream {
+    fn drop(&mut self) {
+        set_original(self.fd, None);
+        // Restoring the descriptor closes the pipe, so the reader sees EOF
+        restore(self.fd, &self.original);
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+        self.flag.store(false, Ordering::Release);
+    }
+}
+
+/// Redirects the process's stdout and/or stderr into a Transport until dropped
+pub struct OutputCapture {
+    streams: Vec<CapturedStream>,
+}
+
+impl OutputCapture {
+    /// Starts capturing the streams selected in `config`
+    pub fn start<T: Transport + 'static>(
+        transport: Arc<T>,
+        config: CaptureConfig,
+    ) -> Result<Self, String> {
+        let mut streams = Vec::new();
+        if config.stdout {
+            streams.push(CapturedStream::start(
+                libc::STDOUT_FILENO,
+                "stdout",
+                config.stdout_level.clone(),
+                transport.clone(),
+                &config,
+            )?);
+        }
+        if config.stderr {
+            // On failure the stdout capture in `streams` is undone by its drop
+            streams.push(CapturedStream::start(
+                libc::STDERR_FILENO,
+                "stderr",
+                config.stderr_level.clone(),
+                transport,
+                &config,
+            )?);
+        }
+        Ok(Self { streams })
+    }
+
+    /// Restores the original descriptors, waiting until everything captured
+    /// so far has been logged
+    pub fn stop(self) {
+        drop(self);
+    }
+}
+
+impl Drop for OutputCapture {
+    fn drop(&mut self) {
+        // Undo in reverse so nested redirections unwind cleanly
+        while let Some(stream) = self.streams.pop() {
+            drop(stream);
+        }
+    }
+}
+
+fn read_pipe<T: Transport>(
+    mut pipe: File,
+    mut tee: Option<File>,
+    transport: &T,
+    name: &'static str,
+    level: String,
+    max_line_length: usize,
+) {
+    let mut writer = transport
+        .as_writer()
+        .with_line_framing(max_line_length)
+        .with_decoder(move |line: &str, level: &str| {
+            Ok(LogInfo::new(level, line).with_meta("stream", name))
+        })
+        .with_default_level(level);
+
+    let mut buf = [0; 8192];
+    loop {
+        let read = match pipe.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        };
+        if let Some(file) = &mut tee {
+            let _ = file.write_all(&buf[..read]);
+        }
+        let _ = writer.write_all(&buf[..read]);
+    }
+    let _ = writer.flush();
+}
+
+fn dup_cloexec(fd: RawFd) -> io::Result<OwnedFd> {
+    // SAFETY: F_DUPFD_CLOEXEC returns a new descriptor or -1
+    let copy = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+    if copy < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: `copy` was just created and nothing else owns it
+    Ok(unsafe { OwnedFd::from_raw_fd(copy) })
+}
+
+fn restore(fd: RawFd, original: &OwnedFd) {
+    flush_std(fd);
+    // SAFETY: both descriptors are open; dup2 only replaces `fd`
+    if unsafe { libc::dup2(original.as_raw_fd(), fd) } < 0 {
+        // `fd` still points at the pipe, so report on the original
+        let error = io::Error::last_os_error();
+        // SAFETY: `original` outlives the borrowed file, which does not close it
+        let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(original.as_raw_fd()) });
+        let _ = writeln!(file, "Failed to restore descriptor {}: {}", fd, error);
+    }
+}
+
+/// Flushes Rust's own buffer for `fd` so output lands on the right side of a
+/// redirection
+fn flush_std(fd: RawFd) {
+    if fd == libc::STDOUT_FILENO {
+        let _ = io::stdout().flush();
+    } else {
+        let _ = io::stderr().flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process::Command};
+
+    struct MockTransport {
+        records: Mutex<Vec<LogInfo>>,
+        echo: bool,
+    }
+
+    impl Transport for MockTransport {
+        fn log(&self, info: LogInfo) {
+            if self.echo {
+                report!("echo {}", info.message);
+            }
+            self.records.lock().unwrap().push(info);
+        }
+    }
+
+    fn mock(echo: bool) -> Arc<MockTransport> {
+        Arc::new(MockTransport {
+            records: Mutex::new(Vec::new()),
+            echo,
+        })
+    }
+
+    fn write_fd(fd: RawFd, bytes: &[u8]) {
+        // SAFETY: writes from a valid buffer to a descriptor that stays open
+        unsafe { libc::write(fd, bytes.as_ptr().cast(), bytes.len()) };
+    }
+
+    /// Runs the named test on its own in a fresh process, as the descriptors
+    /// it swaps are shared with every test running in parallel. Returns `Ok`
+    /// in that process, and the child's stderr in the one that started it.
+    fn isolated(test: &str) -> Result<(), String> {
+        if env::var_os("CAPTURE_TEST_CHILD").is_some() {
+            return Ok(());
+        }
+        let output = Command::new(env::current_exe().unwrap())
+            .args([test, "--exact", "--test-threads=1", "--nocapture"])
+            .env("CAPTURE_TEST_CHILD", "1")
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        assert!(output.status.success(), "{} failed: {}", test, stderr);
+        Err(stderr)
+    }
+
+    fn captured(transport: &MockTransport) -> Vec<LogInfo> {
+        let records = transport.records.lock().unwrap();
+        records
+            .iter()
+            .filter(|info| info.message.contains("captured"))
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn test_capture_stderr() {
+        if isolated("capture::tests::test_capture_stderr").is_err() {
+            return;
+        }
+        let transport = mock(false);
+        let config = CaptureConfig {
+            stdout: false,
+            ..Default::default()
+        };
+
+        let capture = OutputCapture::start(transport.clone(), config.clone()).unwrap();
+        assert!(OutputCapture::start(transport.clone(), config).is_err());
+        write_fd(libc::STDERR_FILENO, b"captured line\ncaptured ");
+        write_fd(libc::STDERR_FILENO, b"partial");
+        // Stopping restores the descriptor and logs the partial line
+        capture.stop();
+
+        let captured = captured(&transport);
+        assert_eq!(captured.len(), 2);
+        assert_eq!(captured[0].message, "captured line");
+        assert_eq!(captured[0].level, "warn");
+        assert_eq!(captured[0].meta.get("stream"), Some(&"stderr".into()));
+        assert_eq!(captured[1].message, "captured partial");
+    }
+
+    #[test]
+    fn test_own_output_bypasses_capture() {
+        if let Err(stderr) = isolated("capture::tests::test_own_output_bypasses_capture") {
+            // Reported on the original stderr rather than captured again
+            assert!(stderr.contains("echo captured line"), "{}", stderr);
+            return;
+        }
+        let transport = mock(true);
+        let config = CaptureConfig {
+            stdout: false,
+            ..Default::default()
+        };
+
+        let capture = OutputCapture::start(transport.clone(), config).unwrap();
+        write_fd(libc::STDERR_FILENO, b"captured line\n");
+        capture.stop();
+
+        let captured = captured(&transport);
+        assert_eq!(captured.len(), 1);
+        assert_eq!(captured[0].message, "captured line");
+    }
+}
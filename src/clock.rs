@@ -0,0 +1,149 @@
+//! Time source for the batching loop.
+//!
+//! `BatchedTransport` reads the time through a `Clock`, so tests can swap the
+//! system clock for a `TestClock` and drive time-based flushes by advancing it
+//! manually instead of sleeping.
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+/// A callback run by a virtual clock whenever it is moved
+pub type ClockListener = dyn Fn() + Send + Sync;
+
+/// A source of time for the batching loop
+pub trait Clock: Send + Sync {
+    /// The current time on this clock
+    fn now(&self) -> Instant;
+
+    /// How long to block in real time while waiting for `deadline` on this
+    /// clock, or None to block until a subscribed listener is run
+    fn wait_time(&self, deadline: Instant) -> Option<Duration> {
+        Some(deadline.saturating_duration_since(self.now()))
+    }
+
+    /// Registers a listener to run whenever the clock moves other than in real
+    /// time; the clock only holds it weakly
+    fn subscribe(&self, _listener: Weak<ClockListener>) {}
+}
+
+/// The real monotonic clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when advanced
+///
+/// `advance` returns once every transport using the clock has handled the
+/// flushes that became due, so tests can assert on them straight away. It must
+/// not be called from inside a wrapped transport.
+pub struct TestClock {
+    base: Instant,
+    offset: Mutex<Duration>,
+    listeners: Mutex<Vec<Weak<ClockListener>>>,
+}
+
+impl TestClock {
+    /// Creates a clock stopped at the current instant
+    pub fn new() -> Self {
+        Self {
+            base: Instant::now(),
+            offset: Mutex::new(Duration::ZERO),
+            listeners: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Moves the clock forward and lets every subscribed transport catch up
+    pub fn advance(&self, duration: Duration) {
+        // Records queued before the call are taken in at the old time
+        self.notify();
+        *self.offset.lock().unwrap() += duration;
+        self.notify();
+    }
+
+    fn notify(&self) {
+        let listeners: Vec<Arc<ClockListener>> = {
+            let mut listeners = self.listeners.lock().unwrap();
+            listeners.retain(|listener| listener.strong_count() > 0);
+            listeners.iter().filter_map(Weak::upgrade).collect()
+        };
+        for listener in listeners {
+            listener();
+        }
+    }
+
+    /// Time passed since the clock was created
+    pub fn elapsed(&self) -> Duration {
+        *self.offset.lock().unwrap()
+    }
+}
+
+impl Default for TestClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for TestClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TestClock")
+            .field("elapsed", &self.elapsed())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> Instant {
+        self.base + self.elapsed()
+    }
+
+    fn wait_time(&self, deadline: Instant) -> Option<Duration> {
+        // Nothing but `advance` can make a future deadline pass
+        (deadline <= self.now()).then_some(Duration::ZERO)
+    }
+
+    fn subscribe(&self, listener: Weak<ClockListener>) {
+        self.listeners.lock().unwrap().push(listener);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_clock_advances_and_notifies() {
+        let clock = TestClock::new();
+        let start = clock.now();
+        assert_eq!(clock.wait_time(start + Duration::from_millis(10)), None);
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let listener: Arc<ClockListener> = Arc::new(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        clock.subscribe(Arc::downgrade(&listener));
+
+        clock.advance(Duration::from_millis(10));
+        assert_eq!(clock.now() - start, Duration::from_millis(10));
+        assert_eq!(
+            clock.wait_time(start + Duration::from_millis(10)),
+            Some(Duration::ZERO)
+        );
+        // Once to catch up before moving and once after
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Dropped listeners are forgotten
+        drop(listener);
+        clock.advance(Duration::from_millis(10));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
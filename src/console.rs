@@ -0,0 +1,467 @@
+//! A transport that prints records to the terminal.
+//!
+//! Levels are coloured by name (`red`, `bright yellow`, `bold blue`, ...)
+//! when the stream is a terminal, following the `NO_COLOR` and `FORCE_COLOR`
+//! conventions unless a `ColorMode` is set explicitly. Levels at or above
+//! the stderr threshold go to stderr and everything else to stdout.
+
+use crate::{diagnostics::StdStream, Transport};
+use logform::{Format, LogInfo};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    io::{self, IsTerminal, Write},
+    sync::{Arc, Mutex},
+};
+
+/// How records are laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConsoleLayout {
+    /// One line per record, with meta as JSON: `info: started {"port":8080}`
+    #[default]
+    Compact,
+    /// A header line followed by one indented line per meta field
+    Pretty,
+}
+
+/// When to colour output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    /// Colour terminals, unless `NO_COLOR` is set; `FORCE_COLOR` colours any
+    /// stream, or none when set to `0` or `false`
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    fn resolve(self, no_color: Option<&str>, force_color: Option<&str>, terminal: bool) -> bool {
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => match force_color {
+                Some(force) => !matches!(force, "0" | "false"),
+                None if no_color.is_some_and(|value| !value.is_empty()) => false,
+                None => terminal,
+            },
+        }
+    }
+}
+
+struct Stream {
+    writer: Mutex<Box<dyn Write + Send>>,
+    terminal: bool,
+}
+
+impl Stream {
+    fn new<W: Write + Send + 'static>(writer: W, terminal: bool) -> Self {
+        Self {
+            writer: Mutex::new(Box::new(writer)),
+            terminal,
+        }
+    }
+
+    fn write(&self, buf: &[u8]) {
+        match self.writer.lock() {
+            Ok(mut writer) => {
+                if let Err(e) = writer.write_all(buf) {
+                    report!("ConsoleTransport: failed to write: {}", e);
+                }
+            }
+            Err(_) => report!("ConsoleTransport: failed to lock stream"),
+        }
+    }
+
+    fn flush(&self) -> Result<(), String> {
+        self.writer
+            .lock()
+            .map_err(|_| "Failed to lock stream".to_string())?
+            .flush()
+            .map_err(|e| format!("Failed to flush: {}", e))
+    }
+}
+
+/// Prints records to stdout and stderr
+pub struct ConsoleTransport {
+    stdout: Stream,
+    stderr: Stream,
+    levels: HashMap<String, usize>,
+    colors: HashMap<String, String>,
+    level_width: usize,
+    stderr_level: Option<String>,
+    layout: ConsoleLayout,
+    color_mode: ColorMode,
+    no_color: Option<String>,
+    force_color: Option<String>,
+    level: Option<String>,
+    format: Option<Arc<dyn Format<Input = LogInfo> + Send + Sync>>,
+}
+
+impl ConsoleTransport {
+    /// Prints to the process's stdout and stderr with the npm-style `error`,
+    /// `warn`, `info`, `debug` and `trace` levels and their usual colours
+    pub fn new() -> Self {
+        let levels = logform::config::rust::levels();
+        Self {
+            stdout: Stream::new(StdStream::Stdout, io::stdout().is_terminal()),
+            stderr: Stream::new(StdStream::Stderr, io::stderr().is_terminal()),
+            level_width: level_width(&levels),
+            levels,
+            colors: logform::config::rust::colors(),
+            stderr_level: None,
+            layout: ConsoleLayout::default(),
+            color_mode: ColorMode::default(),
+            no_color: env::var("NO_COLOR").ok(),
+            force_color: env::var("FORCE_COLOR").ok(),
+            level: None,
+            format: None,
+        }
+    }
+
+    /// Prints to the given writers instead, which count as non-terminals
+    pub fn with_streams<O, E>(mut self, stdout: O, stderr: E) -> Self
+    where
+        O: Write + Send + 'static,
+        E: Write + Send + 'static,
+    {
+        self.stdout = Stream::new(stdout, false);
+        self.stderr = Stream::new(stderr, false);
+        self
+    }
+
+    pub fn with_level(mut self, level: String) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    pub fn with_format<F>(mut self, format: F) -> Self
+    where
+        F: Format<Input = LogInfo> + Send + Sync + 'static,
+    {
+        self.format = Some(Arc::new(format));
+        self
+    }
+
+    /// Replaces the level priorities, where a lower number is more severe
+    pub fn with_levels(mut self, levels: HashMap<String, usize>) -> Self {
+        self.level_width = level_width(&levels);
+        self.levels = levels;
+        self
+    }
+
+    /// Replaces the level to colour map
+    pub fn with_colors(mut self, colors: HashMap<String, String>) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    /// Sets the colour of one level, e.g. `"bold red"`
+    pub fn with_color(mut self, level: impl Into<String>, color: impl Into<String>) -> Self {
+        self.colors.insert(level.into(), color.into());
+        self
+    }
+
+    /// Sends this level and all more severe ones to stderr
+    pub fn with_stderr_level(mut self, level: impl Into<String>) -> Self {
+        self.stderr_level = Some(level.into());
+        self
+    }
+
+    pub fn with_layout(mut self, layout: ConsoleLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    pub fn with_color_mode(mut self, color_mode: ColorMode) -> Self {
+        self.color_mode = color_mode;
+        self
+    }
+
+    fn to_stderr(&self, level: &str) -> bool {
+        let threshold = self
+            .stderr_level
+            .as_ref()
+            .and_then(|threshold| self.levels.get(threshold));
+        match (threshold, self.levels.get(level)) {
+            (Some(threshold), Some(severity)) => severity <= threshold,
+            _ => false,
+        }
+    }
+
+    fn colored(&self, stream: &Stream) -> bool {
+        self.color_mode.resolve(
+            self.no_color.as_deref(),
+            self.force_color.as_deref(),
+            stream.terminal,
+        )
+    }
+
+    fn render(&self, info: &LogInfo, color: bool, out: &mut Vec<u8>) {
+        let paint = |out: &mut Vec<u8>, text: &str, spec: Option<&str>| match spec
+            .filter(|_| color)
+            .and_then(ansi)
+        {
+            Some(code) => out.extend_from_slice(format!("{}{}\x1b[0m", code, text).as_bytes()),
+            None => out.extend_from_slice(text.as_bytes()),
+        };
+
+        let timestamp = match info.meta.get("timestamp") {
+            Some(Value::String(timestamp)) => Some(timestamp.as_str()),
+            _ => None,
+        };
+        let meta: BTreeMap<&String, &Value> = info
+            .meta
+            .iter()
+            .filter(|(key, _)| timestamp.is_none() || key.as_str() != "timestamp")
+            .collect();
+        let padding = " ".repeat(self.level_width.saturating_sub(info.level.chars().count()));
+
+        if let Some(timestamp) = timestamp {
+            paint(out, timestamp, Some("dim"));
+            out.push(b' ');
+        }
+        paint(
+            out,
+            &info.level,
+            self.colors.get(&info.level).map(String::as_str),
+        );
+        match self.layout {
+            ConsoleLayout::Compact => {
+                out.extend_from_slice(format!(":{} {}", padding, info.message).as_bytes());
+                if !meta.is_empty() {
+                    out.push(b' ');
+                    out.extend_from_slice(
+                        serde_json::to_string(&meta).unwrap_or_default().as_bytes(),
+                    );
+                }
+            }
+            ConsoleLayout::Pretty => {
+                out.extend_from_slice(format!("{} {}", padding, info.message).as_bytes());
+                for (key, value) in meta {
+                    out.extend_from_slice(b"\n    ");
+                    paint(out, &format!("{}:", key), Some("dim"));
+                    out.push(b' ');
+                    let value = match value {
+                        Value::String(text) => text.clone(),
+                        other => serde_json::to_string_pretty(other).unwrap_or_default(),
+                    };
+                    out.extend_from_slice(value.replace('\n', "\n    ").as_bytes());
+                }
+            }
+        }
+        out.push(b'\n');
+    }
+}
+
+impl Default for ConsoleTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transport for ConsoleTransport {
+    fn log(&self, info: LogInfo) {
+        self.log_batch_ref(std::slice::from_ref(&info));
+    }
+
+    fn log_batch(&self, infos: Vec<LogInfo>) {
+        self.log_batch_ref(&infos);
+    }
+
+    fn log_batch_ref(&self, infos: &[LogInfo]) {
+        let colors = (self.colored(&self.stdout), self.colored(&self.stderr));
+        let mut buf = Vec::new();
+        let mut current: Option<&Stream> = None;
+        // Consecutive records for the same stream share one write, and the
+        // order between the streams is kept
+        for info in infos {
+            let (stream, color) = if self.to_stderr(&info.level) {
+                (&self.stderr, colors.1)
+            } else {
+                (&self.stdout, colors.0)
+            };
+            if let Some(previous) = current.filter(|previous| !std::ptr::eq(*previous, stream)) {
+                previous.write(&buf);
+                buf.clear();
+            }
+            current = Some(stream);
+            self.render(info, color, &mut buf);
+        }
+        if let Some(stream) = current {
+            stream.write(&buf);
+        }
+    }
+
+    fn borrows_batches(&self) -> bool {
+        true
+    }
+
+    fn flush(&self) -> Result<(), String> {
+        self.stdout.flush()?;
+        self.stderr.flush()
+    }
+
+    fn get_level(&self) -> Option<&String> {
+        self.level.as_ref()
+    }
+
+    fn get_format(&self) -> Option<Arc<dyn Format<Input = LogInfo> + Send + Sync>> {
+        self.format.clone()
+    }
+}
+
+fn level_width(levels: &HashMap<String, usize>) -> usize {
+    levels
+        .keys()
+        .map(|level| level.chars().count())
+        .max()
+        .unwrap_or(0)
+}
+
+/// The escape sequence for a colour spec such as `"bold bright red"`, or
+/// `None` when no word in it is known
+fn ansi(spec: &str) -> Option<String> {
+    let mut codes = Vec::new();
+    let mut bright = false;
+    for word in spec.split_whitespace() {
+        let word = word.to_ascii_lowercase();
+        let code = match word.as_str() {
+            "bright" => {
+                bright = true;
+                continue;
+            }
+            "bold" => 1,
+            "dim" => 2,
+            "italic" => 3,
+            "underline" => 4,
+            "inverse" => 7,
+            "grey" | "gray" => 90,
+            color => match color_code(color) {
+                Some(code) if bright && code < 90 => code + 60,
+                Some(code) => code,
+                None => continue,
+            },
+        };
+        codes.push(code.to_string());
+    }
+    (!codes.is_empty()).then(|| format!("\x1b[{}m", codes.join(";")))
+}
+
+/// The foreground code for a colour name, in the bright range for names such
+/// as `brightred`
+fn color_code(name: &str) -> Option<u8> {
+    let (name, offset) = match name.strip_prefix("bright") {
+        Some(name) => (name, 60),
+        None => (name, 0),
+    };
+    let code = match name {
+        "black" => 30,
+        "red" => 31,
+        "green" => 32,
+        "yellow" => 33,
+        "blue" => 34,
+        "magenta" => 35,
+        "cyan" => 36,
+        "white" => 37,
+        _ => return None,
+    };
+    Some(code + offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    fn console() -> (ConsoleTransport, SharedBuffer, SharedBuffer) {
+        let (stdout, stderr) = (SharedBuffer::default(), SharedBuffer::default());
+        let transport = ConsoleTransport::new()
+            .with_streams(stdout.clone(), stderr.clone())
+            .with_color_mode(ColorMode::Never);
+        (transport, stdout, stderr)
+    }
+
+    #[test]
+    fn test_layouts_and_streams() {
+        let (transport, stdout, stderr) = console();
+        let transport = transport.with_stderr_level("warn");
+        transport.log_batch(vec![
+            LogInfo::new("info", "started")
+                .with_meta("port", 8080)
+                .with_meta("host", "localhost"),
+            LogInfo::new("warn", "slow").with_meta("timestamp", "2024-04-10T12:00:00Z"),
+            LogInfo::new("debug", "tick"),
+            LogInfo::new("error", "failed"),
+        ]);
+        assert_eq!(
+            stdout.text(),
+            "info:  started {\"host\":\"localhost\",\"port\":8080}\ndebug: tick\n"
+        );
+        assert_eq!(
+            stderr.text(),
+            "2024-04-10T12:00:00Z warn:  slow\nerror: failed\n"
+        );
+
+        let (transport, stdout, _) = console();
+        transport
+            .with_layout(ConsoleLayout::Pretty)
+            .log(LogInfo::new("info", "request").with_meta("user", json!({"id": 1})));
+        assert_eq!(
+            stdout.text(),
+            "info  request\n    user: {\n      \"id\": 1\n    }\n"
+        );
+    }
+
+    #[test]
+    fn test_colors() {
+        let (transport, stdout, _) = console();
+        transport
+            .with_color_mode(ColorMode::Always)
+            .with_color("warn", "bold bright yellow")
+            .with_color("info", "brightRed")
+            .with_color("debug", "bright brightblue")
+            .log_batch(vec![
+                LogInfo::new("error", "a"),
+                LogInfo::new("warn", "b"),
+                LogInfo::new("custom", "c"),
+                LogInfo::new("info", "d"),
+                LogInfo::new("debug", "e"),
+            ]);
+        assert_eq!(
+            stdout.text(),
+            "\x1b[31merror\x1b[0m: a\n\x1b[1;93mwarn\x1b[0m:  b\ncustom: c\n\
+             \x1b[91minfo\x1b[0m:  d\n\x1b[94mdebug\x1b[0m: e\n"
+        );
+
+        let auto = ColorMode::Auto;
+        assert!(auto.resolve(None, None, true));
+        assert!(!auto.resolve(None, None, false));
+        assert!(!auto.resolve(Some("1"), None, true));
+        assert!(auto.resolve(Some(""), None, true));
+        assert!(auto.resolve(Some("1"), Some("1"), false));
+        assert!(!auto.resolve(None, Some("0"), true));
+        assert!(ColorMode::Always.resolve(Some("1"), Some("0"), false));
+        assert!(!ColorMode::Never.resolve(None, Some("1"), true));
+    }
+}
@@ -0,0 +1,367 @@
+//! Decoders that turn lines of text into `LogInfo` records.
+//!
+//! A `TransportWriter` configured with `with_decoder` runs every record it
+//! receives through an `InputDecoder`. Text the decoder rejects is logged as a
+//! plain message at the writer's default level, so nothing written is lost.
+
+use logform::LogInfo;
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::{
+    collections::HashMap,
+    sync::{Arc, OnceLock},
+};
+
+/// Parses one line of text into a record
+pub trait InputDecoder: Send + Sync {
+    /// Decodes `input`, using `default_level` when it carries no level of its
+    /// own, or returns why the input is not in this decoder's format
+    fn decode(&self, input: &str, default_level: &str) -> Result<LogInfo, String>;
+}
+
+impl<F> InputDecoder for F
+where
+    F: Fn(&str, &str) -> Result<LogInfo, String> + Send + Sync,
+{
+    fn decode(&self, input: &str, default_level: &str) -> Result<LogInfo, String> {
+        self(input, default_level)
+    }
+}
+
+/// Accepts any input as the message of a record at the default level
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlainTextDecoder;
+
+impl InputDecoder for PlainTextDecoder {
+    fn decode(&self, input: &str, default_level: &str) -> Result<LogInfo, String> {
+        Ok(LogInfo::new(default_level, input))
+    }
+}
+
+/// Decodes JSON objects such as `{"level":"warn","message":"...","user":1}`
+///
+/// `level` and `message` (or `msg`) become the record's level and message.
+/// Every other field goes into meta, and the fields of a nested `meta` object
+/// are merged in, so records serialized from a `LogInfo` round-trip.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonLinesDecoder;
+
+impl InputDecoder for JsonLinesDecoder {
+    fn decode(&self, input: &str, default_level: &str) -> Result<LogInfo, String> {
+        let map = match serde_json::from_str::<Value>(input) {
+            Ok(Value::Object(map)) => map,
+            Ok(_) => return Err("Input is not a JSON object".to_string()),
+            Err(e) => return Err(format!("Invalid JSON: {}", e)),
+        };
+        Ok(record_from_fields(map, default_level))
+    }
+}
+
+/// Decodes logfmt lines such as `level=warn msg="disk low" free=10`
+///
+/// `level` (or `lvl`) and `msg` (or `message`) become the record's level and
+/// message, and the remaining pairs go into meta as strings. Every token must
+/// be a `key=value` pair.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogfmtDecoder;
+
+impl InputDecoder for LogfmtDecoder {
+    fn decode(&self, input: &str, default_level: &str) -> Result<LogInfo, String> {
+        let pairs = parse_logfmt(input)?;
+        let map = pairs
+            .into_iter()
+            .map(|(key, value)| {
+                let key = match key.as_str() {
+                    "lvl" => "level".to_string(),
+                    _ => key,
+                };
+                (key, Value::String(value))
+            })
+            .collect();
+        Ok(record_from_fields(map, default_level))
+    }
+}
+
+/// Detects a level prefix such as `[WARN] disk low` or `ERROR: disk full`
+///
+/// The prefix is matched case-insensitively against known level names and
+/// aliases (`WARNING` becomes `warn`), and the rest of the line is the
+/// message.
+#[derive(Debug, Clone)]
+pub struct PrefixLevelDecoder {
+    levels: HashMap<String, String>,
+}
+
+impl PrefixLevelDecoder {
+    /// Creates a decoder that knows the usual level names and aliases
+    pub fn new() -> Self {
+        let levels = [
+            ("error", "error"),
+            ("err", "error"),
+            ("warn", "warn"),
+            ("warning", "warn"),
+            ("info", "info"),
+            ("debug", "debug"),
+            ("trace", "trace"),
+            ("emerg", "emerg"),
+            ("alert", "alert"),
+            ("crit", "crit"),
+            ("critical", "crit"),
+            ("notice", "notice"),
+        ]
+        .into_iter()
+        .map(|(name, level)| (name.to_string(), level.to_string()))
+        .collect();
+        Self { levels }
+    }
+
+    /// Also accepts `name` as a prefix, logged at `level`
+    pub fn with_alias(mut self, name: &str, level: &str) -> Self {
+        self.levels.insert(name.to_lowercase(), level.to_string());
+        self
+    }
+}
+
+impl Default for PrefixLevelDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputDecoder for PrefixLevelDecoder {
+    fn decode(&self, input: &str, _default_level: &str) -> Result<LogInfo, String> {
+        static PREFIX: OnceLock<Regex> = OnceLock::new();
+        let prefix = PREFIX.get_or_init(|| {
+            Regex::new(r"^\s*(?:\[\s*([A-Za-z]+)\s*\]:?|([A-Za-z]+):)\s*").unwrap()
+        });
+
+        let captures = prefix
+            .captures(input)
+            .ok_or_else(|| "No level prefix found".to_string())?;
+        let name = captures
+            .get(1)
+            .or_else(|| captures.get(2))
+            .unwrap()
+            .as_str();
+        let level = self
+            .levels
+            .get(&name.to_lowercase())
+            .ok_or_else(|| format!("Unknown level prefix '{}'", name))?;
+
+        let message = &input[captures.get(0).unwrap().end()..];
+        Ok(LogInfo::new(level.as_str(), message))
+    }
+}
+
+/// Tries each decoder in turn and returns the first success
+#[derive(Clone, Default)]
+pub struct DecoderChain {
+    decoders: Vec<Arc<dyn InputDecoder>>,
+}
+
+impl DecoderChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a decoder to try after the ones already in the chain
+    pub fn with<D: InputDecoder + 'static>(mut self, decoder: D) -> Self {
+        self.decoders.push(Arc::new(decoder));
+        self
+    }
+}
+
+impl InputDecoder for DecoderChain {
+    fn decode(&self, input: &str, default_level: &str) -> Result<LogInfo, String> {
+        let mut errors = Vec::new();
+        for decoder in &self.decoders {
+            match decoder.decode(input, default_level) {
+                Ok(info) => return Ok(info),
+                Err(e) => errors.push(e),
+            }
+        }
+        Err(if errors.is_empty() {
+            "No decoders configured".to_string()
+        } else {
+            errors.join("; ")
+        })
+    }
+}
+
+/// Decodes `input`, falling back to a plain message at `default_level`
+pub(crate) fn decode_or_plain(
+    decoder: &dyn InputDecoder,
+    input: &str,
+    default_level: &str,
+) -> LogInfo {
+    decoder
+        .decode(input, default_level)
+        .unwrap_or_else(|_| LogInfo::new(default_level, input))
+}
+
+fn record_from_fields(mut map: Map<String, Value>, default_level: &str) -> LogInfo {
+    let level = match map.remove("level") {
+        Some(Value::String(level)) => level,
+        Some(Value::Null) | None => default_level.to_string(),
+        Some(level) => level.to_string(),
+    };
+    let message = match map.remove("message").or_else(|| map.remove("msg")) {
+        Some(Value::String(message)) => message,
+        Some(Value::Null) | None => String::new(),
+        Some(message) => message.to_string(),
+    };
+
+    let mut info = LogInfo::new(level, message);
+    match map.remove("meta") {
+        Some(Value::Object(meta)) => info.meta.extend(meta),
+        Some(meta) => {
+            map.insert("meta".to_string(), meta);
+        }
+        None => {}
+    }
+    info.meta.extend(map);
+    info
+}
+
+/// Splits a logfmt line into its key/value pairs, in order
+pub(crate) fn parse_logfmt(input: &str) -> Result<Vec<(String, String)>, String> {
+    let mut pairs = Vec::new();
+    let mut chars = input.trim().chars().peekable();
+
+    while chars.peek().is_some() {
+        let mut key = String::new();
+        while let Some(&c) = chars.peek() {
+            if c == '=' || c.is_whitespace() {
+                break;
+            }
+            key.push(c);
+            chars.next();
+        }
+        if chars.next() != Some('=') || key.is_empty() {
+            return Err(format!("Expected key=value, found '{}'", key));
+        }
+
+        let mut value = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => value.push('\n'),
+                        Some('t') => value.push('\t'),
+                        Some(c) => value.push(c),
+                        None => return Err("Unterminated escape".to_string()),
+                    },
+                    Some(c) => value.push(c),
+                    None => return Err(format!("Unterminated quoted value for '{}'", key)),
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                value.push(c);
+                chars.next();
+            }
+        }
+        pairs.push((key, value));
+
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+    }
+
+    if pairs.is_empty() {
+        return Err("Empty logfmt line".to_string());
+    }
+    Ok(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_json_lines() {
+        let info = JsonLinesDecoder
+            .decode(r#"{"level":"warn","msg":"disk low","free":10}"#, "info")
+            .unwrap();
+        assert_eq!(info.level, "warn");
+        assert_eq!(info.message, "disk low");
+        assert_eq!(info.meta.get("free"), Some(&json!(10)));
+
+        // Serialized LogInfo records round-trip
+        let original = LogInfo::new("error", "boom").with_meta("code", 7);
+        let info = JsonLinesDecoder
+            .decode(&original.to_value().to_string(), "info")
+            .unwrap();
+        assert_eq!(info.level, "error");
+        assert_eq!(info.meta.get("code"), Some(&json!(7)));
+        assert!(!info.meta.contains_key("meta"));
+
+        let info = JsonLinesDecoder
+            .decode(r#"{"message":"hi"}"#, "INFO")
+            .unwrap();
+        assert_eq!(info.level, "INFO");
+        assert!(JsonLinesDecoder.decode("not json", "info").is_err());
+        assert!(JsonLinesDecoder.decode("[1, 2]", "info").is_err());
+    }
+
+    #[test]
+    fn test_logfmt() {
+        let info = LogfmtDecoder
+            .decode(
+                r#"lvl=debug msg="cache \"miss\"" key=user:1 took=3ms"#,
+                "info",
+            )
+            .unwrap();
+        assert_eq!(info.level, "debug");
+        assert_eq!(info.message, "cache \"miss\"");
+        assert_eq!(info.meta.get("key"), Some(&json!("user:1")));
+        assert_eq!(info.meta.get("took"), Some(&json!("3ms")));
+
+        assert!(LogfmtDecoder.decode("plain words here", "info").is_err());
+        assert!(LogfmtDecoder
+            .decode(r#"msg="unterminated"#, "info")
+            .is_err());
+    }
+
+    #[test]
+    fn test_prefix_level() {
+        let decoder = PrefixLevelDecoder::new().with_alias("fatal", "error");
+        let cases = [
+            ("[WARN] disk low", "warn", "disk low"),
+            ("ERROR: disk full", "error", "disk full"),
+            ("  [ Warning ]: retrying", "warn", "retrying"),
+            ("FATAL: gone", "error", "gone"),
+        ];
+        for (input, level, message) in cases {
+            let info = decoder.decode(input, "info").unwrap();
+            assert_eq!(
+                (info.level.as_str(), info.message.as_str()),
+                (level, message)
+            );
+        }
+
+        assert!(decoder.decode("Note: not a level", "info").is_err());
+        assert!(decoder.decode("no prefix", "info").is_err());
+    }
+
+    #[test]
+    fn test_chain_and_fallback() {
+        let chain = DecoderChain::new()
+            .with(JsonLinesDecoder)
+            .with(PrefixLevelDecoder::new());
+        assert_eq!(chain.decode("[DEBUG] x", "info").unwrap().level, "debug");
+        assert!(chain.decode("plain", "info").is_err());
+
+        let info = decode_or_plain(&chain, "plain", "notice");
+        assert_eq!(
+            (info.level.as_str(), info.message.as_str()),
+            ("notice", "plain")
+        );
+    }
+}
@@ -0,0 +1,57 @@
+//! Where the crate's own output goes.
+//!
+//! Failures with no caller to return them to are reported on stderr, and
+//! `ConsoleTransport` prints to stdout and stderr by default. While
+//! `OutputCapture` has redirected one of those streams, writing to it would
+//! feed the output back into the capture, so it goes to the descriptor saved
+//! before the redirection instead.
+
+use std::{
+    fmt,
+    io::{self, Write},
+};
+
+/// Reports a failure on stderr, bypassing a capture of it
+macro_rules! report {
+    ($($arg:tt)*) => {
+        $crate::diagnostics::report(format_args!($($arg)*))
+    };
+}
+
+pub(crate) fn report(args: fmt::Arguments) {
+    // Formatted up front so the line is written in one piece
+    let line = format!("{}\n", args);
+    let _ = StdStream::Stderr.write_all(line.as_bytes());
+}
+
+/// The process's stdout or stderr, or its original descriptor while captured
+pub(crate) enum StdStream {
+    Stdout,
+    Stderr,
+}
+
+impl Write for StdStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        #[cfg(target_os = "linux")]
+        {
+            let fd = match self {
+                StdStream::Stdout => libc::STDOUT_FILENO,
+                StdStream::Stderr => libc::STDERR_FILENO,
+            };
+            if let Some(result) = crate::capture::write_original(fd, buf) {
+                return result.map(|()| buf.len());
+            }
+        }
+        match self {
+            StdStream::Stdout => io::stdout().write(buf),
+            StdStream::Stderr => io::stderr().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            StdStream::Stdout => io::stdout().flush(),
+            StdStream::Stderr => io::stderr().flush(),
+        }
+    }
+}
@@ -0,0 +1,365 @@
+//! Encoders that serialize `LogInfo` records for `WriterTransport`.
+//!
+//! An `Encoder` appends one record, without a line terminator, to a byte
+//! buffer. The writer transports add the newline and reuse the buffer across
+//! the records of a batch.
+
+use logform::LogInfo;
+use serde_json::Value;
+use std::{borrow::Cow, collections::BTreeMap, io::Write};
+
+/// Serializes a record as one line of output
+pub trait Encoder: Send + Sync {
+    /// Appends `info` to `buf`, without a trailing newline
+    fn encode(&self, info: &LogInfo, buf: &mut Vec<u8>) -> Result<(), String>;
+}
+
+impl<F> Encoder for F
+where
+    F: Fn(&LogInfo, &mut Vec<u8>) -> Result<(), String> + Send + Sync,
+{
+    fn encode(&self, info: &LogInfo, buf: &mut Vec<u8>) -> Result<(), String> {
+        self(info, buf)
+    }
+}
+
+/// Writes only the message, dropping level and meta
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MessageEncoder;
+
+impl Encoder for MessageEncoder {
+    fn encode(&self, info: &LogInfo, buf: &mut Vec<u8>) -> Result<(), String> {
+        buf.extend_from_slice(info.message.as_bytes());
+        Ok(())
+    }
+}
+
+/// Writes `{"level":..,"message":..}` followed by the meta fields in key order
+///
+/// The output reads back with `decoder::JsonLinesDecoder`. A meta field named
+/// `level` or `message` is left out rather than shadowing the record's own.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonLinesEncoder;
+
+impl Encoder for JsonLinesEncoder {
+    fn encode(&self, info: &LogInfo, buf: &mut Vec<u8>) -> Result<(), String> {
+        let json_err = |e: serde_json::Error| format!("Failed to encode record: {}", e);
+
+        buf.extend_from_slice(b"{\"level\":");
+        serde_json::to_writer(&mut *buf, &info.level).map_err(json_err)?;
+        buf.extend_from_slice(b",\"message\":");
+        serde_json::to_writer(&mut *buf, &info.message).map_err(json_err)?;
+        for (key, value) in sorted_meta(info) {
+            if key == "level" || key == "message" {
+                continue;
+            }
+            buf.push(b',');
+            serde_json::to_writer(&mut *buf, key).map_err(json_err)?;
+            buf.push(b':');
+            serde_json::to_writer(&mut *buf, value).map_err(json_err)?;
+        }
+        buf.push(b'}');
+        Ok(())
+    }
+}
+
+/// Writes logfmt such as `level=warn msg="disk low" free=10`
+///
+/// Values are quoted when they contain spaces, quotes or `=`, and meta values
+/// that are not strings are written as JSON.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogfmtEncoder;
+
+impl Encoder for LogfmtEncoder {
+    fn encode(&self, info: &LogInfo, buf: &mut Vec<u8>) -> Result<(), String> {
+        buf.extend_from_slice(b"level=");
+        write_logfmt_value(buf, &info.level);
+        buf.extend_from_slice(b" msg=");
+        write_logfmt_value(buf, &info.message);
+        for (key, value) in sorted_meta(info) {
+            buf.push(b' ');
+            buf.extend_from_slice(key.as_bytes());
+            buf.push(b'=');
+            write_logfmt_value(buf, &value_text(value));
+        }
+        Ok(())
+    }
+}
+
+/// Writes human-readable text such as `warn: disk low free=10 path=/var`
+///
+/// Unlike logfmt the level and message are not keyed, and only meta values are
+/// written as `key=value`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KeyValueEncoder;
+
+impl Encoder for KeyValueEncoder {
+    fn encode(&self, info: &LogInfo, buf: &mut Vec<u8>) -> Result<(), String> {
+        buf.extend_from_slice(info.level.as_bytes());
+        buf.extend_from_slice(b": ");
+        buf.extend_from_slice(info.message.as_bytes());
+        for (key, value) in sorted_meta(info) {
+            buf.push(b' ');
+            buf.extend_from_slice(key.as_bytes());
+            buf.push(b'=');
+            write_logfmt_value(buf, &value_text(value));
+        }
+        Ok(())
+    }
+}
+
+/// Writes the chosen fields as one CSV row, quoted as in RFC 4180
+///
+/// Columns name `level`, `message`, or a meta key; missing fields are empty.
+#[derive(Debug, Clone)]
+pub struct CsvEncoder {
+    columns: Vec<String>,
+}
+
+impl CsvEncoder {
+    /// Creates an encoder writing the given columns in order
+    pub fn new<I, S>(columns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            columns: columns.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// The header row naming the columns, without a trailing newline
+    pub fn header(&self) -> String {
+        let mut buf = Vec::new();
+        for (i, column) in self.columns.iter().enumerate() {
+            if i > 0 {
+                buf.push(b',');
+            }
+            write_csv_value(&mut buf, column);
+        }
+        String::from_utf8(buf).unwrap()
+    }
+}
+
+impl Default for CsvEncoder {
+    fn default() -> Self {
+        Self::new(["level", "message"])
+    }
+}
+
+impl Encoder for CsvEncoder {
+    fn encode(&self, info: &LogInfo, buf: &mut Vec<u8>) -> Result<(), String> {
+        for (i, column) in self.columns.iter().enumerate() {
+            if i > 0 {
+                buf.push(b',');
+            }
+            if let Some(value) = field(info, column) {
+                write_csv_value(buf, &value);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+enum Segment {
+    Text(String),
+    Field(String),
+}
+
+/// Fills a template such as `[{level}] {message} ({user})`
+///
+/// `{level}` and `{message}` are the record's own fields, `{meta}` is all of
+/// the meta as a JSON object, and any other `{name}` is the meta field of that
+/// name, empty when missing. `{{` and `}}` write literal braces.
+#[derive(Clone)]
+pub struct TemplateEncoder {
+    segments: Vec<Segment>,
+}
+
+impl TemplateEncoder {
+    /// Parses the template, failing on an unclosed or empty placeholder
+    pub fn new(template: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(format!("Unclosed placeholder '{{{}'", name)),
+                        }
+                    }
+                    let name = name.trim();
+                    if name.is_empty() {
+                        return Err("Empty placeholder in template".to_string());
+                    }
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Field(name.to_string()));
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Ok(Self { segments })
+    }
+}
+
+impl Encoder for TemplateEncoder {
+    fn encode(&self, info: &LogInfo, buf: &mut Vec<u8>) -> Result<(), String> {
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => buf.extend_from_slice(text.as_bytes()),
+                Segment::Field(name) if name == "meta" => {
+                    let meta: BTreeMap<&String, &Value> = info.meta.iter().collect();
+                    serde_json::to_writer(&mut *buf, &meta)
+                        .map_err(|e| format!("Failed to encode meta: {}", e))?;
+                }
+                Segment::Field(name) => {
+                    if let Some(value) = field(info, name) {
+                        buf.extend_from_slice(value.as_bytes());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn sorted_meta(info: &LogInfo) -> Vec<(&String, &Value)> {
+    let mut meta: Vec<(&String, &Value)> = info.meta.iter().collect();
+    meta.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    meta
+}
+
+fn value_text(value: &Value) -> Cow<'_, str> {
+    match value {
+        Value::String(text) => Cow::Borrowed(text),
+        value => Cow::Owned(value.to_string()),
+    }
+}
+
+fn field<'a>(info: &'a LogInfo, name: &str) -> Option<Cow<'a, str>> {
+    match name {
+        "level" => Some(Cow::Borrowed(&info.level)),
+        "message" => Some(Cow::Borrowed(&info.message)),
+        name => info.meta.get(name).map(value_text),
+    }
+}
+
+fn write_logfmt_value(buf: &mut Vec<u8>, value: &str) {
+    let needs_quotes = value.is_empty()
+        || value
+            .chars()
+            .any(|c| c.is_whitespace() || c == '"' || c == '=' || c == '\\');
+    if !needs_quotes {
+        buf.extend_from_slice(value.as_bytes());
+        return;
+    }
+
+    buf.push(b'"');
+    for c in value.chars() {
+        match c {
+            '"' => buf.extend_from_slice(b"\\\""),
+            '\\' => buf.extend_from_slice(b"\\\\"),
+            '\n' => buf.extend_from_slice(b"\\n"),
+            '\t' => buf.extend_from_slice(b"\\t"),
+            c => {
+                let _ = write!(buf, "{}", c);
+            }
+        }
+    }
+    buf.push(b'"');
+}
+
+fn write_csv_value(buf: &mut Vec<u8>, value: &str) {
+    if !value.contains([',', '"', '\n', '\r']) {
+        buf.extend_from_slice(value.as_bytes());
+        return;
+    }
+    buf.push(b'"');
+    buf.extend_from_slice(value.replace('"', "\"\"").as_bytes());
+    buf.push(b'"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::{InputDecoder, JsonLinesDecoder, LogfmtDecoder};
+    use serde_json::json;
+
+    fn encode(encoder: &dyn Encoder, info: &LogInfo) -> String {
+        let mut buf = Vec::new();
+        encoder.encode(info, &mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    fn record() -> LogInfo {
+        LogInfo::new("warn", "disk \"low\"")
+            .with_meta("free", 10)
+            .with_meta("path", "/var/log")
+    }
+
+    #[test]
+    fn test_json_and_logfmt_round_trip() {
+        let info = record();
+
+        let line = encode(&JsonLinesEncoder, &info);
+        assert_eq!(
+            line,
+            r#"{"level":"warn","message":"disk \"low\"","free":10,"path":"/var/log"}"#
+        );
+        let decoded = JsonLinesDecoder.decode(&line, "info").unwrap();
+        assert_eq!(decoded.message, info.message);
+        assert_eq!(decoded.meta, info.meta);
+
+        let line = encode(&LogfmtEncoder, &info);
+        assert_eq!(
+            line,
+            r#"level=warn msg="disk \"low\"" free=10 path=/var/log"#
+        );
+        let decoded = LogfmtDecoder.decode(&line, "info").unwrap();
+        assert_eq!(decoded.message, info.message);
+        assert_eq!(decoded.meta.get("free"), Some(&json!("10")));
+    }
+
+    #[test]
+    fn test_text_encoders() {
+        let info = record();
+
+        assert_eq!(encode(&MessageEncoder, &info), "disk \"low\"");
+        assert_eq!(
+            encode(&KeyValueEncoder, &info),
+            "warn: disk \"low\" free=10 path=/var/log"
+        );
+
+        let csv = CsvEncoder::new(["level", "message", "free", "missing"]);
+        assert_eq!(csv.header(), "level,message,free,missing");
+        assert_eq!(encode(&csv, &info), r#"warn,"disk ""low""",10,"#);
+
+        let template = TemplateEncoder::new("[{level}] {message} {{{free}}} {meta}").unwrap();
+        assert_eq!(
+            encode(&template, &info),
+            r#"[warn] disk "low" {10} {"free":10,"path":"/var/log"}"#
+        );
+        assert!(TemplateEncoder::new("{level").is_err());
+        assert!(TemplateEncoder::new("{}").is_err());
+    }
+}
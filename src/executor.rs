@@ -0,0 +1,538 @@
+//! A small fixed-size thread pool that multiplexes many wrapped transports.
+//!
+//! Every `into_threaded()` or `into_batched()` call spawns a dedicated OS thread.
+//! With a `SharedExecutor` the same wrappers can instead be attached to a shared
+//! pool via `into_threaded_on(&executor)` and `into_batched_on(&executor)`.
+//!
+//! Each attached transport becomes a task that owns its message queue. A task is
+//! only ever run by one worker at a time, so per-transport ordering is kept, and
+//! batch timers are tracked per task on the executor's timer heap.
+//!
+//! Shutting a transport down waits for its task to finish, except on one of
+//! the executor's own workers, where waiting could block the only thread able
+//! to run it. There the task is scheduled and finishes on the pool afterwards.
+
+use std::{
+    cell::Cell,
+    cmp::Ordering as CmpOrdering,
+    collections::{BinaryHeap, VecDeque},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, Weak,
+    },
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+/// Maximum number of messages a task processes before yielding its worker
+pub(crate) const TASK_BUDGET: usize = 64;
+
+thread_local! {
+    /// The executor this thread is a worker of, if any
+    static CURRENT_EXECUTOR: Cell<*const ExecutorInner> = const { Cell::new(ptr::null()) };
+}
+
+/// Result of running a task once on the executor
+pub(crate) enum TaskStatus {
+    /// No queued work left, optionally asking to be woken at the given instant
+    Idle(Option<Instant>),
+    /// The budget ran out with work still queued
+    Busy,
+    /// The task has shut down and must not be run again
+    Finished,
+}
+
+/// Work that can be driven by a `SharedExecutor`
+pub(crate) trait ExecutorTask: Send {
+    fn run(&mut self) -> TaskStatus;
+}
+
+struct TaskCell {
+    task: Mutex<Option<Box<dyn ExecutorTask>>>,
+    scheduled: AtomicBool,
+    timer: Mutex<Option<Instant>>,
+    finished: Mutex<bool>,
+    done: Condvar,
+}
+
+struct TimerEntry {
+    deadline: Instant,
+    task: Weak<TaskCell>,
+}
+
+impl PartialEq for TimerEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl Eq for TimerEntry {}
+
+impl PartialOrd for TimerEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TimerEntry {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        // Reversed so the earliest deadline sits at the top of the heap
+        other.deadline.cmp(&self.deadline)
+    }
+}
+
+#[derive(Default)]
+struct ExecutorState {
+    run_queue: VecDeque<Arc<TaskCell>>,
+    timers: BinaryHeap<TimerEntry>,
+    live_tasks: usize,
+    closing: bool,
+}
+
+struct ExecutorInner {
+    state: Mutex<ExecutorState>,
+    wakeup: Condvar,
+}
+
+impl ExecutorInner {
+    fn schedule(&self, cell: &Arc<TaskCell>) {
+        if cell.scheduled.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.run_queue.push_back(cell.clone());
+        drop(state);
+        self.wakeup.notify_one();
+    }
+
+    fn register_timer(&self, cell: &Arc<TaskCell>, deadline: Instant) {
+        {
+            let mut timer = cell.timer.lock().unwrap();
+            if *timer == Some(deadline) {
+                return;
+            }
+            *timer = Some(deadline);
+        }
+        let mut state = self.state.lock().unwrap();
+        state.timers.push(TimerEntry {
+            deadline,
+            task: Arc::downgrade(cell),
+        });
+        drop(state);
+        // A sleeping worker may be waiting on a later deadline
+        self.wakeup.notify_one();
+    }
+
+    /// Blocks until a task is ready to run, or returns None once the executor
+    /// is closing and no tasks remain attached
+    fn next_task(&self) -> Option<Arc<TaskCell>> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(cell) = state.run_queue.pop_front() {
+                return Some(cell);
+            }
+
+            let now = Instant::now();
+            while state
+                .timers
+                .peek()
+                .is_some_and(|entry| entry.deadline <= now)
+            {
+                let entry = state.timers.pop().unwrap();
+                if let Some(cell) = entry.task.upgrade() {
+                    {
+                        let mut timer = cell.timer.lock().unwrap();
+                        if *timer == Some(entry.deadline) {
+                            *timer = None;
+                        }
+                    }
+                    if !cell.scheduled.swap(true, Ordering::AcqRel) {
+                        state.run_queue.push_back(cell);
+                    }
+                }
+            }
+            if !state.run_queue.is_empty() {
+                continue;
+            }
+
+            if state.closing && state.live_tasks == 0 {
+                self.wakeup.notify_all();
+                return None;
+            }
+
+            state = match state.timers.peek().map(|entry| entry.deadline) {
+                Some(deadline) => {
+                    self.wakeup
+                        .wait_timeout(state, deadline.saturating_duration_since(now))
+                        .unwrap()
+                        .0
+                }
+                None => self.wakeup.wait(state).unwrap(),
+            };
+        }
+    }
+
+    fn run_task(&self, cell: &Arc<TaskCell>) {
+        let mut slot = cell.task.lock().unwrap();
+        let Some(task) = slot.as_mut() else {
+            return;
+        };
+
+        // A panicking transport stops its own task, not the worker, so the
+        // task is still reported finished and its handle can be joined
+        let status = panic::catch_unwind(AssertUnwindSafe(|| task.run())).unwrap_or_else(|_| {
+            report!("Executor task panicked and was stopped");
+            TaskStatus::Finished
+        });
+        match status {
+            TaskStatus::Idle(None) => {}
+            TaskStatus::Idle(Some(deadline)) => {
+                drop(slot);
+                self.register_timer(cell, deadline);
+            }
+            TaskStatus::Busy => {
+                drop(slot);
+                self.schedule(cell);
+            }
+            TaskStatus::Finished => {
+                *slot = None;
+                drop(slot);
+
+                *cell.finished.lock().unwrap() = true;
+                cell.done.notify_all();
+
+                let mut state = self.state.lock().unwrap();
+                state.live_tasks -= 1;
+                drop(state);
+                self.wakeup.notify_all();
+            }
+        }
+    }
+
+    fn worker_loop(&self) {
+        CURRENT_EXECUTOR.with(|current| current.set(self));
+        while let Some(cell) = self.next_task() {
+            cell.scheduled.store(false, Ordering::Release);
+            self.run_task(&cell);
+        }
+    }
+}
+
+/// A fixed pool of worker threads shared by many threaded or batched transports
+///
+/// Dropping the executor does not detach transports that are still attached;
+/// the workers keep serving them and exit once the last one has shut down.
+pub struct SharedExecutor {
+    inner: Arc<ExecutorInner>,
+    num_threads: usize,
+}
+
+impl SharedExecutor {
+    /// Creates a new executor with the given number of worker threads
+    pub fn new(num_threads: usize) -> Self {
+        Self::with_thread_name(num_threads, "transport-executor".to_string())
+    }
+
+    /// Creates a new executor whose workers are named `{thread_name}-{index}`
+    pub fn with_thread_name(num_threads: usize, thread_name: String) -> Self {
+        let num_threads = num_threads.max(1);
+        let inner = Arc::new(ExecutorInner {
+            state: Mutex::new(ExecutorState::default()),
+            wakeup: Condvar::new(),
+        });
+
+        for index in 0..num_threads {
+            let worker_inner = inner.clone();
+            thread::Builder::new()
+                .name(format!("{}-{}", thread_name, index))
+                .spawn(move || worker_inner.worker_loop())
+                .expect("Failed to spawn executor worker thread");
+        }
+
+        Self { inner, num_threads }
+    }
+
+    /// Gets the number of worker threads in the pool
+    pub fn num_threads(&self) -> usize {
+        self.num_threads
+    }
+
+    pub(crate) fn spawn<E: ExecutorTask + 'static>(&self, task: E) -> TaskHandle {
+        let cell = Arc::new(TaskCell {
+            task: Mutex::new(Some(Box::new(task))),
+            scheduled: AtomicBool::new(false),
+            timer: Mutex::new(None),
+            finished: Mutex::new(false),
+            done: Condvar::new(),
+        });
+
+        self.inner.state.lock().unwrap().live_tasks += 1;
+
+        TaskHandle {
+            inner: self.inner.clone(),
+            cell,
+        }
+    }
+}
+
+impl Default for SharedExecutor {
+    fn default() -> Self {
+        let threads = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .min(4);
+        Self::new(threads)
+    }
+}
+
+impl Drop for SharedExecutor {
+    fn drop(&mut self) {
+        let mut state = self.inner.state.lock().unwrap();
+        state.closing = true;
+        drop(state);
+        self.inner.wakeup.notify_all();
+    }
+}
+
+/// Handle to a task running on a `SharedExecutor`
+pub(crate) struct TaskHandle {
+    inner: Arc<ExecutorInner>,
+    cell: Arc<TaskCell>,
+}
+
+impl TaskHandle {
+    /// Schedules the task to drain its queue
+    pub(crate) fn wake(&self) {
+        self.inner.schedule(&self.cell);
+    }
+
+    /// Returns a waker that can schedule the task from elsewhere
+    fn waker(&self) -> TaskWaker {
+        TaskWaker {
+            inner: self.inner.clone(),
+            cell: self.cell.clone(),
+        }
+    }
+
+    /// Blocks until the task has finished. On one of the executor's own
+    /// workers it returns once the task is scheduled, as waiting could block
+    /// the worker the task needs to run.
+    fn join(&self) {
+        self.wake();
+        if CURRENT_EXECUTOR.with(|current| ptr::eq(current.get(), Arc::as_ptr(&self.inner))) {
+            return;
+        }
+        let mut finished = self.cell.finished.lock().unwrap();
+        while !*finished {
+            finished = self.cell.done.wait(finished).unwrap();
+        }
+    }
+}
+
+impl Drop for TaskHandle {
+    fn drop(&mut self) {
+        // Lets the task observe a disconnected channel if its sender is already gone
+        self.wake();
+    }
+}
+
+/// Schedules a task on its executor without owning it
+#[derive(Clone)]
+pub(crate) struct TaskWaker {
+    inner: Arc<ExecutorInner>,
+    cell: Arc<TaskCell>,
+}
+
+impl TaskWaker {
+    pub(crate) fn wake(&self) {
+        self.inner.schedule(&self.cell);
+    }
+}
+
+/// The worker behind a threaded or batched transport
+pub(crate) enum WorkerHandle {
+    /// A dedicated OS thread
+    Thread(JoinHandle<()>),
+    /// A task on a `SharedExecutor`
+    Shared(TaskHandle),
+}
+
+impl WorkerHandle {
+    /// Notifies the worker that new messages are queued
+    pub(crate) fn wake(&self) {
+        if let WorkerHandle::Shared(handle) = self {
+            handle.wake();
+        }
+    }
+
+    /// Returns a waker for a worker running on a `SharedExecutor`
+    pub(crate) fn waker(&self) -> Option<TaskWaker> {
+        match self {
+            WorkerHandle::Thread(_) => None,
+            WorkerHandle::Shared(handle) => Some(handle.waker()),
+        }
+    }
+
+    /// Waits for the worker to finish
+    pub(crate) fn join(self) -> Result<(), ()> {
+        match self {
+            WorkerHandle::Thread(handle) => handle.join().map_err(|_| ()),
+            WorkerHandle::Shared(handle) => {
+                handle.join();
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        batch_transport::{BatchConfigBuilder, IntoBatchedTransport},
+        threaded_transport::IntoThreadedTransport,
+        Transport,
+    };
+    use logform::LogInfo;
+    use std::time::Duration;
+
+    #[derive(Clone)]
+    struct MockTransport {
+        messages: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl MockTransport {
+        fn new() -> Self {
+            Self {
+                messages: Arc::new(Mutex::new(Vec::new())),
+            }
+        }
+
+        fn get_messages(&self) -> Vec<String> {
+            self.messages.lock().unwrap().clone()
+        }
+    }
+
+    impl Transport for MockTransport {
+        fn log(&self, info: LogInfo) {
+            self.messages.lock().unwrap().push(info.message);
+        }
+    }
+
+    #[test]
+    fn test_many_threaded_transports_keep_order() {
+        let executor = SharedExecutor::new(2);
+        let mocks: Vec<MockTransport> = (0..10).map(|_| MockTransport::new()).collect();
+        let transports: Vec<_> = mocks
+            .iter()
+            .map(|mock| mock.clone().into_threaded_on(&executor))
+            .collect();
+
+        for i in 0..100 {
+            for transport in &transports {
+                transport.log(LogInfo::new("INFO", format!("Message {}", i)));
+            }
+        }
+
+        for transport in &transports {
+            transport.flush().unwrap();
+        }
+
+        for mock in &mocks {
+            let messages = mock.get_messages();
+            assert_eq!(messages.len(), 100);
+            for (i, message) in messages.iter().enumerate() {
+                assert_eq!(message, &format!("Message {}", i));
+            }
+        }
+    }
+
+    #[test]
+    fn test_batched_transport_time_trigger_on_executor() {
+        let executor = SharedExecutor::new(1);
+        let mock = MockTransport::new();
+        let mock_clone = mock.clone();
+
+        let config = BatchConfigBuilder::new()
+            .max_batch_size(100)
+            .max_batch_time(Duration::from_millis(50))
+            .build();
+        let batched = mock.into_batched_with_config_on(config, &executor);
+
+        batched.log(LogInfo::new("INFO", "Message 1"));
+        batched.log(LogInfo::new("INFO", "Message 2"));
+
+        std::thread::sleep(Duration::from_millis(200));
+
+        assert_eq!(mock_clone.get_messages(), vec!["Message 1", "Message 2"]);
+    }
+
+    #[test]
+    fn test_shutdown_after_executor_dropped() {
+        let mock = MockTransport::new();
+        let mock_clone = mock.clone();
+
+        let batched = {
+            let executor = SharedExecutor::new(1);
+            mock.into_batched_on(&executor)
+        };
+
+        batched.log(LogInfo::new("INFO", "Late message"));
+        batched.shutdown().unwrap();
+
+        assert_eq!(mock_clone.get_messages(), vec!["Late message"]);
+    }
+
+    #[test]
+    fn test_shutdown_from_executor_worker() {
+        struct Dropper(Mutex<Option<Box<dyn Transport>>>);
+
+        impl Transport for Dropper {
+            fn log(&self, _info: LogInfo) {
+                drop(self.0.lock().unwrap().take());
+            }
+        }
+
+        // With one worker, waiting for the inner task there would never end
+        let executor = SharedExecutor::new(1);
+        let mock = MockTransport::new();
+        let inner = mock.clone().into_threaded_on(&executor);
+        inner.log(LogInfo::new("INFO", "Queued before shutdown"));
+        let outer = Dropper(Mutex::new(Some(Box::new(inner)))).into_threaded_on(&executor);
+
+        outer.log(LogInfo::new("INFO", "Drop the inner transport"));
+        outer.flush().unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while mock.get_messages().is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(mock.get_messages(), vec!["Queued before shutdown"]);
+    }
+
+    #[test]
+    fn test_panicking_transport_on_executor() {
+        struct Panicking;
+
+        impl Transport for Panicking {
+            fn log(&self, _info: LogInfo) {
+                panic!("transport failed");
+            }
+        }
+
+        let executor = SharedExecutor::new(1);
+        let panicking = Panicking.into_threaded_on(&executor);
+        panicking.log(LogInfo::new("INFO", "Panics"));
+        // Joins the stopped task instead of waiting forever
+        drop(panicking);
+
+        // The worker survives to run other tasks
+        let mock = MockTransport::new();
+        let threaded = mock.clone().into_threaded_on(&executor);
+        threaded.log(LogInfo::new("INFO", "After the panic"));
+        threaded.flush().unwrap();
+        assert_eq!(mock.get_messages(), vec!["After the panic"]);
+    }
+}
@@ -0,0 +1,756 @@
+//! A transport that writes JSON lines to a file, with rotation and retention.
+//!
+//! The active file rotates once it would grow beyond `max_size`, or on the
+//! first write in a new hour or day with `rotation`. The rotated file is
+//! renamed after `file_pattern` in the same directory, optionally gzipped, and
+//! the oldest rotated files are removed beyond `max_files` or `max_age`.
+//! Rotation and compression happen on the logging thread; wrap the transport
+//! in a `ThreadedTransport` or `BatchedTransport` to keep them off it.
+//!
+//! `query` reads the rotated files and the active file and honours every
+//! `LogQuery` field, including the DSL `filter`. A sparse timestamp index per
+//! file lets it read only the requested time window, backwards for the default
+//! descending order, and stop once `limit` is reached. Compressed files are
+//! searched too, skipping those whose first and last timestamps fall outside
+//! the window.
+
+mod codec;
+mod index;
+mod naming;
+mod scan;
+
+use crate::{
+    encoder::{Encoder, JsonLinesEncoder},
+    LogQuery, Transport,
+};
+use chrono::{DateTime, Utc};
+pub use codec::Codec;
+use index::{timestamp_of, SparseIndex};
+use logform::{Format, LogInfo};
+use naming::NamePattern;
+use scan::Segment;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+/// Calendar period after which the active file is rotated, in UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationPeriod {
+    Hourly,
+    Daily,
+}
+
+impl RotationPeriod {
+    fn period_of(self, time: DateTime<Utc>) -> i64 {
+        let seconds = match self {
+            RotationPeriod::Hourly => 3600,
+            RotationPeriod::Daily => 86400,
+        };
+        time.timestamp().div_euclid(seconds)
+    }
+}
+
+/// Configuration for a `FileTransport`
+#[derive(Debug, Clone)]
+pub struct FileConfig {
+    /// The active log file; its directory is created if missing
+    pub path: PathBuf,
+    /// Rotate before the active file would grow beyond this many bytes
+    pub max_size: Option<u64>,
+    /// Rotate at the start of every hour or day
+    pub rotation: Option<RotationPeriod>,
+    /// Name of rotated files, from `{name}` and `{ext}` of the active file,
+    /// the rotation `{timestamp}` and a sequence `{index}`, which is required
+    pub file_pattern: String,
+    /// Number of rotated files to keep
+    pub max_files: Option<usize>,
+    /// Age after which rotated files are removed
+    pub max_age: Option<Duration>,
+    /// Compress rotated files with `codec`
+    pub compress: bool,
+    pub codec: Codec,
+    /// Level threshold reported by `get_level`
+    pub level: Option<String>,
+    /// Add an RFC 3339 `timestamp` to records that have none, so queries can
+    /// filter and sort them by time
+    pub timestamp: bool,
+    /// Records between entries of the sparse timestamp index
+    pub index_interval: usize,
+}
+
+impl FileConfig {
+    /// Creates a configuration for the given file that never rotates
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            max_size: None,
+            rotation: None,
+            file_pattern: "{name}.{timestamp}.{index}.{ext}".to_string(),
+            max_files: None,
+            max_age: None,
+            compress: false,
+            codec: Codec::default(),
+            level: None,
+            timestamp: true,
+            index_interval: 256,
+        }
+    }
+}
+
+struct ActiveFile {
+    writer: BufWriter<File>,
+    size: u64,
+    period: Option<i64>,
+    next_index: u64,
+    index: SparseIndex,
+    buf: Vec<u8>,
+}
+
+/// Writes records as JSON lines to a rotating file
+pub struct FileTransport {
+    config: FileConfig,
+    pattern: NamePattern,
+    active: Mutex<ActiveFile>,
+    format: Option<Arc<dyn Format<Input = LogInfo> + Send + Sync>>,
+}
+
+impl FileTransport {
+    /// Opens the active file for appending, creating it if missing
+    pub fn new(config: FileConfig) -> Result<Self, String> {
+        let pattern = NamePattern::new(&config.file_pattern, &config.path)?;
+        fs::create_dir_all(log_dir(&config.path))
+            .map_err(|e| format!("Failed to create log directory: {}", e))?;
+
+        let file = open_active(&config.path)?;
+        let metadata = file
+            .metadata()
+            .map_err(|e| format!("Failed to read log file metadata: {}", e))?;
+        // A file left from an earlier period rotates on the first write
+        let period = config.rotation.map(|rotation| {
+            let modified = metadata.modified().ok().filter(|_| metadata.len() > 0);
+            rotation.period_of(modified.map_or_else(Utc::now, DateTime::<Utc>::from))
+        });
+        let reader = File::open(&config.path)
+            .map_err(|e| format!("Failed to open log file {}: {}", config.path.display(), e))?;
+        let index = SparseIndex::build(BufReader::new(reader), config.index_interval)
+            .map_err(|e| format!("Failed to index log file: {}", e))?;
+        let next_index = list_rotated(&config.path, &pattern)
+            .map_err(|e| format!("Failed to list rotated files: {}", e))?
+            .last()
+            .map_or(1, |(_, name)| name.index + 1);
+
+        Ok(Self {
+            active: Mutex::new(ActiveFile {
+                writer: BufWriter::new(file),
+                size: metadata.len(),
+                period,
+                next_index,
+                index,
+                buf: Vec::new(),
+            }),
+            config,
+            pattern,
+            format: None,
+        })
+    }
+
+    pub fn with_format<F>(mut self, format: F) -> Self
+    where
+        F: Format<Input = LogInfo> + Send + Sync + 'static,
+    {
+        self.format = Some(Arc::new(format));
+        self
+    }
+
+    /// Rotated files, oldest first
+    pub fn rotated_files(&self) -> Result<Vec<PathBuf>, String> {
+        list_rotated(&self.config.path, &self.pattern)
+            .map(|files| files.into_iter().map(|(path, _)| path).collect())
+            .map_err(|e| format!("Failed to list rotated files: {}", e))
+    }
+
+    fn write_record(&self, active: &mut ActiveFile, info: &LogInfo) -> Result<(), String> {
+        active.buf.clear();
+        JsonLinesEncoder.encode(info, &mut active.buf)?;
+        let timestamp = if self.config.timestamp && !info.meta.contains_key("timestamp") {
+            // Added inside the encoded object's closing brace
+            let now = Utc::now();
+            active.buf.pop();
+            active.buf.extend_from_slice(b",\"timestamp\":\"");
+            active.buf.extend_from_slice(now.to_rfc3339().as_bytes());
+            active.buf.extend_from_slice(b"\"}");
+            Some(now)
+        } else {
+            timestamp_of(info)
+        };
+        active.buf.push(b'\n');
+
+        let len = active.buf.len() as u64;
+        let too_big = self
+            .config
+            .max_size
+            .is_some_and(|max_size| active.size > 0 && active.size + len > max_size);
+        let now = Utc::now();
+        let new_period = match (self.config.rotation, active.period) {
+            (Some(rotation), Some(period)) => rotation.period_of(now) != period,
+            _ => false,
+        };
+        if too_big || new_period {
+            self.rotate(active, now)?;
+        }
+
+        active
+            .writer
+            .write_all(&active.buf)
+            .map_err(|e| format!("Failed to write log file: {}", e))?;
+        let offset = active.size;
+        active.index.record(offset, timestamp);
+        active.size += len;
+        active.index.len = active.size;
+        Ok(())
+    }
+
+    fn rotate(&self, active: &mut ActiveFile, now: DateTime<Utc>) -> Result<(), String> {
+        active
+            .writer
+            .flush()
+            .map_err(|e| format!("Failed to flush log file: {}", e))?;
+
+        let rotated = log_dir(&self.config.path).join(self.pattern.format(now, active.next_index));
+        fs::rename(&self.config.path, &rotated)
+            .map_err(|e| format!("Failed to rotate log file: {}", e))?;
+        active.writer = BufWriter::new(open_active(&self.config.path)?);
+        let index = std::mem::replace(
+            &mut active.index,
+            SparseIndex::new(self.config.index_interval),
+        );
+        if let Err(e) = index.save(&rotated) {
+            report!("Failed to save index of {}: {}", rotated.display(), e);
+        }
+        active.size = 0;
+        active.next_index += 1;
+        active.period = self.config.rotation.map(|rotation| rotation.period_of(now));
+
+        if self.config.compress {
+            if let Err(e) = self.config.codec.compress(&rotated) {
+                report!("Failed to compress {}: {}", rotated.display(), e);
+            }
+        }
+        self.apply_retention();
+        Ok(())
+    }
+
+    fn apply_retention(&self) {
+        let mut rotated = match list_rotated(&self.config.path, &self.pattern) {
+            Ok(rotated) => rotated,
+            Err(e) => {
+                report!("Failed to list rotated files: {}", e);
+                return;
+            }
+        };
+
+        let excess = self
+            .config
+            .max_files
+            .map_or(0, |max_files| rotated.len().saturating_sub(max_files));
+        let expired = |path: &Path| {
+            self.config.max_age.is_some_and(|max_age| {
+                fs::metadata(path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+                    .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                    .is_some_and(|age| age > max_age)
+            })
+        };
+
+        for (i, (path, _)) in rotated.drain(..).enumerate() {
+            if i < excess || expired(&path) {
+                if let Err(e) = fs::remove_file(&path) {
+                    report!("Failed to remove {}: {}", path.display(), e);
+                }
+                let _ = fs::remove_file(index::sidecar_path(&path));
+            }
+        }
+    }
+}
+
+impl Transport for FileTransport {
+    fn log(&self, info: LogInfo) {
+        self.log_batch_ref(std::slice::from_ref(&info));
+    }
+
+    fn log_batch(&self, logs: Vec<LogInfo>) {
+        self.log_batch_ref(&logs);
+    }
+
+    fn log_batch_ref(&self, logs: &[LogInfo]) {
+        let mut active = self.active.lock().unwrap();
+        for info in logs {
+            if let Err(e) = self.write_record(&mut active, info) {
+                report!("FileTransport: {}", e);
+            }
+        }
+    }
+
+    fn borrows_batches(&self) -> bool {
+        true
+    }
+
+    fn flush(&self) -> Result<(), String> {
+        self.active
+            .lock()
+            .unwrap()
+            .writer
+            .flush()
+            .map_err(|e| format!("Failed to flush log file: {}", e))
+    }
+
+    fn get_level(&self) -> Option<&String> {
+        self.config.level.as_ref()
+    }
+
+    fn get_format(&self) -> Option<Arc<dyn Format<Input = LogInfo> + Send + Sync>> {
+        self.format.clone()
+    }
+
+    fn query(&self, query: &LogQuery) -> Result<Vec<LogInfo>, String> {
+        // The files are listed under the lock so no rotation moves them
+        // meanwhile, and read after it is released so logging carries on
+        let segments = {
+            let mut active = self.active.lock().unwrap();
+            active
+                .writer
+                .flush()
+                .map_err(|e| format!("Failed to flush log file: {}", e))?;
+
+            let mut segments: Vec<Segment> = list_rotated(&self.config.path, &self.pattern)
+                .map_err(|e| format!("Failed to list rotated files: {}", e))?
+                .into_iter()
+                .map(|(path, name)| Segment {
+                    index: SparseIndex::load(&path, name.codec.is_some()),
+                    codec: name.codec,
+                    path,
+                    file: None,
+                })
+                .collect();
+            // Opened now, so the snapshot of its index still describes the
+            // file read even if it is rotated before the query gets to it
+            let file = File::open(&self.config.path).map_err(|e| {
+                format!(
+                    "Failed to open log file {}: {}",
+                    self.config.path.display(),
+                    e
+                )
+            })?;
+            segments.push(Segment {
+                path: self.config.path.clone(),
+                codec: None,
+                index: Some(active.index.clone()),
+                file: Some(file),
+            });
+            segments
+        };
+        scan::query_segments(&segments, query)
+    }
+}
+
+impl Drop for FileTransport {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+fn log_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+fn open_active(path: &Path) -> Result<File, String> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Failed to open log file {}: {}", path.display(), e))
+}
+
+/// Rotated files of the active file at `path`, oldest first
+fn list_rotated(
+    path: &Path,
+    pattern: &NamePattern,
+) -> io::Result<Vec<(PathBuf, naming::RotatedName)>> {
+    let mut rotated = Vec::new();
+    for entry in fs::read_dir(log_dir(path))? {
+        let entry = entry?;
+        if let Some(name) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| pattern.parse(name))
+        {
+            rotated.push((entry.path(), name));
+        }
+    }
+    rotated.sort_by_key(|(_, name)| name.index);
+    Ok(rotated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use serde_json::json;
+    use std::io::Read;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "winston_transport_file_{}_{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn record(level: &str, message: &str, minute: u32) -> LogInfo {
+        LogInfo::new(level, message)
+            .with_meta("timestamp", format!("2024-04-10T12:{:02}:00Z", minute))
+    }
+
+    fn messages(infos: &[LogInfo]) -> Vec<&str> {
+        infos.iter().map(|info| info.message.as_str()).collect()
+    }
+
+    fn query() -> LogQuery {
+        LogQuery::new()
+            .from("2024-04-10T00:00:00Z")
+            .until("2024-04-11T00:00:00Z")
+    }
+
+    #[test]
+    fn test_write_and_query() {
+        let dir = temp_dir("query");
+        let transport = FileTransport::new(FileConfig::new(dir.join("app.log"))).unwrap();
+
+        transport.log(record("info", "user login", 1).with_meta("user", json!({"age": 30})));
+        transport.log_batch(vec![
+            record("error", "database down", 2),
+            record("info", "database up", 3).with_meta("user", json!({"age": 12})),
+            record("info", "yesterday", 0).with_meta("timestamp", "2024-04-09T12:00:00Z"),
+        ]);
+
+        let results = transport.query(&query()).unwrap();
+        assert_eq!(
+            messages(&results),
+            vec!["database up", "database down", "user login"]
+        );
+
+        let results = transport
+            .query(&query().order("asc").start(1).limit(1))
+            .unwrap();
+        assert_eq!(messages(&results), vec!["database down"]);
+
+        let results = transport
+            .query(&query().levels(vec!["info"]).search_term("(?i)DATABASE"))
+            .unwrap();
+        assert_eq!(messages(&results), vec!["database up"]);
+
+        let results = transport
+            .query(
+                &query()
+                    .filter(json!({"meta.user.age": {"$gt": 18}}))
+                    .fields(vec!["user"]),
+            )
+            .unwrap();
+        assert_eq!(messages(&results), vec!["user login"]);
+        assert_eq!(results[0].meta.len(), 1);
+
+        // Records without a timestamp are stamped when written
+        transport.log(LogInfo::new("info", "now"));
+        let results = transport.query(&LogQuery::new()).unwrap();
+        assert_eq!(messages(&results), vec!["now"]);
+
+        drop(transport);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_size_rotation_and_retention() {
+        let dir = temp_dir("rotate");
+        let mut config = FileConfig::new(dir.join("app.log"));
+        config.max_size = Some(100);
+        config.max_files = Some(2);
+        let transport = FileTransport::new(config.clone()).unwrap();
+
+        for minute in 0..6 {
+            transport.log(record("info", &format!("message {}", minute), minute));
+        }
+
+        // Each file holds one record; only the two newest rotated files remain
+        let rotated = transport.rotated_files().unwrap();
+        assert_eq!(rotated.len(), 2);
+        let names: Vec<String> = rotated
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert!(names[0].starts_with("app.") && names[0].ends_with(".4.log"));
+        assert!(names[1].ends_with(".5.log"));
+
+        let results = transport.query(&query().order("asc")).unwrap();
+        assert_eq!(
+            messages(&results),
+            vec!["message 3", "message 4", "message 5"]
+        );
+
+        // A new transport continues the sequence
+        drop(transport);
+        let transport = FileTransport::new(config).unwrap();
+        transport.log(record("info", "message 6", 6));
+        let last = transport.rotated_files().unwrap().pop().unwrap();
+        assert!(last.to_string_lossy().ends_with(".6.log"));
+
+        drop(transport);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_indexed_window_queries() {
+        let dir = temp_dir("indexed");
+        let mut config = FileConfig::new(dir.join("app.log"));
+        config.max_size = Some(4096);
+        config.index_interval = 8;
+        let transport = FileTransport::new(config.clone()).unwrap();
+
+        let at = |i: u32| format!("2024-04-10T{:02}:{:02}:00Z", i / 60, i % 60);
+        for i in 0..600 {
+            transport.log(
+                LogInfo::new(if i % 3 == 0 { "error" } else { "info" }, i.to_string())
+                    .with_meta("timestamp", at(i)),
+            );
+        }
+        assert!(transport.rotated_files().unwrap().len() > 5);
+
+        let window = |from: u32, until: u32| {
+            LogQuery::new()
+                .from(at(from).as_str())
+                .until(at(until).as_str())
+        };
+        let expected = |range: Vec<u32>| range.iter().map(u32::to_string).collect::<Vec<_>>();
+
+        let results = transport.query(&window(0, 599).limit(5)).unwrap();
+        assert_eq!(messages(&results), expected((595..600).rev().collect()));
+
+        let results = transport
+            .query(&window(100, 300).order("asc").start(10).limit(4))
+            .unwrap();
+        assert_eq!(messages(&results), expected((110..114).collect()));
+
+        let results = transport
+            .query(&window(250, 260).levels(vec!["error"]).limit(50))
+            .unwrap();
+        assert_eq!(messages(&results), expected(vec![258, 255, 252]));
+
+        // Files indexed before a restart give the same answers
+        drop(transport);
+        let transport = FileTransport::new(config).unwrap();
+        let results = transport.query(&window(398, 402).order("asc")).unwrap();
+        assert_eq!(messages(&results), expected((398..403).collect()));
+
+        // Without their indexes, rotated files are read in full
+        for path in transport.rotated_files().unwrap() {
+            fs::remove_file(index::sidecar_path(&path)).unwrap();
+        }
+        let results = transport.query(&window(0, 599).limit(3)).unwrap();
+        assert_eq!(messages(&results), expected(vec![599, 598, 597]));
+
+        drop(transport);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_indexed_queries_read_only_the_window() {
+        let dir = temp_dir("indexed_seek");
+        let mut config = FileConfig::new(dir.join("app.log"));
+        config.max_size = Some(4096);
+        config.index_interval = 8;
+        let transport = FileTransport::new(config).unwrap();
+
+        let at = |i: u32| format!("2024-04-10T{:02}:{:02}:00Z", i / 60, i % 60);
+        for i in 0..200 {
+            transport.log(LogInfo::new("info", i.to_string()).with_meta("timestamp", at(i)));
+        }
+        let path = transport.rotated_files().unwrap()[1].clone();
+        let content = fs::read_to_string(&path).unwrap();
+        let records: Vec<u32> = content
+            .lines()
+            .map(|line| {
+                let record: serde_json::Value = serde_json::from_str(line).unwrap();
+                record["message"].as_str().unwrap().parse().unwrap()
+            })
+            .collect();
+        assert!(records.len() > 30);
+        let middle = records[records.len() / 2];
+
+        // Move the first and last records into the window without changing
+        // the file's length, so its index still applies. They are more than
+        // one index interval from the window, so they are only returned if
+        // the scan reads from the start of the file or past the window.
+        let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+        for line in [0, lines.len() - 1] {
+            let timestamp = at(records[line]);
+            assert!(lines[line].contains(&timestamp));
+            lines[line] = lines[line].replace(&timestamp, &at(middle));
+        }
+        fs::write(&path, lines.join("\n") + "\n").unwrap();
+
+        let window = LogQuery::new()
+            .from(at(middle - 1).as_str())
+            .until(at(middle + 1).as_str());
+        let expected: Vec<String> = (middle - 1..=middle + 1).map(|i| i.to_string()).collect();
+        let results = transport.query(&window.clone().order("asc")).unwrap();
+        assert_eq!(messages(&results), expected);
+        let results = transport.query(&window).unwrap();
+        assert_eq!(
+            messages(&results),
+            expected.into_iter().rev().collect::<Vec<_>>()
+        );
+
+        drop(transport);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn archive_queries(name: &str, codec: Codec) {
+        let dir = temp_dir(name);
+        let mut config = FileConfig::new(dir.join("app.log"));
+        config.max_size = Some(2048);
+        config.compress = true;
+        config.codec = codec;
+        let transport = FileTransport::new(config).unwrap();
+
+        let at = |i: u32| format!("2024-04-10T{:02}:{:02}:00Z", i / 60, i % 60);
+        for i in 0..300 {
+            transport.log(LogInfo::new("info", i.to_string()).with_meta("timestamp", at(i)));
+        }
+        let archives = transport.rotated_files().unwrap();
+        assert!(archives.len() > 3);
+        let extension = format!(".{}", codec.extension());
+        assert!(archives
+            .iter()
+            .all(|path| path.to_string_lossy().ends_with(&extension)));
+
+        // Archives outside the window are never opened, so damaging them is harmless
+        fs::write(&archives[0], b"not compressed").unwrap();
+        let window = |from: u32, until: u32| {
+            LogQuery::new()
+                .from(at(from).as_str())
+                .until(at(until).as_str())
+        };
+        let expected = |range: Vec<u32>| range.iter().map(u32::to_string).collect::<Vec<_>>();
+
+        let results = transport.query(&window(100, 200).limit(3)).unwrap();
+        assert_eq!(messages(&results), expected(vec![200, 199, 198]));
+        let results = transport
+            .query(&window(100, 200).order("asc").limit(3))
+            .unwrap();
+        assert_eq!(messages(&results), expected(vec![100, 101, 102]));
+        let results = transport.query(&window(150, 299).limit(200)).unwrap();
+        assert_eq!(messages(&results), expected((150..300).rev().collect()));
+        assert!(transport.query(&window(0, 299).order("asc")).is_err());
+
+        drop(transport);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unordered_segment_still_skips_archives() {
+        let dir = temp_dir("unordered");
+        let mut config = FileConfig::new(dir.join("app.log"));
+        config.max_size = Some(2048);
+        config.compress = true;
+        let transport = FileTransport::new(config).unwrap();
+
+        let at = |i: u32| format!("2024-04-10T{:02}:{:02}:00Z", i / 60, i % 60);
+        for i in 0..300 {
+            transport.log(LogInfo::new("info", i.to_string()).with_meta("timestamp", at(i)));
+            if i == 100 {
+                // Puts one file out of order, so queries read files in full
+                transport.log(LogInfo::new("info", "backdated").with_meta("timestamp", at(50)));
+            }
+        }
+        let archives = transport.rotated_files().unwrap();
+        let mut first = String::new();
+        GzDecoder::new(fs::File::open(&archives[0]).unwrap())
+            .read_to_string(&mut first)
+            .unwrap();
+        assert!(!first.contains("\"message\":\"40\""));
+
+        // The first archive holds no record from either window, so damaging
+        // it is harmless
+        fs::write(&archives[0], b"not compressed").unwrap();
+        let window = |from: u32, until: u32| {
+            LogQuery::new()
+                .from(at(from).as_str())
+                .until(at(until).as_str())
+        };
+
+        let results = transport.query(&window(200, 202).order("asc")).unwrap();
+        assert_eq!(messages(&results), vec!["200", "201", "202"]);
+        let results = transport.query(&window(50, 51).order("asc")).unwrap();
+        assert_eq!(messages(&results), vec!["50", "backdated", "51"]);
+        assert!(transport.query(&window(0, 299)).is_err());
+
+        drop(transport);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_gzip_archive_queries() {
+        archive_queries("gzip", Codec::Gzip);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_archive_queries() {
+        archive_queries("zstd", Codec::Zstd);
+    }
+
+    #[test]
+    fn test_time_rotation_and_compression() {
+        let dir = temp_dir("daily");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        fs::write(&path, "{\"level\":\"info\",\"message\":\"old\"}\n").unwrap();
+        let yesterday = SystemTime::now() - Duration::from_secs(86400);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(yesterday)
+            .unwrap();
+
+        let mut config = FileConfig::new(&path);
+        config.rotation = Some(RotationPeriod::Daily);
+        config.compress = true;
+        let transport = FileTransport::new(config).unwrap();
+        transport.log(LogInfo::new("info", "new"));
+        transport.flush().unwrap();
+
+        // Yesterday's file was rotated and compressed on the first write
+        let rotated = transport.rotated_files().unwrap();
+        assert_eq!(rotated.len(), 1);
+        assert!(rotated[0].to_string_lossy().ends_with(".1.log.gz"));
+        let mut text = String::new();
+        GzDecoder::new(File::open(&rotated[0]).unwrap())
+            .read_to_string(&mut text)
+            .unwrap();
+        assert!(text.contains("\"old\""));
+        assert!(fs::read_to_string(&path).unwrap().contains("\"new\""));
+
+        drop(transport);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,80 @@
+//! Compression of rotated files.
+
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+/// Compression applied to rotated files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Codec {
+    /// `.gz`, readable with `zcat`
+    #[default]
+    Gzip,
+    /// `.zst`, with the `zstd` feature
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Codec {
+    /// Every codec compiled in, which rotated file names are matched against
+    pub(crate) const ALL: &'static [Codec] = &[
+        Codec::Gzip,
+        #[cfg(feature = "zstd")]
+        Codec::Zstd,
+    ];
+
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            Codec::Gzip => "gz",
+            #[cfg(feature = "zstd")]
+            Codec::Zstd => "zst",
+        }
+    }
+
+    pub(crate) fn from_extension(extension: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|codec| codec.extension() == extension)
+    }
+
+    /// Replaces `path` with a compressed copy named with the codec's extension
+    pub(crate) fn compress(self, path: &Path) -> io::Result<PathBuf> {
+        let mut target = path.as_os_str().to_owned();
+        target.push(".");
+        target.push(self.extension());
+        let mut temp = target.clone();
+        temp.push(".tmp");
+
+        let mut source = File::open(path)?;
+        let file = match self {
+            Codec::Gzip => {
+                let mut encoder = GzEncoder::new(File::create(&temp)?, Compression::default());
+                io::copy(&mut source, &mut encoder)?;
+                encoder.finish()?
+            }
+            #[cfg(feature = "zstd")]
+            Codec::Zstd => {
+                let mut encoder = zstd::Encoder::new(File::create(&temp)?, 0)?;
+                io::copy(&mut source, &mut encoder)?;
+                encoder.finish()?
+            }
+        };
+        file.sync_all()?;
+        fs::rename(&temp, &target)?;
+        fs::remove_file(path)?;
+        Ok(target.into())
+    }
+
+    /// Streams the decompressed contents of `file`
+    pub(crate) fn decoder(self, file: File) -> io::Result<Box<dyn Read>> {
+        Ok(match self {
+            Codec::Gzip => Box::new(MultiGzDecoder::new(BufReader::new(file))),
+            #[cfg(feature = "zstd")]
+            Codec::Zstd => Box::new(zstd::Decoder::new(file)?),
+        })
+    }
+}
@@ -0,0 +1,191 @@
+//! Sparse timestamp index over a log file.
+//!
+//! Every `interval`-th record contributes its byte offset and timestamp. While
+//! a file's timestamps never decrease, the samples bound where any time window
+//! can start and end, so queries seek past older records and stop at newer
+//! ones instead of reading the whole file. A file whose records are out of
+//! order, or lack a timestamp, is marked unordered and always read in full.
+//!
+//! The index of the active file lives in memory and is written next to the
+//! file as `<rotated name>.idx` when it rotates.
+
+use super::codec::Codec;
+use crate::LogQuery;
+use chrono::{DateTime, Utc};
+use logform::LogInfo;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs,
+    io::{self, BufRead},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SparseIndex {
+    interval: usize,
+    /// Length of the indexed file, to detect a sidecar left stale
+    pub(crate) len: u64,
+    pub(crate) count: u64,
+    pub(crate) ordered: bool,
+    /// Earliest and latest timestamps, in nanoseconds since the epoch
+    pub(crate) first: Option<i64>,
+    pub(crate) last: Option<i64>,
+    samples: Vec<(u64, i64)>,
+}
+
+impl SparseIndex {
+    pub(crate) fn new(interval: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            len: 0,
+            count: 0,
+            ordered: true,
+            first: None,
+            last: None,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Indexes the records of an existing file
+    pub(crate) fn build(reader: impl BufRead, interval: usize) -> io::Result<Self> {
+        let mut index = Self::new(interval);
+        let len = super::scan::for_each_record(reader, |offset, info| {
+            index.record(offset, timestamp_of(&info));
+            std::ops::ControlFlow::Continue(())
+        })?;
+        index.len = len;
+        Ok(index)
+    }
+
+    /// Notes a record written at `offset`
+    pub(crate) fn record(&mut self, offset: u64, timestamp: Option<DateTime<Utc>>) {
+        let count = self.count;
+        self.count += 1;
+        let Some(nanos) = timestamp.and_then(|timestamp| timestamp.timestamp_nanos_opt()) else {
+            self.ordered = false;
+            return;
+        };
+        if self.last.is_some_and(|last| nanos < last) {
+            self.ordered = false;
+        }
+        self.first = Some(self.first.map_or(nanos, |first| first.min(nanos)));
+        self.last = Some(self.last.map_or(nanos, |last| last.max(nanos)));
+        if count % self.interval as u64 == 0 {
+            self.samples.push((offset, nanos));
+        }
+    }
+
+    /// Whether the file can hold records between `from` and `until`
+    pub(crate) fn overlaps(&self, from: Option<i64>, until: Option<i64>) -> bool {
+        match (self.first, self.last) {
+            (Some(first), Some(last)) => {
+                from.is_none_or(|from| last >= from) && until.is_none_or(|until| first <= until)
+            }
+            _ => self.count > 0,
+        }
+    }
+
+    /// Offset before which every record is older than `from`; ordered files only
+    pub(crate) fn seek(&self, from: Option<i64>) -> u64 {
+        let Some(from) = from else { return 0 };
+        let after = self.samples.partition_point(|&(_, nanos)| nanos < from);
+        after
+            .checked_sub(1)
+            .map_or(0, |sample| self.samples[sample].0)
+    }
+
+    /// Offset from which every record is newer than `until`; ordered files only
+    pub(crate) fn end(&self, until: Option<i64>) -> u64 {
+        let Some(until) = until else { return self.len };
+        let after = self.samples.partition_point(|&(_, nanos)| nanos <= until);
+        self.samples
+            .get(after)
+            .map_or(self.len, |&(offset, _)| offset)
+    }
+
+    /// Reads the sidecar of the file at `path`, if it still describes the file.
+    /// Offsets and `len` count uncompressed bytes, so a compressed file's
+    /// length cannot be checked.
+    pub(crate) fn load(path: &Path, compressed: bool) -> Option<Self> {
+        let index: Self = serde_json::from_slice(&fs::read(sidecar_path(path)).ok()?).ok()?;
+        let len = fs::metadata(path).ok()?.len();
+        (compressed || index.len == len).then_some(index)
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> io::Result<()> {
+        let target = sidecar_path(path);
+        let mut temp = target.clone().into_os_string();
+        temp.push(".tmp");
+        fs::write(&temp, serde_json::to_vec(self)?)?;
+        fs::rename(&temp, &target)
+    }
+}
+
+/// The sidecar of a rotated file, shared by its compressed form
+pub(crate) fn sidecar_path(path: &Path) -> PathBuf {
+    let compressed = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(Codec::from_extension);
+    let mut name = match compressed {
+        Some(_) => path.with_extension(""),
+        None => path.to_path_buf(),
+    }
+    .into_os_string();
+    name.push(".idx");
+    PathBuf::from(name)
+}
+
+/// The timestamp `LogQuery` filters and sorts the record by
+pub(crate) fn timestamp_of(info: &LogInfo) -> Option<DateTime<Utc>> {
+    // Most records carry RFC 3339, which parses much faster than the general case
+    if let Some(Value::String(text)) = info.meta.get("timestamp") {
+        if let Ok(timestamp) = DateTime::parse_from_rfc3339(text) {
+            return Some(timestamp.with_timezone(&Utc));
+        }
+    }
+    LogQuery::extract_timestamp(info)
+}
+
+/// Nanoseconds for a query bound, `None` when unbounded or out of range
+pub(crate) fn nanos(bound: Option<DateTime<Utc>>) -> Option<Option<i64>> {
+    match bound {
+        None => Some(None),
+        Some(bound) => bound.timestamp_nanos_opt().map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seek_and_end() {
+        let mut index = SparseIndex::new(2);
+        for (i, second) in [10, 20, 30, 40, 50].into_iter().enumerate() {
+            let timestamp = DateTime::from_timestamp(second, 0).unwrap();
+            index.record(i as u64 * 100, Some(timestamp));
+        }
+        index.len = 500;
+        let at = |second: i64| Some(second * 1_000_000_000);
+
+        assert!(index.ordered);
+        assert_eq!(index.seek(None), 0);
+        assert_eq!(index.seek(at(10)), 0);
+        assert_eq!(index.seek(at(35)), 200);
+        assert_eq!(index.seek(at(60)), 400);
+        assert_eq!(index.end(None), 500);
+        assert_eq!(index.end(at(30)), 400);
+        assert_eq!(index.end(at(25)), 200);
+        assert_eq!(index.end(at(5)), 0);
+        assert!(index.overlaps(at(50), None));
+        assert!(!index.overlaps(at(51), None));
+        assert!(!index.overlaps(None, at(9)));
+
+        index.record(500, DateTime::from_timestamp(45, 0));
+        assert!(!index.ordered);
+        index.record(600, None);
+        assert_eq!(index.count, 7);
+    }
+}
@@ -0,0 +1,149 @@
+//! Names of rotated files.
+//!
+//! A pattern such as `{name}.{timestamp}.{index}.{ext}` turns the active file
+//! `app.log` into `app.20241018T120000.7.log` when it rotates. The index grows
+//! by one with every rotation, so it orders the rotated files and keeps their
+//! names unique. Compressed files keep the name with `.gz` or `.zst` appended.
+
+use super::codec::Codec;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use std::path::Path;
+
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S";
+
+/// A rotated file found in the log directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RotatedName {
+    pub(crate) index: u64,
+    pub(crate) codec: Option<Codec>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct NamePattern {
+    pattern: String,
+    name: String,
+    ext: String,
+    regex: Regex,
+}
+
+impl NamePattern {
+    /// Parses `pattern` for rotating the file at `active`
+    pub(crate) fn new(pattern: &str, active: &Path) -> Result<Self, String> {
+        let name = active
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| format!("Invalid log file path '{}'", active.display()))?
+            .to_string();
+        let ext = active
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("")
+            .to_string();
+
+        let mut regex = String::from("^");
+        let mut rest = pattern;
+        let mut has_index = false;
+        while let Some(start) = rest.find('{') {
+            regex.push_str(&regex::escape(&rest[..start]));
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("Unclosed placeholder in '{}'", pattern))?;
+            match &rest[start + 1..start + end] {
+                "name" => regex.push_str(&regex::escape(&name)),
+                "ext" => regex.push_str(&regex::escape(&ext)),
+                "timestamp" => regex.push_str(r"\d{8}T\d{6}"),
+                "index" if !has_index => {
+                    regex.push_str(r"(?P<index>\d+)");
+                    has_index = true;
+                }
+                other => {
+                    return Err(format!(
+                        "Unsupported placeholder '{{{}}}' in '{}'",
+                        other, pattern
+                    ))
+                }
+            }
+            rest = &rest[start + end + 1..];
+        }
+        regex.push_str(&regex::escape(rest));
+        let extensions: Vec<&str> = Codec::ALL.iter().map(|codec| codec.extension()).collect();
+        regex.push_str(&format!(r"(?:\.(?P<codec>{}))?$", extensions.join("|")));
+
+        if !has_index {
+            return Err(format!("File pattern '{}' must contain {{index}}", pattern));
+        }
+        if pattern.contains(['/', '\\']) {
+            return Err(format!("File pattern '{}' must be a file name", pattern));
+        }
+
+        Ok(Self {
+            pattern: pattern.to_string(),
+            regex: Regex::new(&regex).map_err(|e| format!("Invalid file pattern: {}", e))?,
+            name,
+            ext,
+        })
+    }
+
+    /// The name for the file rotated at `timestamp` with sequence `index`
+    pub(crate) fn format(&self, timestamp: DateTime<Utc>, index: u64) -> String {
+        self.pattern
+            .replace("{name}", &self.name)
+            .replace("{ext}", &self.ext)
+            .replace(
+                "{timestamp}",
+                &timestamp.format(TIMESTAMP_FORMAT).to_string(),
+            )
+            .replace("{index}", &index.to_string())
+    }
+
+    /// Recognizes a file name produced by `format`, compressed or not
+    pub(crate) fn parse(&self, file_name: &str) -> Option<RotatedName> {
+        let captures = self.regex.captures(file_name)?;
+        Some(RotatedName {
+            index: captures["index"].parse().ok()?,
+            codec: match captures.name("codec") {
+                Some(extension) => Some(Codec::from_extension(extension.as_str())?),
+                None => None,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_format_and_parse() {
+        let pattern = NamePattern::new(
+            "{name}.{timestamp}.{index}.{ext}",
+            Path::new("logs/app.log"),
+        )
+        .unwrap();
+        let timestamp = Utc.with_ymd_and_hms(2024, 10, 18, 12, 0, 0).unwrap();
+        let name = pattern.format(timestamp, 7);
+        assert_eq!(name, "app.20241018T120000.7.log");
+
+        assert_eq!(
+            pattern.parse(&name),
+            Some(RotatedName {
+                index: 7,
+                codec: None
+            })
+        );
+        assert_eq!(
+            pattern
+                .parse(&format!("{}.gz", name))
+                .map(|name| name.codec),
+            Some(Some(Codec::Gzip))
+        );
+        assert_eq!(pattern.parse("app.log"), None);
+        assert_eq!(pattern.parse("other.20241018T120000.7.log"), None);
+
+        assert!(NamePattern::new("{name}.{timestamp}.{ext}", Path::new("app.log")).is_err());
+        assert!(NamePattern::new("{name}.{index}.{bogus}", Path::new("app.log")).is_err());
+        assert!(NamePattern::new("old/{name}.{index}", Path::new("app.log")).is_err());
+    }
+}
@@ -0,0 +1,347 @@
+//! Reading records back out of log files for `FileTransport::query`.
+//!
+//! When every file has an ordered index and the files follow one another in
+//! time, the query reads only the window between `from` and `until`: forwards
+//! from the oldest file for ascending order, backwards in blocks from the
+//! newest for descending order, stopping once `start + limit` records match.
+//! Anything else falls back to reading every file and sorting the matches,
+//! still skipping files whose index puts all their records outside the
+//! window, since the first and last timestamps of an index bound its records
+//! even when they are out of order.
+//!
+//! Compressed files are decompressed as a stream, and only when their first
+//! and last timestamps overlap the window. Descending reads of them collect the
+//! window's records and walk them backwards, since a stream cannot be read
+//! from the end.
+
+use super::{
+    codec::Codec,
+    index::{nanos, timestamp_of, SparseIndex},
+};
+use crate::{
+    decoder::{InputDecoder, JsonLinesDecoder},
+    log_query::Order,
+    LogInfo, LogQuery,
+};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+    ops::ControlFlow,
+    path::PathBuf,
+};
+
+const BLOCK_SIZE: u64 = 64 * 1024;
+
+/// A file to search, with its index when one is available
+pub(crate) struct Segment {
+    pub(crate) path: PathBuf,
+    pub(crate) codec: Option<Codec>,
+    pub(crate) index: Option<SparseIndex>,
+    /// The file opened when it was listed, for one that may be renamed since
+    pub(crate) file: Option<File>,
+}
+
+impl Segment {
+    /// Opens the file, or `None` when retention removed it since it was listed
+    fn open(&self) -> io::Result<Option<File>> {
+        if let Some(file) = &self.file {
+            return file.try_clone().map(Some);
+        }
+        match File::open(&self.path) {
+            Ok(file) => Ok(Some(file)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Runs `query` over the segments, given oldest first
+pub(crate) fn query_segments(
+    segments: &[Segment],
+    query: &LogQuery,
+) -> Result<Vec<LogInfo>, String> {
+    let (Some(from), Some(until)) = (nanos(query.from), nanos(query.until)) else {
+        return query_files(segments, query);
+    };
+    let Some(indexes) = ordered_indexes(segments) else {
+        return query_files(segments, query);
+    };
+
+    let wanted = query
+        .limit
+        .map(|limit| limit.saturating_add(query.start.unwrap_or(0)));
+    let mut matches = Vec::new();
+    let mut visit = |info: LogInfo| {
+        let Some(timestamp) = timestamp_of(&info).and_then(|t| t.timestamp_nanos_opt()) else {
+            return ControlFlow::Continue(());
+        };
+        // Past the window, and so is every record further along
+        let past = match query.order {
+            Order::Ascending => until.is_some_and(|until| timestamp > until),
+            Order::Descending => from.is_some_and(|from| timestamp < from),
+        };
+        if past {
+            return ControlFlow::Break(());
+        }
+        if query.matches(&info) {
+            matches.push(info);
+            if wanted.is_some_and(|wanted| matches.len() >= wanted) {
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(())
+    };
+
+    let mut ordered: Vec<(&Segment, &SparseIndex)> = segments.iter().zip(indexes).collect();
+    if let Order::Descending = query.order {
+        ordered.reverse();
+    }
+    for (segment, index) in ordered {
+        if !index.overlaps(from, until) {
+            continue;
+        }
+        let (begin, end) = (index.seek(from), index.end(until));
+        let flow = read_range(segment, begin, end, &query.order, &mut visit)
+            .map_err(|e| format!("Failed to read {}: {}", segment.path.display(), e))?;
+        if flow.is_break() {
+            break;
+        }
+    }
+
+    Ok(page(matches, query))
+}
+
+/// Indexes of all segments when each is ordered and each starts no earlier
+/// than the previous one ends
+fn ordered_indexes(segments: &[Segment]) -> Option<Vec<&SparseIndex>> {
+    let mut last = None;
+    let mut indexes = Vec::with_capacity(segments.len());
+    for segment in segments {
+        let index = segment.index.as_ref().filter(|index| index.ordered)?;
+        if let Some(first) = index.first {
+            if last.is_some_and(|last| first < last) {
+                return None;
+            }
+            last = index.last;
+        }
+        indexes.push(index);
+    }
+    Some(indexes)
+}
+
+fn read_range(
+    segment: &Segment,
+    begin: u64,
+    end: u64,
+    order: &Order,
+    f: &mut impl FnMut(LogInfo) -> ControlFlow<()>,
+) -> io::Result<ControlFlow<()>> {
+    if begin >= end {
+        return Ok(ControlFlow::Continue(()));
+    }
+    let Some(mut file) = segment.open()? else {
+        return Ok(ControlFlow::Continue(()));
+    };
+    if let Some(codec) = segment.codec {
+        let mut reader = codec.decoder(file)?;
+        io::copy(&mut (&mut reader).take(begin), &mut io::sink())?;
+        let reader = BufReader::new(reader.take(end - begin));
+        return match order {
+            Order::Ascending => {
+                let mut flow = ControlFlow::Continue(());
+                for_each_record(reader, |_, info| {
+                    flow = f(info);
+                    flow
+                })?;
+                Ok(flow)
+            }
+            Order::Descending => {
+                let mut records = Vec::new();
+                for_each_record(reader, |_, info| {
+                    records.push(info);
+                    ControlFlow::Continue(())
+                })?;
+                Ok(records.into_iter().rev().try_for_each(f))
+            }
+        };
+    }
+    match order {
+        Order::Ascending => {
+            file.seek(SeekFrom::Start(begin))?;
+            let mut flow = ControlFlow::Continue(());
+            for_each_record(BufReader::new(file.take(end - begin)), |_, info| {
+                flow = f(info);
+                flow
+            })?;
+            Ok(flow)
+        }
+        Order::Descending => for_each_record_rev(&mut file, begin, end, f),
+    }
+}
+
+/// Runs `query` over the files, given oldest first, reading each one that can
+/// hold matches in full
+fn query_files(segments: &[Segment], query: &LogQuery) -> Result<Vec<LogInfo>, String> {
+    let window = nanos(query.from).zip(nanos(query.until));
+    let mut matches = Vec::new();
+    for segment in segments {
+        if let (Some((from, until)), Some(index)) = (window, &segment.index) {
+            if !index.overlaps(from, until) {
+                continue;
+            }
+        }
+        let path = &segment.path;
+        let file = match segment.open() {
+            Ok(Some(file)) => file,
+            Ok(None) => continue,
+            Err(e) => return Err(format!("Failed to open {}: {}", path.display(), e)),
+        };
+        let reader: Box<dyn Read> = match segment.codec {
+            Some(codec) => codec
+                .decoder(file)
+                .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?,
+            None => Box::new(file),
+        };
+        for_each_record(BufReader::new(reader), |_, info| {
+            if query.matches(&info) {
+                matches.push(info);
+            }
+            ControlFlow::Continue(())
+        })
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    }
+
+    query.sort(&mut matches);
+    Ok(page(matches, query))
+}
+
+/// Applies `start`, `limit` and `fields` to matches already in query order
+fn page(matches: Vec<LogInfo>, query: &LogQuery) -> Vec<LogInfo> {
+    let matches = matches.into_iter().skip(query.start.unwrap_or(0));
+    let mut results: Vec<LogInfo> = match query.limit {
+        Some(limit) => matches.take(limit).collect(),
+        None => matches.collect(),
+    };
+    query.retain_fields(&mut results);
+    results
+}
+
+/// Decodes every JSON line in `reader` with its offset, skipping lines that do
+/// not parse, and returns the number of bytes read
+pub(crate) fn for_each_record(
+    mut reader: impl BufRead,
+    mut f: impl FnMut(u64, LogInfo) -> ControlFlow<()>,
+) -> io::Result<u64> {
+    let mut line = Vec::new();
+    let mut offset = 0;
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)? as u64;
+        if read == 0 {
+            return Ok(offset);
+        }
+        if let Some(info) = decode_line(&line) {
+            if f(offset, info).is_break() {
+                return Ok(offset + read);
+            }
+        }
+        offset += read;
+    }
+}
+
+/// Decodes the JSON lines between `begin` and `end` newest first, reading the
+/// file backwards in blocks; both offsets must fall on line starts
+fn for_each_record_rev(
+    file: &mut File,
+    begin: u64,
+    end: u64,
+    f: &mut impl FnMut(LogInfo) -> ControlFlow<()>,
+) -> io::Result<ControlFlow<()>> {
+    let mut pos = end;
+    // Start of the line that continues past the block read last
+    let mut carry = Vec::new();
+    while pos > begin {
+        let size = BLOCK_SIZE.min(pos - begin);
+        pos -= size;
+        let mut block = vec![0; size as usize];
+        file.seek(SeekFrom::Start(pos))?;
+        file.read_exact(&mut block)?;
+        block.extend_from_slice(&carry);
+
+        let mut line_end = block.len();
+        while let Some(newline) = block[..line_end].iter().rposition(|&b| b == b'\n') {
+            if let Some(info) = decode_line(&block[newline + 1..line_end]) {
+                if f(info).is_break() {
+                    return Ok(ControlFlow::Break(()));
+                }
+            }
+            line_end = newline;
+        }
+        block.truncate(line_end);
+        carry = block;
+    }
+    Ok(decode_line(&carry).map_or(ControlFlow::Continue(()), f))
+}
+
+fn decode_line(line: &[u8]) -> Option<LogInfo> {
+    let text = String::from_utf8_lossy(line);
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    // A torn last line from a crash does not parse and is skipped
+    JsonLinesDecoder.decode(text, "").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_reverse_read_across_blocks() {
+        let path =
+            std::env::temp_dir().join(format!("winston_transport_scan_{}.log", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        // Lines longer than a block and lines straddling block boundaries
+        let long = "x".repeat(BLOCK_SIZE as usize + 10);
+        let messages: Vec<String> = (0..2000)
+            .map(|i| {
+                if i % 500 == 0 {
+                    format!("{}{}", long, i)
+                } else {
+                    i.to_string()
+                }
+            })
+            .collect();
+        for message in &messages {
+            writeln!(file, "{{\"level\":\"info\",\"message\":\"{}\"}}", message).unwrap();
+        }
+        let len = file.metadata().unwrap().len();
+
+        let mut read = Vec::new();
+        let flow = for_each_record_rev(&mut File::open(&path).unwrap(), 0, len, &mut |info| {
+            read.push(info.message);
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        assert!(flow.is_continue());
+        read.reverse();
+        assert_eq!(read, messages);
+
+        let mut read = Vec::new();
+        let flow = for_each_record_rev(&mut File::open(&path).unwrap(), 0, len, &mut |info| {
+            read.push(info.message);
+            if read.len() == 3 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })
+        .unwrap();
+        assert!(flow.is_break());
+        assert_eq!(read, vec!["1999", "1998", "1997"]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,6 +1,24 @@
+#[macro_use]
+mod diagnostics;
+
 pub mod batch_transport;
+#[cfg(target_os = "linux")]
+pub mod capture;
+pub mod clock;
+pub mod console;
+pub mod decoder;
+pub mod encoder;
+pub mod executor;
+pub mod file_transport;
 mod log_query;
+pub mod parsers;
+pub mod process;
 pub mod query_dsl;
+pub mod receipt;
+mod ring_buffer;
+pub mod stats;
+pub mod syslog;
+pub mod tail;
 pub mod threaded_transport;
 mod transport;
 pub mod transport_adapters;
@@ -6,6 +6,8 @@ use regex::Regex;
 use serde_json::Value;
 use std::str::FromStr;
 
+use crate::query_dsl::dlc::alpha::a::QueryNode;
+
 // todo: the matches, extract_timestamp, and sort methods and functions was
 // created as a result of the FileTransport, there is a high chance it wont be used else where
 // this is as observed when creating the `MongoDDTransport`
@@ -19,6 +21,7 @@ pub struct LogQuery {
     pub levels: Vec<String>,
     pub fields: Vec<String>,
     pub search_term: Option<Regex>,
+    pub filter: Option<QueryNode>,
 }
 
 #[derive(Debug, Clone)]
@@ -144,6 +147,7 @@ impl LogQuery {
             fields: Vec::new(),
             levels: Vec::new(),
             search_term: None,
+            filter: None,
         }
     }
 
@@ -203,7 +207,14 @@ impl LogQuery {
         self
     }
 
-    fn extract_timestamp(entry: &LogInfo) -> Option<DateTime<Utc>> {
+    /// Only matches entries the query DSL node accepts. Paths are resolved
+    /// against `{"level", "message", "meta"}`, e.g. `meta.user.age`.
+    pub fn filter<T: Into<QueryNode>>(mut self, filter: T) -> Self {
+        self.filter = Some(filter.into());
+        self
+    }
+
+    pub(crate) fn extract_timestamp(entry: &LogInfo) -> Option<DateTime<Utc>> {
         entry.meta.get("timestamp").and_then(|value| match value {
             Value::String(ts_str) => parse(&ts_str).ok().map(|dt| dt.with_timezone(&Utc)),
             _ => None,
@@ -250,6 +261,12 @@ impl LogQuery {
             }
         }
 
+        if let Some(ref filter) = self.filter {
+            if !filter.evaluate(&entry.to_value()) {
+                return false;
+            }
+        }
+
         // Check fields in meta data
         /*for field in &self.fields {
             // Check if the field exists in either meta or as a top-level attribute
@@ -300,6 +317,17 @@ impl LogQuery {
 
         projected
     }
+
+    /// Keeps only the meta fields named in `fields` on each entry, when any
+    /// are named
+    pub(crate) fn retain_fields(&self, entries: &mut [LogInfo]) {
+        if self.fields.is_empty() {
+            return;
+        }
+        for entry in entries {
+            entry.meta.retain(|key, _| self.fields.contains(key));
+        }
+    }
 }
 
 #[cfg(test)]
@@ -464,4 +492,22 @@ mod test {
             Utc.with_ymd_and_hms(2020, 1, 2, 0, 0, 0).unwrap()
         );
     }
+
+    #[test]
+    fn test_log_query_filter() {
+        use serde_json::json;
+
+        let query = LogQuery::new()
+            .from("2024-04-10T00:00:00Z")
+            .until("2024-04-11T00:00:00Z")
+            .filter(json!({ "meta.user.age": { "$gt": 18 } }));
+
+        let adult = LogInfo::new("info", "Signup")
+            .with_meta("user", json!({ "age": 30 }))
+            .with_meta("timestamp", "2024-04-10T12:00:00Z");
+        let minor = adult.clone().with_meta("user", json!({ "age": 12 }));
+
+        assert!(query.matches(&adult));
+        assert!(!query.matches(&minor));
+    }
 }
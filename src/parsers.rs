@@ -0,0 +1,497 @@
+//! Decoders for common third-party log formats.
+//!
+//! Each parser is an `InputDecoder`, so it can be used with
+//! `TransportWriter::with_decoder` and `TailConfig::with_decoder`. Meta fields
+//! are typed: numbers stay numbers and timestamps are normalized to RFC 3339
+//! in a `timestamp` field, which `LogQuery` sorts and filters on. Levels use
+//! the syslog names (`emerg` to `debug`).
+//!
+//! Wrap a parser in `WithErrorRecords` to keep lines it cannot parse as
+//! records carrying the `parse_error`, instead of plain messages.
+
+use crate::decoder::{parse_logfmt, InputDecoder};
+use chrono::{DateTime, Datelike, NaiveDateTime, TimeZone, Utc};
+use logform::LogInfo;
+use regex::Regex;
+use serde_json::{Map, Number, Value};
+use std::sync::OnceLock;
+
+pub(crate) const SEVERITIES: [&str; 8] = [
+    "emerg", "alert", "crit", "error", "warning", "notice", "info", "debug",
+];
+
+/// Parses nginx/Apache access logs in the combined or common log format
+///
+/// `127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /a.gif HTTP/1.0" 200
+/// 2326 "http://example.com/" "Mozilla/4.08"` becomes a record whose message is
+/// the request line, with `remote_addr`, `remote_user`, `timestamp`, `method`,
+/// `path`, `protocol`, `status`, `bytes`, `referer` and `user_agent` meta.
+/// Fields logged as `-` are left out. Responses with a 5xx status are logged
+/// as `error` and 4xx as `warning`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AccessLogDecoder;
+
+impl InputDecoder for AccessLogDecoder {
+    fn decode(&self, input: &str, default_level: &str) -> Result<LogInfo, String> {
+        static PATTERN: OnceLock<Regex> = OnceLock::new();
+        let pattern = PATTERN.get_or_init(|| {
+            Regex::new(
+                r#"^(\S+) (\S+) (\S+) \[([^\]]+)\] "((?:[^"\\]|\\.)*)" (\d{3}) (\d+|-)(?: "((?:[^"\\]|\\.)*)" "((?:[^"\\]|\\.)*)")?\s*$"#,
+            )
+            .unwrap()
+        });
+        let captures = pattern
+            .captures(input)
+            .ok_or_else(|| "Not an access log line".to_string())?;
+        let text = |i: usize| {
+            captures
+                .get(i)
+                .map(|m| m.as_str())
+                .filter(|value| *value != "-")
+        };
+
+        let status: u16 = captures[6]
+            .parse()
+            .map_err(|e| format!("Invalid access log status: {}", e))?;
+        let level = match status {
+            500.. => "error",
+            400..=499 => "warning",
+            _ => default_level,
+        };
+        let request = captures[5].to_string();
+        let mut info = LogInfo::new(level, request.as_str()).with_meta("status", status);
+
+        if let Some(addr) = text(1) {
+            info = info.with_meta("remote_addr", addr);
+        }
+        if let Some(user) = text(3) {
+            info = info.with_meta("remote_user", user);
+        }
+        let timestamp = DateTime::parse_from_str(&captures[4], "%d/%b/%Y:%H:%M:%S %z")
+            .map_err(|e| format!("Invalid access log timestamp: {}", e))?;
+        info = info.with_meta("timestamp", timestamp.to_rfc3339());
+
+        let mut parts = request.splitn(3, ' ');
+        if let (Some(method), Some(path)) = (parts.next(), parts.next()) {
+            info = info.with_meta("method", method).with_meta("path", path);
+            if let Some(protocol) = parts.next() {
+                info = info.with_meta("protocol", protocol);
+            }
+        }
+        if let Some(bytes) = text(7) {
+            let bytes: u64 = bytes
+                .parse()
+                .map_err(|e| format!("Invalid access log byte count: {}", e))?;
+            info = info.with_meta("bytes", bytes);
+        }
+        if let Some(referer) = text(8) {
+            info = info.with_meta("referer", referer);
+        }
+        if let Some(user_agent) = text(9) {
+            info = info.with_meta("user_agent", user_agent);
+        }
+        Ok(info)
+    }
+}
+
+/// Parses syslog lines in RFC 5424 or RFC 3164 (BSD) format
+///
+/// The level comes from the severity in `<PRI>`, and `facility`, `hostname`,
+/// `app_name`, `procid`, `msgid` and `timestamp` go into meta. RFC 5424
+/// structured data becomes a `structured_data` object keyed by SD-ID. RFC 3164
+/// timestamps carry no year or zone; they are read as UTC in the most recent
+/// year that does not put them in the future. The `<PRI>` of an RFC 3164 line
+/// may be missing, as in files written by the syslog daemon.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SyslogDecoder;
+
+impl InputDecoder for SyslogDecoder {
+    fn decode(&self, input: &str, default_level: &str) -> Result<LogInfo, String> {
+        let (priority, rest) = match input.strip_prefix('<') {
+            Some(rest) => {
+                let end = rest
+                    .find('>')
+                    .filter(|end| (1..=3).contains(end))
+                    .ok_or_else(|| "Invalid syslog priority".to_string())?;
+                let priority: u8 = rest[..end]
+                    .parse()
+                    .ok()
+                    .filter(|priority| *priority < 192)
+                    .ok_or_else(|| "Invalid syslog priority".to_string())?;
+                (Some(priority), &rest[end + 1..])
+            }
+            None => (None, input),
+        };
+
+        let mut info = match rest.strip_prefix("1 ") {
+            Some(rest) if priority.is_some() => parse_rfc5424(rest)?,
+            _ => parse_rfc3164(rest, Utc::now())?,
+        };
+        info.level = match priority {
+            Some(priority) => {
+                info = info.with_meta("facility", priority >> 3);
+                SEVERITIES[usize::from(priority & 7)].to_string()
+            }
+            None => default_level.to_string(),
+        };
+        Ok(info)
+    }
+}
+
+fn parse_rfc5424(input: &str) -> Result<LogInfo, String> {
+    let mut fields = input.splitn(5, ' ');
+    let mut next = |name: &str| {
+        fields
+            .next()
+            .filter(|field| !field.is_empty())
+            .ok_or_else(|| format!("Missing syslog {}", name))
+    };
+    let timestamp = next("timestamp")?;
+    let header = [
+        ("hostname", next("hostname")?),
+        ("app_name", next("app name")?),
+        ("procid", next("procid")?),
+    ];
+    let rest = next("msgid")?;
+    let (msgid, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+
+    let (structured_data, message) = parse_structured_data(rest)?;
+    let message = message.strip_prefix('\u{feff}').unwrap_or(message);
+    let mut info = LogInfo::new("", message);
+
+    if timestamp != "-" {
+        let timestamp = DateTime::parse_from_rfc3339(timestamp)
+            .map_err(|e| format!("Invalid syslog timestamp: {}", e))?;
+        info = info.with_meta("timestamp", timestamp.to_rfc3339());
+    }
+    for (key, value) in header.into_iter().chain([("msgid", msgid)]) {
+        if value != "-" {
+            info = info.with_meta(key, value);
+        }
+    }
+    if let Some(structured_data) = structured_data {
+        info = info.with_meta("structured_data", structured_data);
+    }
+    Ok(info)
+}
+
+/// Splits RFC 5424 structured data from the message that follows it
+fn parse_structured_data(input: &str) -> Result<(Option<Value>, &str), String> {
+    if let Some(rest) = input.strip_prefix('-') {
+        return Ok((None, rest.strip_prefix(' ').unwrap_or(rest)));
+    }
+
+    let mut elements = Map::new();
+    let mut rest = input;
+    while let Some(element) = rest.strip_prefix('[') {
+        let id_end = element
+            .find([' ', ']'])
+            .ok_or_else(|| "Unterminated structured data".to_string())?;
+        let id = &element[..id_end];
+        let mut params = Map::new();
+        let mut chars = element[id_end..].char_indices();
+
+        let end = loop {
+            match chars.next() {
+                Some((i, ']')) => break id_end + i + 1,
+                Some((_, ' ')) => {}
+                Some((start, _)) => {
+                    let name_end = element[id_end + start..]
+                        .find("=\"")
+                        .ok_or_else(|| format!("Invalid structured data in [{}]", id))?;
+                    let name = &element[id_end + start..id_end + start + name_end];
+                    for _ in 0..name_end + 1 {
+                        chars.next();
+                    }
+                    let mut value = String::new();
+                    loop {
+                        match chars.next() {
+                            Some((_, '"')) => break,
+                            Some((_, '\\')) => match chars.next() {
+                                Some((_, c @ ('"' | '\\' | ']'))) => value.push(c),
+                                Some((_, c)) => {
+                                    value.push('\\');
+                                    value.push(c);
+                                }
+                                None => return Err("Unterminated structured data".to_string()),
+                            },
+                            Some((_, c)) => value.push(c),
+                            None => return Err("Unterminated structured data".to_string()),
+                        }
+                    }
+                    params.insert(name.to_string(), Value::String(value));
+                }
+                None => return Err("Unterminated structured data".to_string()),
+            }
+        };
+        elements.insert(id.to_string(), Value::Object(params));
+        rest = &element[end..];
+    }
+    if elements.is_empty() {
+        return Err("Invalid structured data".to_string());
+    }
+    Ok((
+        Some(Value::Object(elements)),
+        rest.strip_prefix(' ').unwrap_or(rest),
+    ))
+}
+
+fn parse_rfc3164(input: &str, now: DateTime<Utc>) -> Result<LogInfo, String> {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let pattern = PATTERN.get_or_init(|| {
+        Regex::new(
+            r"^([A-Z][a-z]{2} [ \d]\d \d{2}:\d{2}:\d{2}) (\S+) ([^\s:\[]+)(?:\[([^\]]+)\])?: ?(.*)$",
+        )
+        .unwrap()
+    });
+    let captures = pattern
+        .captures(input)
+        .ok_or_else(|| "Not a syslog line".to_string())?;
+
+    let timestamp = rfc3164_timestamp(&captures[1], now)?;
+    let mut info = LogInfo::new("", &captures[5])
+        .with_meta("timestamp", timestamp.to_rfc3339())
+        .with_meta("hostname", &captures[2])
+        .with_meta("app_name", &captures[3]);
+    if let Some(procid) = captures.get(4) {
+        info = info.with_meta("procid", procid.as_str());
+    }
+    Ok(info)
+}
+
+fn rfc3164_timestamp(text: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    let parse = |year: i32| {
+        NaiveDateTime::parse_from_str(&format!("{} {}", year, text), "%Y %b %e %H:%M:%S")
+            .map(|naive| Utc.from_utc_datetime(&naive))
+            .map_err(|e| format!("Invalid syslog timestamp: {}", e))
+    };
+    let timestamp = parse(now.year())?;
+    // A December line read in January belongs to the previous year
+    if timestamp > now + chrono::Duration::days(1) {
+        return parse(now.year() - 1);
+    }
+    Ok(timestamp)
+}
+
+/// Parses systemd-style `KEY=value` lines such as
+/// `PRIORITY=3 MESSAGE="disk full" _PID=812 _SYSTEMD_UNIT=app.service`
+///
+/// `MESSAGE` (or `message`/`msg`) is the message and the numeric syslog
+/// `PRIORITY` (or a `level` name) the level. Other keys go into meta with
+/// integers, floats and booleans typed, and the microsecond
+/// `__REALTIME_TIMESTAMP` or `_SOURCE_REALTIME_TIMESTAMP` becomes `timestamp`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KeyValueDecoder;
+
+impl InputDecoder for KeyValueDecoder {
+    fn decode(&self, input: &str, default_level: &str) -> Result<LogInfo, String> {
+        let mut info = LogInfo::new(default_level, "");
+        for (key, value) in parse_logfmt(input)? {
+            match key.as_str() {
+                "MESSAGE" | "message" | "msg" => info.message = value,
+                "PRIORITY" => {
+                    let severity = value
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|severity| SEVERITIES.get(severity))
+                        .ok_or_else(|| format!("Invalid PRIORITY '{}'", value))?;
+                    info.level = severity.to_string();
+                }
+                "level" => info.level = value,
+                "__REALTIME_TIMESTAMP" | "_SOURCE_REALTIME_TIMESTAMP" => {
+                    let timestamp = value
+                        .parse::<i64>()
+                        .ok()
+                        .and_then(DateTime::from_timestamp_micros)
+                        .ok_or_else(|| format!("Invalid {} '{}'", key, value))?;
+                    // The source's own time wins over the journal's
+                    if key.starts_with("_SOURCE") || !info.meta.contains_key("timestamp") {
+                        info = info.with_meta("timestamp", timestamp.to_rfc3339());
+                    }
+                }
+                _ => {
+                    info.meta.insert(key, typed(value));
+                }
+            }
+        }
+        Ok(info)
+    }
+}
+
+fn typed(value: String) -> Value {
+    if let Ok(number) = value.parse::<i64>() {
+        return Value::Number(number.into());
+    }
+    if let Some(number) = value.parse::<f64>().ok().and_then(Number::from_f64) {
+        if value.contains(|c: char| c.is_ascii_digit()) && !value.contains(char::is_alphabetic) {
+            return Value::Number(number);
+        }
+    }
+    match value.as_str() {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => Value::String(value),
+    }
+}
+
+/// Keeps lines the wrapped decoder rejects as records at the default level,
+/// with the reason in a `parse_error` meta field
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WithErrorRecords<D> {
+    decoder: D,
+}
+
+impl<D: InputDecoder> WithErrorRecords<D> {
+    pub fn new(decoder: D) -> Self {
+        Self { decoder }
+    }
+}
+
+impl<D: InputDecoder> InputDecoder for WithErrorRecords<D> {
+    fn decode(&self, input: &str, default_level: &str) -> Result<LogInfo, String> {
+        Ok(self
+            .decoder
+            .decode(input, default_level)
+            .unwrap_or_else(|e| LogInfo::new(default_level, input).with_meta("parse_error", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_access_log() {
+        let line = r#"127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /apache_pb.gif HTTP/1.0" 404 2326 "http://www.example.com/start.html" "Mozilla/4.08 [en] (Win98; I ;Nav)""#;
+        let info = AccessLogDecoder.decode(line, "info").unwrap();
+        assert_eq!(info.level, "warning");
+        assert_eq!(info.message, "GET /apache_pb.gif HTTP/1.0");
+        assert_eq!(info.meta.get("status"), Some(&json!(404)));
+        assert_eq!(info.meta.get("bytes"), Some(&json!(2326)));
+        assert_eq!(info.meta.get("method"), Some(&json!("GET")));
+        assert_eq!(info.meta.get("remote_user"), Some(&json!("frank")));
+        assert_eq!(
+            info.meta.get("timestamp"),
+            Some(&json!("2000-10-10T13:55:36-07:00"))
+        );
+        assert_eq!(
+            info.meta.get("user_agent"),
+            Some(&json!("Mozilla/4.08 [en] (Win98; I ;Nav)"))
+        );
+
+        // Common log format, without referer and user agent
+        let info = AccessLogDecoder
+            .decode(
+                r#"10.0.0.1 - - [01/Jan/2024:00:00:00 +0000] "POST /api HTTP/1.1" 200 -"#,
+                "info",
+            )
+            .unwrap();
+        assert_eq!(info.level, "info");
+        assert!(!info.meta.contains_key("bytes"));
+        assert!(!info.meta.contains_key("remote_user"));
+
+        assert!(AccessLogDecoder
+            .decode("not an access log", "info")
+            .is_err());
+        // A byte count too large for a u64 fails instead of panicking
+        assert!(AccessLogDecoder
+            .decode(
+                r#"10.0.0.1 - - [01/Jan/2024:00:00:00 +0000] "GET / HTTP/1.1" 200 99999999999999999999"#,
+                "info",
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_syslog_5424() {
+        let line = r#"<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 [exampleSDID@32473 iut="3" eventSource="App\"lication"][meta sequenceId="1"] An application event"#;
+        let info = SyslogDecoder.decode(line, "info").unwrap();
+        assert_eq!(info.level, "notice");
+        assert_eq!(info.message, "An application event");
+        assert_eq!(info.meta.get("facility"), Some(&json!(20)));
+        assert_eq!(
+            info.meta.get("hostname"),
+            Some(&json!("mymachine.example.com"))
+        );
+        assert_eq!(info.meta.get("app_name"), Some(&json!("evntslog")));
+        assert_eq!(info.meta.get("msgid"), Some(&json!("ID47")));
+        assert!(!info.meta.contains_key("procid"));
+        assert_eq!(
+            info.meta.get("timestamp"),
+            Some(&json!("2003-10-11T22:14:15.003+00:00"))
+        );
+        assert_eq!(
+            info.meta.get("structured_data"),
+            Some(&json!({
+                "exampleSDID@32473": {"iut": "3", "eventSource": "App\"lication"},
+                "meta": {"sequenceId": "1"}
+            }))
+        );
+
+        let info = SyslogDecoder
+            .decode("<11>1 - host app 42 - - \u{feff}failed", "info")
+            .unwrap();
+        assert_eq!(info.level, "error");
+        assert_eq!(info.message, "failed");
+        assert_eq!(info.meta.get("procid"), Some(&json!("42")));
+    }
+
+    #[test]
+    fn test_syslog_3164() {
+        let info = SyslogDecoder
+            .decode(
+                "<34>Oct 11 22:14:15 mymachine su[230]: 'su root' failed",
+                "info",
+            )
+            .unwrap();
+        assert_eq!(info.level, "crit");
+        assert_eq!(info.message, "'su root' failed");
+        assert_eq!(info.meta.get("app_name"), Some(&json!("su")));
+        assert_eq!(info.meta.get("procid"), Some(&json!("230")));
+
+        // Lines from a syslog file have no priority
+        let info = SyslogDecoder
+            .decode("Jan  2 03:04:05 host cron: job done", "notice")
+            .unwrap();
+        assert_eq!(info.level, "notice");
+        assert!(!info.meta.contains_key("facility"));
+
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(
+            rfc3164_timestamp("Dec 31 23:59:59", now)
+                .unwrap()
+                .to_rfc3339(),
+            "2023-12-31T23:59:59+00:00"
+        );
+        assert!(SyslogDecoder.decode("<999>bad", "info").is_err());
+    }
+
+    #[test]
+    fn test_key_value_and_error_records() {
+        let info = KeyValueDecoder
+            .decode(
+                r#"PRIORITY=3 MESSAGE="disk full" _PID=812 LOAD=0.5 CACHED=true __REALTIME_TIMESTAMP=1700000000000000 _SYSTEMD_UNIT=app.service"#,
+                "info",
+            )
+            .unwrap();
+        assert_eq!(info.level, "error");
+        assert_eq!(info.message, "disk full");
+        assert_eq!(info.meta.get("_PID"), Some(&json!(812)));
+        assert_eq!(info.meta.get("LOAD"), Some(&json!(0.5)));
+        assert_eq!(info.meta.get("CACHED"), Some(&json!(true)));
+        assert_eq!(info.meta.get("_SYSTEMD_UNIT"), Some(&json!("app.service")));
+        assert_eq!(
+            info.meta.get("timestamp"),
+            Some(&json!("2023-11-14T22:13:20+00:00"))
+        );
+
+        let decoder = WithErrorRecords::new(AccessLogDecoder);
+        let info = decoder.decode("garbage", "info").unwrap();
+        assert_eq!(info.message, "garbage");
+        assert_eq!(
+            info.meta.get("parse_error"),
+            Some(&json!("Not an access log line"))
+        );
+    }
+}
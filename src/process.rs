@@ -0,0 +1,353 @@
+//! Capture of a child process's output into a Transport.
+//!
+//! `ChildCapture::spawn` starts a `Command` with piped stdout and stderr and
+//! reads each on its own thread, logging one record per line through a
+//! line-framed `TransportWriter`. Every record carries `pid`, `command` and
+//! `stream` meta fields. Once both streams are closed and the process has
+//! exited, its exit status is logged as a final record.
+
+use crate::{transport_adapters::AsTransportWriter, LogInfo, Transport};
+use std::{
+    io::{self, Read, Write},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+};
+
+#[cfg(not(unix))]
+use std::time::Duration;
+
+/// How the output of a child process is logged
+#[derive(Debug, Clone)]
+pub struct ChildConfig {
+    /// Level of records read from stdout
+    pub stdout_level: String,
+    /// Level of records read from stderr
+    pub stderr_level: String,
+    /// Level of the exit record when the process succeeds
+    pub exit_level: String,
+    /// Level of the exit record when the process fails or is killed
+    pub failure_level: String,
+    /// Lines longer than this many bytes are split into several records
+    pub max_line_length: usize,
+}
+
+impl Default for ChildConfig {
+    fn default() -> Self {
+        Self {
+            stdout_level: "info".to_string(),
+            stderr_level: "warn".to_string(),
+            exit_level: "info".to_string(),
+            failure_level: "error".to_string(),
+            max_line_length: 64 * 1024,
+        }
+    }
+}
+
+/// A running child process whose output is being logged
+///
+/// Dropping it without calling `wait` leaves the process running; its output
+/// and exit status are still logged when it finishes.
+pub struct ChildCapture {
+    pid: u32,
+    child: Arc<Mutex<Child>>,
+    supervisor: Option<JoinHandle<Result<ExitStatus, String>>>,
+}
+
+impl ChildCapture {
+    /// Spawns `command` with its stdout and stderr logged on `transport`
+    pub fn spawn<T: Transport + 'static>(
+        command: &mut Command,
+        transport: Arc<T>,
+        config: ChildConfig,
+    ) -> Result<Self, String> {
+        let description = describe(command);
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to spawn '{}': {}", description, e))?;
+        let pid = child.id();
+
+        let context = StreamContext {
+            pid,
+            command: description.clone(),
+            max_line_length: config.max_line_length,
+        };
+        let mut readers = Vec::new();
+        let mut started = Ok(());
+        if let Some(stdout) = child.stdout.take() {
+            started = context
+                .read(
+                    stdout,
+                    "stdout",
+                    config.stdout_level.clone(),
+                    transport.clone(),
+                )
+                .map(|reader| readers.push(reader));
+        }
+        if let (Ok(()), Some(stderr)) = (&started, child.stderr.take()) {
+            started = context
+                .read(
+                    stderr,
+                    "stderr",
+                    config.stderr_level.clone(),
+                    transport.clone(),
+                )
+                .map(|reader| readers.push(reader));
+        }
+        if let Err(e) = started {
+            // Not left running unsupervised; a reader already started sees
+            // its pipe close and finishes
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(e);
+        }
+
+        let child = Arc::new(Mutex::new(child));
+        let waited = child.clone();
+        let supervisor = thread::Builder::new()
+            .name(format!("child-{}", pid))
+            .spawn(move || {
+                for reader in readers {
+                    let _ = reader.join();
+                }
+                let status = wait_for(&waited)
+                    .map_err(|e| format!("Failed to wait for '{}': {}", description, e))?;
+                transport.log(exit_record(&status, pid, &description, &config));
+                Ok(status)
+            });
+        let supervisor = match supervisor {
+            Ok(supervisor) => supervisor,
+            Err(e) => {
+                let mut child = child.lock().unwrap();
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("Failed to spawn supervisor thread: {}", e));
+            }
+        };
+
+        Ok(Self {
+            pid,
+            child,
+            supervisor: Some(supervisor),
+        })
+    }
+
+    /// The operating system's id for the process
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Kills the process; its exit is still logged
+    pub fn kill(&self) -> Result<(), String> {
+        self.child
+            .lock()
+            .unwrap()
+            .kill()
+            .map_err(|e| format!("Failed to kill process {}: {}", self.pid, e))
+    }
+
+    /// Waits for the process to exit and for all of its output and the exit
+    /// record to be logged
+    pub fn wait(mut self) -> Result<ExitStatus, String> {
+        self.supervisor
+            .take()
+            .unwrap()
+            .join()
+            .map_err(|_| "Child capture thread panicked".to_string())?
+    }
+}
+
+struct StreamContext {
+    pid: u32,
+    command: String,
+    max_line_length: usize,
+}
+
+impl StreamContext {
+    fn read<T: Transport + 'static>(
+        &self,
+        mut stream: impl Read + Send + 'static,
+        name: &'static str,
+        level: String,
+        transport: Arc<T>,
+    ) -> Result<JoinHandle<()>, String> {
+        let pid = self.pid;
+        let command = self.command.clone();
+        let max_line_length = self.max_line_length;
+
+        thread::Builder::new()
+            .name(format!("child-{}-{}", pid, name))
+            .spawn(move || {
+                let mut writer = transport
+                    .as_writer()
+                    .with_line_framing(max_line_length)
+                    .with_decoder(move |line: &str, level: &str| {
+                        Ok(LogInfo::new(level, line)
+                            .with_meta("pid", pid)
+                            .with_meta("command", command.as_str())
+                            .with_meta("stream", name))
+                    })
+                    .with_default_level(level);
+
+                let mut buf = [0; 8192];
+                loop {
+                    match stream.read(&mut buf) {
+                        Ok(0) => break,
+                        Ok(read) => {
+                            let _ = writer.write_all(&buf[..read]);
+                        }
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(_) => break,
+                    }
+                }
+                let _ = writer.flush();
+            })
+            .map_err(|e| format!("Failed to spawn {} reader thread: {}", name, e))
+    }
+}
+
+/// Blocks until the process exits and collects its status. The process is
+/// left unreaped while waiting, so its pid cannot be reused by another
+/// process before `kill` stops using it; the lock is only taken to reap it.
+#[cfg(unix)]
+fn wait_for(child: &Mutex<Child>) -> io::Result<ExitStatus> {
+    let pid = child.lock().unwrap().id();
+    loop {
+        // SAFETY: an all-zero siginfo_t is valid, and waitid only writes to it
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let result = unsafe {
+            libc::waitid(
+                libc::P_PID,
+                pid as libc::id_t,
+                &mut info,
+                libc::WEXITED | libc::WNOWAIT,
+            )
+        };
+        if result == 0 {
+            break;
+        }
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+    child.lock().unwrap().wait()
+}
+
+/// Polls for the process to exit, releasing the lock in between so `kill`
+/// can take it
+#[cfg(not(unix))]
+fn wait_for(child: &Mutex<Child>) -> io::Result<ExitStatus> {
+    loop {
+        if let Some(status) = child.lock().unwrap().try_wait()? {
+            return Ok(status);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn describe(command: &Command) -> String {
+    let mut description = command.get_program().to_string_lossy().into_owned();
+    for arg in command.get_args() {
+        description.push(' ');
+        description.push_str(&arg.to_string_lossy());
+    }
+    description
+}
+
+fn exit_record(status: &ExitStatus, pid: u32, command: &str, config: &ChildConfig) -> LogInfo {
+    let level = if status.success() {
+        &config.exit_level
+    } else {
+        &config.failure_level
+    };
+    let info = LogInfo::new(level.as_str(), format!("Process exited with {}", status))
+        .with_meta("pid", pid)
+        .with_meta("command", command)
+        .with_meta("stream", "exit")
+        .with_meta("exit_code", status.code());
+
+    #[cfg(unix)]
+    let info = {
+        use std::os::unix::process::ExitStatusExt;
+        match status.signal() {
+            Some(signal) => info.with_meta("signal", signal),
+            None => info,
+        }
+    };
+    info
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    struct MockTransport {
+        records: Mutex<Vec<LogInfo>>,
+    }
+
+    impl Transport for MockTransport {
+        fn log(&self, info: LogInfo) {
+            self.records.lock().unwrap().push(info);
+        }
+    }
+
+    #[test]
+    fn test_child_output_and_exit() {
+        let transport = Arc::new(MockTransport {
+            records: Mutex::new(Vec::new()),
+        });
+        let capture = ChildCapture::spawn(
+            Command::new("sh").args(["-c", "echo out; echo err >&2; printf tail; exit 3"]),
+            transport.clone(),
+            ChildConfig::default(),
+        )
+        .unwrap();
+        let pid = capture.pid();
+        let status = capture.wait().unwrap();
+        assert_eq!(status.code(), Some(3));
+
+        let records = transport.records.lock().unwrap();
+        assert_eq!(records.len(), 4);
+        let find = |message: &str| records.iter().find(|info| info.message == message).unwrap();
+
+        let out = find("out");
+        assert_eq!(out.level, "info");
+        assert_eq!(out.meta.get("stream"), Some(&json!("stdout")));
+        assert_eq!(out.meta.get("pid"), Some(&json!(pid)));
+        assert_eq!(
+            out.meta.get("command"),
+            Some(&json!("sh -c echo out; echo err >&2; printf tail; exit 3"))
+        );
+        assert_eq!(find("err").level, "warn");
+        assert_eq!(find("tail").meta.get("stream"), Some(&json!("stdout")));
+
+        // The exit status comes last
+        let exit = records.last().unwrap();
+        assert_eq!(exit.level, "error");
+        assert_eq!(exit.meta.get("stream"), Some(&json!("exit")));
+        assert_eq!(exit.meta.get("exit_code"), Some(&json!(3)));
+    }
+
+    #[test]
+    fn test_kill() {
+        let transport = Arc::new(MockTransport {
+            records: Mutex::new(Vec::new()),
+        });
+        let capture = ChildCapture::spawn(
+            Command::new("sleep").arg("30"),
+            transport.clone(),
+            ChildConfig::default(),
+        )
+        .unwrap();
+        capture.kill().unwrap();
+        assert!(!capture.wait().unwrap().success());
+
+        let records = transport.records.lock().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].meta.get("signal"), Some(&json!(9)));
+    }
+}
@@ -0,0 +1,210 @@
+//! Completion handles for records and flushes handed to a background worker.
+//!
+//! `log_with_receipt` returns a `Receipt` that completes once the record has
+//! been delivered and the inner transport flushed, and `flush_async` returns a
+//! `FlushHandle` that completes with the flush result. Both can be polled,
+//! waited on with a timeout, or awaited.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+struct ReceiptState {
+    result: Option<Result<(), String>>,
+    waker: Option<Waker>,
+}
+
+struct ReceiptShared {
+    state: Mutex<ReceiptState>,
+    done: Condvar,
+}
+
+impl ReceiptShared {
+    fn complete(&self, result: Result<(), String>) {
+        let mut state = self.state.lock().unwrap();
+        if state.result.is_some() {
+            return;
+        }
+        state.result = Some(result);
+        let waker = state.waker.take();
+        drop(state);
+
+        self.done.notify_all();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Completes once a record, or a flush, has been handled by the worker
+pub struct Receipt {
+    shared: Arc<ReceiptShared>,
+}
+
+/// Completes with the result of a flush requested with `flush_async`
+pub type FlushHandle = Receipt;
+
+impl Receipt {
+    /// Creates a receipt that is already complete
+    pub(crate) fn ready(result: Result<(), String>) -> Self {
+        let (sender, receipt) = receipt();
+        sender.complete(result);
+        receipt
+    }
+
+    /// Returns the result if the receipt has completed, without blocking
+    pub fn try_wait(&self) -> Option<Result<(), String>> {
+        self.shared.state.lock().unwrap().result.clone()
+    }
+
+    /// Whether the receipt has completed
+    pub fn is_complete(&self) -> bool {
+        self.shared.state.lock().unwrap().result.is_some()
+    }
+
+    /// Blocks until the receipt completes
+    pub fn wait(self) -> Result<(), String> {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if let Some(result) = &state.result {
+                return result.clone();
+            }
+            state = self.shared.done.wait(state).unwrap();
+        }
+    }
+
+    /// Blocks until the receipt completes or the timeout elapses, returning
+    /// None on timeout
+    pub fn wait_timeout(&self, timeout: Duration) -> Option<Result<(), String>> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if let Some(result) = &state.result {
+                return Some(result.clone());
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return None;
+            }
+            state = self.shared.done.wait_timeout(state, remaining).unwrap().0;
+        }
+    }
+}
+
+impl Future for Receipt {
+    type Output = Result<(), String>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.shared.state.lock().unwrap();
+        match &state.result {
+            Some(result) => Poll::Ready(result.clone()),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// The worker's side of a receipt
+///
+/// Dropping it without completing fails the receipt, so a record lost to a
+/// shutdown or a closed queue never leaves its receipt pending forever.
+pub(crate) struct ReceiptSender {
+    shared: Arc<ReceiptShared>,
+}
+
+impl ReceiptSender {
+    pub(crate) fn complete(self, result: Result<(), String>) {
+        self.shared.complete(result);
+    }
+}
+
+impl Drop for ReceiptSender {
+    fn drop(&mut self) {
+        self.shared.complete(Err(
+            "Transport stopped before the request completed".to_string()
+        ));
+    }
+}
+
+impl std::fmt::Debug for ReceiptSender {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReceiptSender").finish_non_exhaustive()
+    }
+}
+
+/// Creates a connected receipt sender and receipt
+pub(crate) fn receipt() -> (ReceiptSender, Receipt) {
+    let shared = Arc::new(ReceiptShared {
+        state: Mutex::new(ReceiptState {
+            result: None,
+            waker: None,
+        }),
+        done: Condvar::new(),
+    });
+    (
+        ReceiptSender {
+            shared: shared.clone(),
+        },
+        Receipt { shared },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        task::Wake,
+        thread::{self, Thread},
+    };
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    #[test]
+    fn test_wait_and_poll() {
+        let (sender, receipt) = receipt();
+        assert_eq!(receipt.try_wait(), None);
+        assert_eq!(receipt.wait_timeout(Duration::from_millis(10)), None);
+
+        thread::spawn(move || sender.complete(Ok(())));
+        assert_eq!(receipt.wait_timeout(Duration::from_secs(5)), Some(Ok(())));
+        assert!(receipt.is_complete());
+        assert_eq!(receipt.wait(), Ok(()));
+    }
+
+    #[test]
+    fn test_dropped_sender_fails_receipt() {
+        let (sender, receipt) = receipt();
+        drop(sender);
+        assert!(receipt.wait().is_err());
+    }
+
+    #[test]
+    fn test_await() {
+        let (sender, mut receipt) = receipt();
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+
+        assert!(Pin::new(&mut receipt).poll(&mut cx).is_pending());
+        thread::spawn(move || sender.complete(Err("failed".to_string())));
+
+        loop {
+            if let Poll::Ready(result) = Pin::new(&mut receipt).poll(&mut cx) {
+                assert_eq!(result, Err("failed".to_string()));
+                break;
+            }
+            thread::park();
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! A preallocated lock-free bounded queue used by the high-throughput mode of
+//! `ThreadedTransport`.
+//!
+//! This is Dmitry Vyukov's bounded MPMC queue: every slot carries a sequence
+//! number that tells producers and consumers whether it is free or filled, so
+//! neither side takes a lock. The slots are allocated once, at construction;
+//! values are moved in and out of them without allocating.
+
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    ops::Deref,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Keeps the producer and consumer positions on separate cache lines
+#[repr(align(64))]
+struct CachePadded<T>(T);
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+struct Slot<T> {
+    sequence: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+pub(crate) struct RingBuffer<T> {
+    slots: Box<[Slot<T>]>,
+    mask: usize,
+    enqueue_pos: CachePadded<AtomicUsize>,
+    dequeue_pos: CachePadded<AtomicUsize>,
+}
+
+// Values are moved in and out of slots under the sequence protocol, so the
+// buffer can be shared as long as the values themselves can be sent
+unsafe impl<T: Send> Send for RingBuffer<T> {}
+unsafe impl<T: Send> Sync for RingBuffer<T> {}
+
+impl<T> RingBuffer<T> {
+    /// Creates a buffer holding at least `capacity` values, rounded up to a
+    /// power of two
+    pub(crate) fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(2).next_power_of_two();
+        let slots = (0..capacity)
+            .map(|i| Slot {
+                sequence: AtomicUsize::new(i),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect();
+
+        Self {
+            slots,
+            mask: capacity - 1,
+            enqueue_pos: CachePadded(AtomicUsize::new(0)),
+            dequeue_pos: CachePadded(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Pushes a value, handing it back if the buffer is full
+    pub(crate) fn push(&self, value: T) -> Result<(), T> {
+        let mut pos = self.enqueue_pos.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let diff = (sequence as isize).wrapping_sub(pos as isize);
+
+            if diff == 0 {
+                match self.enqueue_pos.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: winning the CAS gives this producer exclusive
+                        // access to the free slot until the sequence is published
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.sequence.store(pos.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if diff < 0 {
+                return Err(value);
+            } else {
+                pos = self.enqueue_pos.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Pops the oldest value, if any
+    pub(crate) fn pop(&self) -> Option<T> {
+        let mut pos = self.dequeue_pos.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let diff = (sequence as isize).wrapping_sub(pos.wrapping_add(1) as isize);
+
+            if diff == 0 {
+                match self.dequeue_pos.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: the sequence says the slot was filled, and winning
+                        // the CAS gives this consumer exclusive access to it
+                        let value = unsafe { (*slot.value.get()).assume_init_read() };
+                        slot.sequence
+                            .store(pos.wrapping_add(self.mask + 1), Ordering::Release);
+                        return Some(value);
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if diff < 0 {
+                return None;
+            } else {
+                pos = self.dequeue_pos.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Moves up to `max` values into `out`, returning how many were moved
+    pub(crate) fn pop_batch(&self, out: &mut Vec<T>, max: usize) -> usize {
+        let mut moved = 0;
+        while moved < max {
+            match self.pop() {
+                Some(value) => {
+                    out.push(value);
+                    moved += 1;
+                }
+                None => break,
+            }
+        }
+        moved
+    }
+
+    /// Whether no value has been claimed for enqueueing beyond those dequeued
+    pub(crate) fn is_empty(&self) -> bool {
+        self.dequeue_pos.load(Ordering::SeqCst) == self.enqueue_pos.load(Ordering::SeqCst)
+    }
+}
+
+impl<T> Drop for RingBuffer<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn test_push_pop_in_order() {
+        // Rounded up to four slots
+        let ring = RingBuffer::new(3);
+
+        for i in 0..4 {
+            ring.push(i).unwrap();
+        }
+        assert_eq!(ring.push(4), Err(4));
+
+        let mut out = Vec::new();
+        assert_eq!(ring.pop_batch(&mut out, 3), 3);
+        assert_eq!(out, vec![0, 1, 2]);
+
+        ring.push(5).unwrap();
+        assert_eq!(ring.pop(), Some(3));
+        assert_eq!(ring.pop(), Some(5));
+        assert_eq!(ring.pop(), None);
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn test_multiple_producers() {
+        let ring = Arc::new(RingBuffer::new(64));
+        let producers: Vec<_> = (0..4)
+            .map(|p| {
+                let ring = ring.clone();
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        let mut value = p * 1000 + i;
+                        while let Err(rejected) = ring.push(value) {
+                            value = rejected;
+                            thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let mut received = Vec::new();
+        while received.len() < 4000 {
+            if ring.pop_batch(&mut received, 32) == 0 {
+                thread::yield_now();
+            }
+        }
+        for producer in producers {
+            producer.join().unwrap();
+        }
+
+        // Values from each producer arrive in the order they were pushed
+        for p in 0..4 {
+            let from_producer: Vec<_> = received.iter().filter(|v| **v / 1000 == p).collect();
+            assert!(from_producer.windows(2).all(|w| w[0] < w[1]));
+            assert_eq!(from_producer.len(), 1000);
+        }
+    }
+
+    #[test]
+    fn test_drop_releases_remaining_values() {
+        let value = Arc::new(());
+        let ring = RingBuffer::new(8);
+        ring.push(value.clone()).unwrap();
+        ring.push(value.clone()).unwrap();
+        assert_eq!(Arc::strong_count(&value), 3);
+
+        drop(ring);
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
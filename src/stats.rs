@@ -0,0 +1,453 @@
+//! Queue and throughput statistics for the threaded and batched wrappers.
+//!
+//! Both `ThreadedTransport` and `BatchedTransport` expose a `stats()` method that
+//! returns a `TransportStats` snapshot. Snapshots can be rendered in the
+//! Prometheus text exposition format with `to_prometheus` or `prometheus_text`.
+
+use std::{
+    fmt::Write as _,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Upper bounds of the batch size histogram, in records
+const BATCH_SIZE_BUCKETS: &[u64] = &[1, 2, 5, 10, 25, 50, 100, 250, 500, 1000];
+
+/// Upper bounds of the flush latency histogram, in microseconds
+const FLUSH_LATENCY_BUCKETS: &[u64] = &[
+    100, 500, 1_000, 5_000, 10_000, 50_000, 100_000, 500_000, 1_000_000, 5_000_000,
+];
+
+type MetricValue = fn(&TransportStats) -> Option<f64>;
+type HistogramField = fn(&TransportStats) -> &HistogramSnapshot;
+
+/// A point-in-time view of a wrapper's queue and delivery counters
+#[derive(Debug, Clone)]
+pub struct TransportStats {
+    /// Records accepted by `log` but not yet picked up by the worker
+    pub queued: usize,
+    /// Records picked up by the worker but not yet delivered to the inner transport
+    pub in_flight_batch: usize,
+    /// Records accepted by `log`
+    pub logged_total: u64,
+    /// Records delivered to the inner transport and successfully flushed
+    pub flushed_total: u64,
+    /// Records dropped instead of queued: the worker was gone, the ring buffer
+    /// was full under `OverflowPolicy::Drop`, or the spool could not store them
+    pub dropped_total: u64,
+    /// Records delivered to the inner transport whose flush failed
+    pub failed_total: u64,
+    /// Records larger than the configured batch byte limit on their own
+    pub oversized_total: u64,
+    /// When the inner transport last flushed successfully
+    pub last_flush: Option<SystemTime>,
+    /// Number of records per delivered batch
+    pub batch_size: HistogramSnapshot,
+    /// Time spent delivering and flushing a batch, in seconds
+    pub flush_latency: HistogramSnapshot,
+    /// Whether the background worker is still running
+    pub worker_alive: bool,
+    /// Batch size currently in effect, tuned when adaptive batching is enabled
+    pub batch_size_limit: Option<usize>,
+    /// Batch time currently in effect, tuned when adaptive batching is enabled
+    pub batch_time_limit: Option<Duration>,
+}
+
+/// A snapshot of a fixed-bucket histogram
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramSnapshot {
+    /// Upper bound and number of observations of each bucket, excluding `+Inf`
+    pub buckets: Vec<(f64, u64)>,
+    /// Sum of all observed values
+    pub sum: f64,
+    /// Total number of observations, including those above the last bound
+    pub count: u64,
+}
+
+impl TransportStats {
+    /// Renders these stats in the Prometheus text format, labelled with the
+    /// given transport name
+    pub fn to_prometheus(&self, transport: &str) -> String {
+        prometheus_text([(transport, self)])
+    }
+}
+
+/// Renders the stats of several transports in the Prometheus text format
+///
+/// Each metric family is written once with one sample per transport, labelled
+/// `transport="<name>"`.
+pub fn prometheus_text<'a, I>(entries: I) -> String
+where
+    I: IntoIterator<Item = (&'a str, &'a TransportStats)>,
+{
+    let entries: Vec<(String, &TransportStats)> = entries
+        .into_iter()
+        .map(|(name, stats)| (escape_label(name), stats))
+        .collect();
+    let mut out = String::new();
+
+    let gauges: [(&str, &str, &str, MetricValue); 11] = [
+        ("queued", "gauge", "Records waiting in the queue", |s| {
+            Some(s.queued as f64)
+        }),
+        (
+            "in_flight_batch",
+            "gauge",
+            "Records picked up but not yet delivered",
+            |s| Some(s.in_flight_batch as f64),
+        ),
+        ("logged_total", "counter", "Records accepted by log", |s| {
+            Some(s.logged_total as f64)
+        }),
+        (
+            "flushed_total",
+            "counter",
+            "Records delivered and flushed",
+            |s| Some(s.flushed_total as f64),
+        ),
+        (
+            "dropped_total",
+            "counter",
+            "Records dropped before queueing: worker gone, ring buffer full or spool write failed",
+            |s| Some(s.dropped_total as f64),
+        ),
+        (
+            "failed_total",
+            "counter",
+            "Records whose flush failed",
+            |s| Some(s.failed_total as f64),
+        ),
+        (
+            "oversized_total",
+            "counter",
+            "Records exceeding the batch byte limit",
+            |s| Some(s.oversized_total as f64),
+        ),
+        (
+            "last_flush_timestamp_seconds",
+            "gauge",
+            "Unix time of the last successful flush",
+            |s| {
+                Some(
+                    s.last_flush
+                        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                        .map_or(0.0, |d| d.as_secs_f64()),
+                )
+            },
+        ),
+        (
+            "worker_alive",
+            "gauge",
+            "Whether the worker is running",
+            |s| Some(if s.worker_alive { 1.0 } else { 0.0 }),
+        ),
+        (
+            "batch_size_limit",
+            "gauge",
+            "Batch size currently in effect",
+            |s| s.batch_size_limit.map(|size| size as f64),
+        ),
+        (
+            "batch_time_limit_seconds",
+            "gauge",
+            "Batch time currently in effect",
+            |s| s.batch_time_limit.map(|time| time.as_secs_f64()),
+        ),
+    ];
+
+    for (name, kind, help, value) in gauges {
+        let samples: Vec<(&String, f64)> = entries
+            .iter()
+            .filter_map(|(transport, stats)| value(stats).map(|v| (transport, v)))
+            .collect();
+        if samples.is_empty() {
+            continue;
+        }
+
+        let _ = writeln!(out, "# HELP winston_transport_{} {}", name, help);
+        let _ = writeln!(out, "# TYPE winston_transport_{} {}", name, kind);
+        for (transport, value) in samples {
+            let _ = writeln!(
+                out,
+                "winston_transport_{}{{transport=\"{}\"}} {}",
+                name, transport, value
+            );
+        }
+    }
+
+    let histograms: [(&str, &str, HistogramField); 2] = [
+        ("batch_size", "Records per delivered batch", |s| {
+            &s.batch_size
+        }),
+        (
+            "flush_latency_seconds",
+            "Time spent delivering and flushing a batch",
+            |s| &s.flush_latency,
+        ),
+    ];
+
+    for (name, help, histogram) in histograms {
+        let _ = writeln!(out, "# HELP winston_transport_{} {}", name, help);
+        let _ = writeln!(out, "# TYPE winston_transport_{} histogram", name);
+        for (transport, stats) in &entries {
+            let snapshot = histogram(stats);
+            let mut cumulative = 0;
+            for (bound, count) in &snapshot.buckets {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "winston_transport_{}_bucket{{transport=\"{}\",le=\"{}\"}} {}",
+                    name, transport, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "winston_transport_{}_bucket{{transport=\"{}\",le=\"+Inf\"}} {}",
+                name, transport, snapshot.count
+            );
+            let _ = writeln!(
+                out,
+                "winston_transport_{}_sum{{transport=\"{}\"}} {}",
+                name, transport, snapshot.sum
+            );
+            let _ = writeln!(
+                out,
+                "winston_transport_{}_count{{transport=\"{}\"}} {}",
+                name, transport, snapshot.count
+            );
+        }
+    }
+
+    out
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// A lock-free histogram over fixed integer bucket bounds
+struct Histogram {
+    bounds: &'static [u64],
+    // One slot per bound plus the overflow bucket
+    counts: Vec<AtomicU64>,
+    sum: AtomicU64,
+    // Divisor turning the recorded integer units into the reported unit
+    scale: f64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [u64], scale: f64) -> Self {
+        Self {
+            bounds,
+            counts: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum: AtomicU64::new(0),
+            scale,
+        }
+    }
+
+    fn observe(&self, value: u64) {
+        let index = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.bounds.len());
+        self.counts[index].fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        let counts: Vec<u64> = self
+            .counts
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .collect();
+        HistogramSnapshot {
+            buckets: self
+                .bounds
+                .iter()
+                .zip(&counts)
+                .map(|(bound, count)| (*bound as f64 / self.scale, *count))
+                .collect(),
+            sum: self.sum.load(Ordering::Relaxed) as f64 / self.scale,
+            count: counts.iter().sum(),
+        }
+    }
+}
+
+/// Counters shared between a wrapper and its background worker
+pub(crate) struct StatsRecorder {
+    enqueued: AtomicU64,
+    dequeued: AtomicU64,
+    in_flight: AtomicUsize,
+    flushed: AtomicU64,
+    dropped: AtomicU64,
+    failed: AtomicU64,
+    oversized: AtomicU64,
+    // Milliseconds since the Unix epoch, zero when no flush happened yet
+    last_flush_ms: AtomicU64,
+    batch_size: Histogram,
+    flush_latency: Histogram,
+    worker_alive: AtomicBool,
+    // Zero when the wrapper does not batch
+    batch_size_limit: AtomicUsize,
+    batch_time_limit_us: AtomicU64,
+}
+
+impl StatsRecorder {
+    pub(crate) fn new() -> Self {
+        Self {
+            enqueued: AtomicU64::new(0),
+            dequeued: AtomicU64::new(0),
+            in_flight: AtomicUsize::new(0),
+            flushed: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            oversized: AtomicU64::new(0),
+            last_flush_ms: AtomicU64::new(0),
+            batch_size: Histogram::new(BATCH_SIZE_BUCKETS, 1.0),
+            flush_latency: Histogram::new(FLUSH_LATENCY_BUCKETS, 1_000_000.0),
+            worker_alive: AtomicBool::new(true),
+            batch_size_limit: AtomicUsize::new(0),
+            batch_time_limit_us: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn record_enqueued(&self) {
+        self.enqueued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_oversized(&self) {
+        self.oversized.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that the worker took records off the queue
+    pub(crate) fn record_dequeued(&self, records: usize) {
+        self.dequeued.fetch_add(records as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_in_flight(&self, records: usize) {
+        self.in_flight.store(records, Ordering::Relaxed);
+    }
+
+    /// Records the outcome of delivering and flushing `records` records
+    pub(crate) fn record_flush(&self, records: usize, latency: Duration, success: bool) {
+        if records > 0 {
+            self.batch_size.observe(records as u64);
+        }
+        self.flush_latency.observe(latency.as_micros() as u64);
+
+        if success {
+            self.flushed.fetch_add(records as u64, Ordering::Relaxed);
+            let now_ms = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as u64);
+            self.last_flush_ms.store(now_ms, Ordering::Relaxed);
+        } else {
+            self.failed.fetch_add(records as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Publishes the batch size and time currently in effect
+    pub(crate) fn set_batch_limits(&self, size: usize, time: Duration) {
+        self.batch_size_limit.store(size, Ordering::Relaxed);
+        self.batch_time_limit_us
+            .store(time.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn worker_alive(&self) -> bool {
+        self.worker_alive.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_worker_alive(&self, alive: bool) {
+        self.worker_alive.store(alive, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> TransportStats {
+        let enqueued = self.enqueued.load(Ordering::Relaxed);
+        let dequeued = self.dequeued.load(Ordering::Relaxed);
+        let last_flush_ms = self.last_flush_ms.load(Ordering::Relaxed);
+
+        TransportStats {
+            queued: enqueued.saturating_sub(dequeued) as usize,
+            in_flight_batch: self.in_flight.load(Ordering::Relaxed),
+            logged_total: enqueued,
+            flushed_total: self.flushed.load(Ordering::Relaxed),
+            dropped_total: self.dropped.load(Ordering::Relaxed),
+            failed_total: self.failed.load(Ordering::Relaxed),
+            oversized_total: self.oversized.load(Ordering::Relaxed),
+            last_flush: (last_flush_ms > 0)
+                .then(|| UNIX_EPOCH + Duration::from_millis(last_flush_ms)),
+            batch_size: self.batch_size.snapshot(),
+            flush_latency: self.flush_latency.snapshot(),
+            worker_alive: self.worker_alive.load(Ordering::Relaxed),
+            batch_size_limit: match self.batch_size_limit.load(Ordering::Relaxed) {
+                0 => None,
+                size => Some(size),
+            },
+            batch_time_limit: match self.batch_size_limit.load(Ordering::Relaxed) {
+                0 => None,
+                _ => Some(Duration::from_micros(
+                    self.batch_time_limit_us.load(Ordering::Relaxed),
+                )),
+            },
+        }
+    }
+}
+
+/// Marks the worker as stopped when dropped, including on panic
+pub(crate) struct AliveGuard(pub(crate) std::sync::Arc<StatsRecorder>);
+
+impl Drop for AliveGuard {
+    fn drop(&mut self) {
+        self.0.set_worker_alive(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets() {
+        let histogram = Histogram::new(&[1, 10, 100], 1.0);
+        histogram.observe(1);
+        histogram.observe(5);
+        histogram.observe(50);
+        histogram.observe(500);
+
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.buckets, vec![(1.0, 1), (10.0, 1), (100.0, 1)]);
+        assert_eq!(snapshot.count, 4);
+        assert_eq!(snapshot.sum, 556.0);
+    }
+
+    #[test]
+    fn test_prometheus_text() {
+        let recorder = StatsRecorder::new();
+        recorder.record_enqueued();
+        recorder.record_enqueued();
+        recorder.record_dequeued(1);
+        recorder.record_flush(1, Duration::from_millis(2), true);
+
+        let text = recorder.snapshot().to_prometheus("app\"1");
+
+        assert!(text.contains("# TYPE winston_transport_queued gauge"));
+        assert!(text.contains("winston_transport_queued{transport=\"app\\\"1\"} 1"));
+        assert!(text.contains("winston_transport_logged_total{transport=\"app\\\"1\"} 2"));
+        assert!(text.contains("winston_transport_flushed_total{transport=\"app\\\"1\"} 1"));
+        assert!(text
+            .contains("winston_transport_batch_size_bucket{transport=\"app\\\"1\",le=\"+Inf\"} 1"));
+        assert!(text
+            .contains("winston_transport_flush_latency_seconds_sum{transport=\"app\\\"1\"} 0.002"));
+        assert!(text.contains("winston_transport_worker_alive{transport=\"app\\\"1\"} 1"));
+        // Only batching wrappers report batch limits
+        assert!(!text.contains("winston_transport_batch_size_limit"));
+    }
+}
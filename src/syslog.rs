@@ -0,0 +1,743 @@
+//! A transport that delivers records to a syslog daemon.
+//!
+//! Records are formatted as RFC 5424 or RFC 3164 messages, with the severity
+//! mapped from the level and a configured facility. They are sent over a Unix
+//! datagram socket such as `/dev/log`, over UDP, or over TCP framed with octet
+//! counting (RFC 6587). A failed send drops the connection; the next send
+//! reconnects, at most once per `reconnect_delay`, and records logged while
+//! the daemon is unreachable are dropped.
+//!
+//! In RFC 5424 messages, meta becomes structured data: a `structured_data`
+//! object keyed by SD-ID (as produced by `parsers::SyslogDecoder`) is sent as
+//! is, and the remaining fields form one element named `sd_id`. The
+//! `timestamp`, `hostname`, `app_name`, `procid`, `msgid` and `facility` meta
+//! fields override the header. RFC 3164 messages append meta as JSON instead.
+
+use crate::{parsers::SEVERITIES, Transport};
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use logform::{Format, LogInfo};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    net::{TcpStream, ToSocketAddrs, UdpSocket},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+#[cfg(unix)]
+use std::{os::unix::net::UnixDatagram, path::PathBuf};
+
+/// Where messages are sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyslogTarget {
+    /// A Unix datagram socket, usually `/dev/log`
+    #[cfg(unix)]
+    Unix(PathBuf),
+    /// A `host:port` address, one datagram per message
+    Udp(String),
+    /// A `host:port` address, with octet-counting framing
+    Tcp(String),
+}
+
+impl Default for SyslogTarget {
+    #[cfg(unix)]
+    fn default() -> Self {
+        SyslogTarget::Unix(PathBuf::from("/dev/log"))
+    }
+
+    #[cfg(not(unix))]
+    fn default() -> Self {
+        SyslogTarget::Udp("127.0.0.1:514".to_string())
+    }
+}
+
+/// Message format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyslogFormat {
+    #[default]
+    Rfc5424,
+    /// The BSD format, for daemons that do not understand RFC 5424
+    Rfc3164,
+}
+
+/// Syslog facilities, with their codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Facility {
+    Kern = 0,
+    #[default]
+    User = 1,
+    Mail = 2,
+    Daemon = 3,
+    Auth = 4,
+    Syslog = 5,
+    Lpr = 6,
+    News = 7,
+    Uucp = 8,
+    Cron = 9,
+    Authpriv = 10,
+    Ftp = 11,
+    Local0 = 16,
+    Local1 = 17,
+    Local2 = 18,
+    Local3 = 19,
+    Local4 = 20,
+    Local5 = 21,
+    Local6 = 22,
+    Local7 = 23,
+}
+
+/// Configuration for a `SyslogTransport`
+#[derive(Debug, Clone)]
+pub struct SyslogConfig {
+    pub target: SyslogTarget,
+    pub format: SyslogFormat,
+    pub facility: Facility,
+    /// Defaults to the machine's host name
+    pub hostname: Option<String>,
+    /// Defaults to the executable's name
+    pub app_name: Option<String>,
+    /// Defaults to the process id
+    pub procid: Option<String>,
+    pub msgid: Option<String>,
+    /// Severity (0 to 7) of each level; unknown levels are sent as `info`
+    pub severities: HashMap<String, u8>,
+    /// SD-ID of the element holding meta fields; use your own private
+    /// enterprise number in place of the documentation number 32473
+    pub sd_id: String,
+    /// Datagrams longer than this are truncated
+    pub max_datagram_size: usize,
+    pub connect_timeout: Duration,
+    /// Minimum time between connection attempts after one fails
+    pub reconnect_delay: Duration,
+    /// Level threshold reported by `get_level`
+    pub level: Option<String>,
+}
+
+impl SyslogConfig {
+    pub fn new(target: SyslogTarget) -> Self {
+        Self {
+            target,
+            format: SyslogFormat::default(),
+            facility: Facility::default(),
+            hostname: None,
+            app_name: None,
+            procid: None,
+            msgid: None,
+            severities: default_severities(),
+            sd_id: "meta@32473".to_string(),
+            max_datagram_size: 8192,
+            connect_timeout: Duration::from_secs(5),
+            reconnect_delay: Duration::from_secs(1),
+            level: None,
+        }
+    }
+}
+
+impl Default for SyslogConfig {
+    fn default() -> Self {
+        Self::new(SyslogTarget::default())
+    }
+}
+
+/// The syslog level names and their usual aliases
+fn default_severities() -> HashMap<String, u8> {
+    let mut severities: HashMap<String, u8> = SEVERITIES
+        .iter()
+        .zip(0..)
+        .map(|(name, severity)| (name.to_string(), severity))
+        .collect();
+    for (alias, severity) in [
+        ("emergency", 0),
+        ("critical", 2),
+        ("err", 3),
+        ("warn", 4),
+        ("http", 6),
+        ("verbose", 7),
+        ("trace", 7),
+        ("silly", 7),
+    ] {
+        severities.insert(alias.to_string(), severity);
+    }
+    severities
+}
+
+enum Connection {
+    #[cfg(unix)]
+    Unix(UnixDatagram),
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+#[derive(Default)]
+struct State {
+    connection: Option<Connection>,
+    last_failure: Option<Instant>,
+}
+
+/// Sends records to a syslog daemon
+pub struct SyslogTransport {
+    config: SyslogConfig,
+    hostname: String,
+    app_name: String,
+    procid: String,
+    state: Mutex<State>,
+    format: Option<Arc<dyn Format<Input = LogInfo> + Send + Sync>>,
+}
+
+impl SyslogTransport {
+    /// Creates the transport; it connects on the first record
+    pub fn new(config: SyslogConfig) -> Self {
+        let hostname = config.hostname.clone().or_else(default_hostname);
+        let app_name = config.app_name.clone().or_else(|| {
+            std::env::current_exe()
+                .ok()
+                .and_then(|exe| Some(exe.file_stem()?.to_string_lossy().into_owned()))
+        });
+        Self {
+            hostname: hostname.unwrap_or_else(|| "-".to_string()),
+            app_name: app_name.unwrap_or_else(|| "-".to_string()),
+            procid: config
+                .procid
+                .clone()
+                .unwrap_or_else(|| std::process::id().to_string()),
+            config,
+            state: Mutex::new(State::default()),
+            format: None,
+        }
+    }
+
+    pub fn with_format<F>(mut self, format: F) -> Self
+    where
+        F: Format<Input = LogInfo> + Send + Sync + 'static,
+    {
+        self.format = Some(Arc::new(format));
+        self
+    }
+
+    /// Formats `info` as a syslog message, without framing
+    pub fn format_message(&self, info: &LogInfo) -> String {
+        let severity = self
+            .config
+            .severities
+            .get(&info.level)
+            .copied()
+            .unwrap_or(6)
+            .min(7);
+        let facility = match info.meta.get("facility").and_then(Value::as_u64) {
+            Some(facility) if facility < 24 => facility as u8,
+            _ => self.config.facility as u8,
+        };
+        let priority = facility * 8 + severity;
+        let timestamp = match info.meta.get("timestamp") {
+            Some(Value::String(text)) => DateTime::parse_from_rfc3339(text)
+                .map(|timestamp| timestamp.with_timezone(&Utc))
+                .unwrap_or_else(|_| Utc::now()),
+            _ => Utc::now(),
+        };
+        let header = |key: &str, default: &str, max: usize| {
+            let value = match info.meta.get(key) {
+                Some(Value::String(text)) => text.clone(),
+                Some(Value::Number(number)) => number.to_string(),
+                _ => default.to_string(),
+            };
+            header_field(&value, max)
+        };
+        let hostname = header("hostname", &self.hostname, 255);
+        let app_name = header("app_name", &self.app_name, 48);
+        let procid = header("procid", &self.procid, 128);
+        let meta: BTreeMap<&String, &Value> = info
+            .meta
+            .iter()
+            .filter(|(key, _)| {
+                !matches!(
+                    key.as_str(),
+                    "timestamp" | "hostname" | "app_name" | "procid" | "msgid" | "facility"
+                )
+            })
+            .collect();
+
+        match self.config.format {
+            SyslogFormat::Rfc5424 => {
+                let msgid = header("msgid", self.config.msgid.as_deref().unwrap_or("-"), 32);
+                format!(
+                    "<{}>1 {} {} {} {} {} {} {}",
+                    priority,
+                    timestamp.to_rfc3339_opts(SecondsFormat::Micros, true),
+                    hostname,
+                    app_name,
+                    procid,
+                    msgid,
+                    self.structured_data(&meta),
+                    info.message
+                )
+            }
+            SyslogFormat::Rfc3164 => {
+                let mut message = format!(
+                    "<{}>{} {} {}[{}]: {}",
+                    priority,
+                    timestamp.with_timezone(&Local).format("%b %e %H:%M:%S"),
+                    hostname,
+                    app_name,
+                    procid,
+                    info.message
+                );
+                if !meta.is_empty() {
+                    message.push(' ');
+                    message.push_str(&serde_json::to_string(&meta).unwrap_or_default());
+                }
+                message
+            }
+        }
+    }
+
+    fn structured_data(&self, meta: &BTreeMap<&String, &Value>) -> String {
+        let mut elements = String::new();
+        let mut write_element = |id: &str, params: &mut dyn Iterator<Item = (&String, &Value)>| {
+            let mut element = format!("[{}", sd_name(id));
+            for (name, value) in params {
+                let value = match value {
+                    Value::String(text) => text.clone(),
+                    other => other.to_string(),
+                };
+                let _ = write!(element, " {}=\"{}\"", sd_name(name), sd_escape(&value));
+            }
+            elements.push_str(&element);
+            elements.push(']');
+        };
+
+        if let Some(Value::Object(structured_data)) = meta
+            .iter()
+            .find(|(key, _)| key.as_str() == "structured_data")
+            .map(|(_, value)| *value)
+        {
+            for (id, params) in structured_data {
+                match params {
+                    Value::Object(params) => write_element(id, &mut params.iter()),
+                    _ => write_element(id, &mut std::iter::empty()),
+                }
+            }
+        }
+        let mut fields = meta
+            .iter()
+            .filter(|(key, _)| key.as_str() != "structured_data")
+            .map(|(key, value)| (*key, *value))
+            .peekable();
+        if fields.peek().is_some() {
+            write_element(&self.config.sd_id, &mut fields);
+        }
+
+        if elements.is_empty() {
+            "-".to_string()
+        } else {
+            elements
+        }
+    }
+
+    fn connect(&self) -> io::Result<Connection> {
+        let timeout = self.config.connect_timeout;
+        match &self.config.target {
+            #[cfg(unix)]
+            SyslogTarget::Unix(path) => {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(path)?;
+                Ok(Connection::Unix(socket))
+            }
+            SyslogTarget::Udp(address) => {
+                let address = resolve(address)?;
+                let local = if address.is_ipv4() {
+                    "0.0.0.0:0"
+                } else {
+                    "[::]:0"
+                };
+                let socket = UdpSocket::bind(local)?;
+                socket.connect(address)?;
+                Ok(Connection::Udp(socket))
+            }
+            SyslogTarget::Tcp(address) => {
+                let stream = TcpStream::connect_timeout(&resolve(address)?, timeout)?;
+                stream.set_write_timeout(Some(timeout))?;
+                stream.set_nodelay(true)?;
+                Ok(Connection::Tcp(stream))
+            }
+        }
+    }
+
+    /// Sends the messages, or fails with how many of them were sent first
+    fn send(
+        connection: &mut Connection,
+        messages: &[String],
+        max_datagram: usize,
+    ) -> Result<(), (usize, io::Error)> {
+        match connection {
+            #[cfg(unix)]
+            Connection::Unix(socket) => {
+                send_datagrams(messages, max_datagram, |datagram| socket.send(datagram))
+            }
+            Connection::Udp(socket) => {
+                send_datagrams(messages, max_datagram, |datagram| socket.send(datagram))
+            }
+            Connection::Tcp(stream) => {
+                let mut framed = Vec::new();
+                for message in messages {
+                    framed.extend_from_slice(format!("{} {}", message.len(), message).as_bytes());
+                }
+                // How much of a failed write the daemon received is unknown,
+                // so the whole batch counts as unsent
+                stream.write_all(&framed).map_err(|e| (0, e))
+            }
+        }
+    }
+
+    fn deliver(&self, messages: &[String]) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return report!("SyslogTransport: failed to lock connection"),
+        };
+        let max_datagram = self.config.max_datagram_size;
+        let mut pending = messages;
+
+        // One retry on a fresh connection covers a daemon that restarted, and
+        // resends only what the failed connection did not take
+        for attempt in 0..2 {
+            if state.connection.is_none() {
+                let waiting = state
+                    .last_failure
+                    .is_some_and(|failed| failed.elapsed() < self.config.reconnect_delay);
+                if waiting {
+                    return;
+                }
+                match self.connect() {
+                    Ok(connection) => {
+                        state.connection = Some(connection);
+                        state.last_failure = None;
+                    }
+                    Err(e) => {
+                        report!("SyslogTransport: failed to connect: {}", e);
+                        state.last_failure = Some(Instant::now());
+                        return;
+                    }
+                }
+            }
+            let Some(connection) = state.connection.as_mut() else {
+                return;
+            };
+            match Self::send(connection, pending, max_datagram) {
+                Ok(()) => return,
+                Err((sent, e)) => {
+                    pending = &pending[sent..];
+                    state.connection = None;
+                    if attempt == 1 {
+                        report!("SyslogTransport: failed to send: {}", e);
+                        state.last_failure = Some(Instant::now());
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Transport for SyslogTransport {
+    fn log(&self, info: LogInfo) {
+        self.log_batch_ref(std::slice::from_ref(&info));
+    }
+
+    fn log_batch(&self, infos: Vec<LogInfo>) {
+        self.log_batch_ref(&infos);
+    }
+
+    fn log_batch_ref(&self, infos: &[LogInfo]) {
+        let messages: Vec<String> = infos.iter().map(|info| self.format_message(info)).collect();
+        self.deliver(&messages);
+    }
+
+    fn borrows_batches(&self) -> bool {
+        true
+    }
+
+    fn flush(&self) -> Result<(), String> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| "Failed to lock connection".to_string())?;
+        match state.connection.as_mut() {
+            Some(Connection::Tcp(stream)) => stream
+                .flush()
+                .map_err(|e| format!("Failed to flush: {}", e)),
+            _ => Ok(()),
+        }
+    }
+
+    fn get_level(&self) -> Option<&String> {
+        self.config.level.as_ref()
+    }
+
+    fn get_format(&self) -> Option<Arc<dyn Format<Input = LogInfo> + Send + Sync>> {
+        self.format.clone()
+    }
+}
+
+fn resolve(address: &str) -> io::Result<std::net::SocketAddr> {
+    address.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("No address for {}", address),
+        )
+    })
+}
+
+fn default_hostname() -> Option<String> {
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .find_map(|path| fs::read_to_string(path).ok())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// A header field limited to printable ASCII without spaces, or `-` when empty
+fn header_field(value: &str, max: usize) -> String {
+    let field: String = value
+        .chars()
+        .map(|c| if c.is_ascii_graphic() { c } else { '_' })
+        .take(max)
+        .collect();
+    if field.is_empty() {
+        "-".to_string()
+    } else {
+        field
+    }
+}
+
+/// An SD-ID or PARAM-NAME: at most 32 printable ASCII characters, without
+/// `=`, space, `]` or `"`
+fn sd_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '=' | ']' | '"' => '_',
+            c if c.is_ascii_graphic() => c,
+            _ => '_',
+        })
+        .take(32)
+        .collect();
+    if name.is_empty() {
+        "_".to_string()
+    } else {
+        name
+    }
+}
+
+fn sd_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Sends each message as a datagram, or fails with how many were sent first
+fn send_datagrams(
+    messages: &[String],
+    max_datagram: usize,
+    mut send: impl FnMut(&[u8]) -> io::Result<usize>,
+) -> Result<(), (usize, io::Error)> {
+    for (sent, message) in messages.iter().enumerate() {
+        send(truncate(message, max_datagram)).map_err(|e| (sent, e))?;
+    }
+    Ok(())
+}
+
+/// The longest prefix of `message` within `max` bytes, on a character boundary
+fn truncate(message: &str, max: usize) -> &[u8] {
+    let mut end = message.len().min(max);
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+    &message.as_bytes()[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decoder::InputDecoder, parsers::SyslogDecoder};
+    use serde_json::json;
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+    };
+
+    fn config(target: SyslogTarget) -> SyslogConfig {
+        let mut config = SyslogConfig::new(target);
+        config.hostname = Some("host".to_string());
+        config.app_name = Some("app".to_string());
+        config.procid = Some("42".to_string());
+        config.reconnect_delay = Duration::ZERO;
+        config
+    }
+
+    #[test]
+    fn test_rfc5424_format() {
+        let mut config = config(SyslogTarget::Udp("127.0.0.1:514".to_string()));
+        config.facility = Facility::Local4;
+        config.msgid = Some("ID47".to_string());
+        let transport = SyslogTransport::new(config);
+
+        let info = LogInfo::new("warn", "disk almost full")
+            .with_meta("timestamp", "2024-04-10T12:00:00.5+02:00")
+            .with_meta("free", 3)
+            .with_meta("path", "/var \"data\"]")
+            .with_meta("structured_data", json!({"origin": {"ip": "10.0.0.1"}}));
+        let message = transport.format_message(&info);
+        assert_eq!(
+            message,
+            "<164>1 2024-04-10T10:00:00.500000Z host app 42 ID47 \
+             [origin ip=\"10.0.0.1\"][meta@32473 free=\"3\" path=\"/var \\\"data\\\"\\]\"] \
+             disk almost full"
+        );
+
+        // Messages read back with the decoder as they were written
+        let decoded = SyslogDecoder.decode(&message, "info").unwrap();
+        assert_eq!(decoded.level, "warning");
+        assert_eq!(decoded.message, "disk almost full");
+        assert_eq!(
+            decoded.meta.get("structured_data"),
+            Some(&json!({
+                "origin": {"ip": "10.0.0.1"},
+                "meta@32473": {"free": "3", "path": "/var \"data\"]"}
+            }))
+        );
+
+        let message = transport.format_message(&LogInfo::new("custom", "plain"));
+        assert!(message.starts_with("<166>1 "));
+        assert!(message.ends_with(" host app 42 ID47 - plain"));
+    }
+
+    #[test]
+    fn test_rfc3164_over_udp() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut config = config(SyslogTarget::Udp(server.local_addr().unwrap().to_string()));
+        config.format = SyslogFormat::Rfc3164;
+        config.facility = Facility::Daemon;
+        let transport = SyslogTransport::new(config);
+
+        transport.log(LogInfo::new("error", "failed").with_meta("code", 7));
+        let mut buf = [0; 1024];
+        let len = server.recv(&mut buf).unwrap();
+        let message = String::from_utf8_lossy(&buf[..len]).into_owned();
+        assert!(message.starts_with("<27>"));
+        assert!(message.ends_with(" host app[42]: failed {\"code\":7}"));
+
+        let decoded = SyslogDecoder.decode(&message, "info").unwrap();
+        assert_eq!(decoded.level, "error");
+        assert_eq!(decoded.meta.get("hostname"), Some(&json!("host")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_datagram() {
+        let path = std::env::temp_dir().join(format!("winston_syslog_{}.sock", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let server = UnixDatagram::bind(&path).unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut config = config(SyslogTarget::Unix(path.clone()));
+        config.max_datagram_size = 64;
+        let transport = SyslogTransport::new(config);
+
+        transport.log_batch(vec![
+            LogInfo::new("info", "first"),
+            LogInfo::new("info", "é".repeat(100)),
+        ]);
+        let mut buf = [0; 1024];
+        let len = server.recv(&mut buf).unwrap();
+        assert!(String::from_utf8_lossy(&buf[..len]).ends_with(" - first"));
+        let len = server.recv(&mut buf).unwrap();
+        assert!(len <= 64);
+        assert!(std::str::from_utf8(&buf[..len]).is_ok());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_retry_resends_only_unsent_datagrams() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let mut config = config(SyslogTarget::Udp(server.local_addr().unwrap().to_string()));
+        // Lets through a message too large for a UDP datagram, which fails
+        config.max_datagram_size = 100_000;
+        let transport = SyslogTransport::new(config);
+
+        transport.log_batch(vec![
+            LogInfo::new("info", "first"),
+            LogInfo::new("info", "x".repeat(70_000)),
+        ]);
+        let mut buf = [0; 1024];
+        let len = server.recv(&mut buf).unwrap();
+        assert!(String::from_utf8_lossy(&buf[..len]).ends_with(" - first"));
+        // The retry started from the message that failed
+        assert!(server.recv(&mut buf).is_err());
+    }
+
+    fn read_frame(reader: &mut impl BufRead) -> io::Result<String> {
+        let mut len = Vec::new();
+        reader.read_until(b' ', &mut len)?;
+        let len: usize = String::from_utf8_lossy(&len)
+            .trim()
+            .parse()
+            .map_err(|_| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        let mut message = vec![0; len];
+        reader.read_exact(&mut message)?;
+        Ok(String::from_utf8(message).unwrap())
+    }
+
+    #[test]
+    fn test_tcp_octet_counting_and_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let transport = SyslogTransport::new(config(SyslogTarget::Tcp(
+            listener.local_addr().unwrap().to_string(),
+        )));
+
+        transport.log_batch(vec![
+            LogInfo::new("info", "one"),
+            LogInfo::new("info", "two words"),
+        ]);
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        assert!(read_frame(&mut reader).unwrap().ends_with(" - one"));
+        assert!(read_frame(&mut reader).unwrap().ends_with(" - two words"));
+
+        // The daemon restarts; records keep being sent until one arrives on a
+        // new connection
+        drop(reader);
+        listener.set_nonblocking(true).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let stream = loop {
+            transport.log(LogInfo::new("info", "again"));
+            if let Ok((stream, _)) = listener.accept() {
+                break stream;
+            }
+            assert!(Instant::now() < deadline, "transport did not reconnect");
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        stream.set_nonblocking(false).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        assert!(read_frame(&mut BufReader::new(stream))
+            .unwrap()
+            .ends_with(" - again"));
+    }
+}
@@ -0,0 +1,763 @@
+//! Ingestion of log files that other programs write, in the manner of `tail -F`.
+//!
+//! `FileTailSource` polls a set of paths on a background thread. Each
+//! complete line is decoded with an `InputDecoder`, given a `file` meta field,
+//! and forwarded to a `Transport` in batches. A partial last line is held
+//! until its newline arrives.
+//!
+//! A path that is renamed away and recreated (rotation) is followed to the new
+//! file once everything left in the old one has been delivered. A file that
+//! shrinks, or whose first bytes change (truncation, even when it has grown
+//! past the old offset again), is read again from the start. A batch counts as
+//! delivered once the transport has flushed it; when the flush fails, the
+//! file is read again from the end of the last delivered batch. With a
+//! checkpoint file, the offset delivered in each file is saved after every
+//! batch, so a restarted source resumes where the last one stopped. Delivery
+//! is at least once: records handed over but not yet checkpointed are read
+//! again after a crash.
+
+use crate::{
+    decoder::{decode_or_plain, InputDecoder, PlainTextDecoder},
+    LogInfo, Transport,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{self, File, Metadata},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Size of each read from a followed file
+const READ_SIZE: usize = 64 * 1024;
+
+/// Number of leading bytes compared to notice a file truncated and rewritten
+const HEAD_LEN: usize = 256;
+
+/// Where to start reading a file that has no checkpointed offset
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StartPosition {
+    /// Read the whole file
+    #[default]
+    Beginning,
+    /// Read only lines written after the source started
+    End,
+}
+
+/// Configuration for a `FileTailSource`
+#[derive(Clone)]
+pub struct TailConfig {
+    /// Files to follow; they need not exist yet
+    pub paths: Vec<PathBuf>,
+    /// File holding the offset reached in each path
+    pub checkpoint: Option<PathBuf>,
+    /// Where to start in a file seen for the first time. Files that appear
+    /// after a rotation are always read from the beginning.
+    pub start_position: StartPosition,
+    /// Parses each line; lines it rejects are logged as plain messages
+    pub decoder: Arc<dyn InputDecoder>,
+    /// Level of lines that carry none
+    pub default_level: String,
+    /// How long to wait between polls once every file has been read
+    pub poll_interval: Duration,
+    /// Largest number of records forwarded in one `log_batch` call
+    pub max_batch_size: usize,
+    /// Lines longer than this many bytes are split into several records
+    pub max_line_length: usize,
+}
+
+impl TailConfig {
+    /// Creates a configuration following the given paths as plain text
+    pub fn new<I, P>(paths: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        Self {
+            paths: paths.into_iter().map(Into::into).collect(),
+            checkpoint: None,
+            start_position: StartPosition::Beginning,
+            decoder: Arc::new(PlainTextDecoder),
+            default_level: "info".to_string(),
+            poll_interval: Duration::from_millis(250),
+            max_batch_size: 500,
+            max_line_length: 64 * 1024,
+        }
+    }
+
+    /// Saves offsets to `path` and resumes from it on start
+    pub fn with_checkpoint(mut self, path: impl Into<PathBuf>) -> Self {
+        self.checkpoint = Some(path.into());
+        self
+    }
+
+    /// Parses lines with `decoder`
+    pub fn with_decoder<D: InputDecoder + 'static>(mut self, decoder: D) -> Self {
+        self.decoder = Arc::new(decoder);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct FilePosition {
+    id: u64,
+    offset: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Checkpoint {
+    files: HashMap<String, FilePosition>,
+}
+
+struct TailedFile {
+    path: PathBuf,
+    label: String,
+    file: Option<File>,
+    id: u64,
+    // Position of the file cursor; `pending` holds the bytes before it that
+    // do not yet form a complete line
+    read_pos: u64,
+    pending: Vec<u8>,
+    // Offset up to which lines have been delivered and flushed
+    delivered: u64,
+    // First bytes of the file, up to `HEAD_LEN`, as they were when read
+    head: Vec<u8>,
+    // The path names another file now, and this one has been read to its end
+    exhausted: bool,
+    seen: bool,
+}
+
+enum FileState {
+    Unchanged,
+    Truncated,
+    Replaced,
+}
+
+impl TailedFile {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            label: path.display().to_string(),
+            path,
+            file: None,
+            id: 0,
+            read_pos: 0,
+            pending: Vec::new(),
+            delivered: 0,
+            head: Vec::new(),
+            exhausted: false,
+            seen: false,
+        }
+    }
+
+    /// Offset up to which every line has been read
+    fn committed(&self) -> u64 {
+        self.read_pos - self.pending.len() as u64
+    }
+
+    /// Opens the path if it exists, returning whether it is open
+    fn open(&mut self, checkpoint: &Checkpoint, start: StartPosition) -> io::Result<bool> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        let metadata = file.metadata()?;
+        let id = file_id(&metadata);
+
+        let offset = match checkpoint.files.get(&self.label) {
+            Some(position) if position.id == id && position.offset <= metadata.len() => {
+                position.offset
+            }
+            _ if !self.seen && start == StartPosition::End => metadata.len(),
+            _ => 0,
+        };
+        file.seek(SeekFrom::Start(offset))?;
+
+        self.file = Some(file);
+        self.id = id;
+        self.read_pos = offset;
+        self.pending.clear();
+        self.delivered = offset;
+        self.head.clear();
+        self.seen = true;
+        Ok(true)
+    }
+
+    /// Moves back to the end of the last delivered batch, so the lines after
+    /// it are read again
+    fn rewind(&mut self) -> io::Result<()> {
+        if let Some(file) = &mut self.file {
+            file.seek(SeekFrom::Start(self.delivered))?;
+        }
+        self.read_pos = self.delivered;
+        self.pending.clear();
+        self.exhausted = false;
+        Ok(())
+    }
+
+    fn state(&mut self) -> io::Result<FileState> {
+        let file = self.file.as_ref().unwrap();
+        if file.metadata()?.len() < self.read_pos || !self.head_matches()? {
+            return Ok(FileState::Truncated);
+        }
+        match fs::metadata(&self.path) {
+            Ok(metadata) if file_id(&metadata) != self.id => Ok(FileState::Replaced),
+            // A missing path may be recreated later; keep reading the old file
+            _ => Ok(FileState::Unchanged),
+        }
+    }
+
+    /// Whether the file still starts with the bytes read from it before, which
+    /// a file truncated and written past the old offset again does not
+    fn head_matches(&mut self) -> io::Result<bool> {
+        let len = self.read_pos.min(HEAD_LEN as u64) as usize;
+        if len == 0 {
+            return Ok(true);
+        }
+        let file = self.file.as_mut().unwrap();
+        let mut head = [0; HEAD_LEN];
+        file.seek(SeekFrom::Start(0))?;
+        let read = file.read_exact(&mut head[..len]);
+        file.seek(SeekFrom::Start(self.read_pos))?;
+        match read {
+            Ok(()) => {}
+            // Shrunk since its length was checked
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(e) => return Err(e),
+        }
+
+        let known = self.head.len().min(len);
+        if head[..known] != self.head[..known] {
+            return Ok(false);
+        }
+        self.head.extend_from_slice(&head[known..len]);
+        Ok(true)
+    }
+
+    /// Reads until the end of the file or until `records` holds a full batch,
+    /// returning whether the end was reached
+    fn read_lines(
+        &mut self,
+        config: &TailConfig,
+        buf: &mut [u8],
+        records: &mut Vec<LogInfo>,
+    ) -> io::Result<bool> {
+        while records.len() < config.max_batch_size {
+            let read = match self.file.as_mut().unwrap().read(buf) {
+                Ok(0) => return Ok(true),
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            self.read_pos += read as u64;
+            self.pending.extend_from_slice(&buf[..read]);
+            self.take_lines(config, records);
+        }
+        Ok(false)
+    }
+
+    fn take_lines(&mut self, config: &TailConfig, records: &mut Vec<LogInfo>) {
+        let mut start = 0;
+        while let Some(end) = self.pending[start..].iter().position(|b| *b == b'\n') {
+            let line = &self.pending[start..start + end];
+            self.emit(line, config, records);
+            start += end + 1;
+        }
+        let max_line_length = config.max_line_length.max(1);
+        while self.pending.len() - start > max_line_length {
+            let line = &self.pending[start..start + max_line_length];
+            self.emit(line, config, records);
+            start += max_line_length;
+        }
+        self.pending.drain(..start);
+    }
+
+    /// Emits the partial last line of a file that will not grow any more
+    fn finish(&mut self, config: &TailConfig, records: &mut Vec<LogInfo>) {
+        let pending = std::mem::take(&mut self.pending);
+        self.emit(&pending, config, records);
+    }
+
+    fn emit(&self, line: &[u8], config: &TailConfig, records: &mut Vec<LogInfo>) {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() {
+            return;
+        }
+        let text = String::from_utf8_lossy(line);
+        let info = decode_or_plain(&*config.decoder, &text, &config.default_level)
+            .with_meta("file", self.label.as_str());
+        records.push(info);
+    }
+}
+
+/// Reads the followed files and forwards their lines, one poll at a time
+struct Tailer {
+    config: TailConfig,
+    files: Vec<TailedFile>,
+    checkpoint: Checkpoint,
+    // Shared by every read, so polling does not allocate it each time
+    buf: Vec<u8>,
+}
+
+impl Tailer {
+    fn new(config: TailConfig) -> Result<Self, String> {
+        let checkpoint = match &config.checkpoint {
+            Some(path) => match fs::read(path) {
+                Ok(bytes) => serde_json::from_slice(&bytes)
+                    .map_err(|e| format!("Corrupt tail checkpoint: {}", e))?,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Checkpoint::default(),
+                Err(e) => return Err(format!("Failed to read tail checkpoint: {}", e)),
+            },
+            None => Checkpoint::default(),
+        };
+        let files = config.paths.iter().cloned().map(TailedFile::new).collect();
+        Ok(Self {
+            config,
+            files,
+            checkpoint,
+            buf: vec![0; READ_SIZE],
+        })
+    }
+
+    /// Reads what is new in every file and forwards it, returning the number
+    /// of records forwarded
+    fn poll<T: Transport + ?Sized>(&mut self, transport: &T) -> Result<usize, String> {
+        let mut forwarded = 0;
+        let mut first_error = None;
+
+        for index in 0..self.files.len() {
+            loop {
+                let mut records = Vec::new();
+                let done = match self.read_file(index, &mut records) {
+                    Ok(done) => done,
+                    Err(e) => {
+                        first_error.get_or_insert(format!(
+                            "Failed to read {}: {}",
+                            self.files[index].label, e
+                        ));
+                        true
+                    }
+                };
+                let count = records.len();
+                if let Err(e) = self.deliver(transport, records) {
+                    // Read the undelivered lines again on the next poll
+                    let file = &mut self.files[index];
+                    if file.rewind().is_err() {
+                        // Reopened from the checkpoint instead
+                        file.file = None;
+                    }
+                    return Err(first_error.unwrap_or(e));
+                }
+                let file = &mut self.files[index];
+                file.delivered = file.committed();
+                forwarded += count;
+                if done {
+                    break;
+                }
+            }
+        }
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(forwarded),
+        }
+    }
+
+    /// Reads the next batch from one file, following truncation and rotation,
+    /// and returns whether the file has been read to its end
+    fn read_file(&mut self, index: usize, records: &mut Vec<LogInfo>) -> io::Result<bool> {
+        let config = &self.config;
+        let file = &mut self.files[index];
+        loop {
+            if file.exhausted {
+                file.exhausted = false;
+                file.file = None;
+            }
+            if file.file.is_none() && !file.open(&self.checkpoint, config.start_position)? {
+                return Ok(true);
+            }
+
+            if let FileState::Truncated = file.state()? {
+                file.file.as_mut().unwrap().seek(SeekFrom::Start(0))?;
+                file.read_pos = 0;
+                file.pending.clear();
+                file.delivered = 0;
+                file.head.clear();
+            }
+            if !file.read_lines(config, &mut self.buf, records)? {
+                return Ok(false);
+            }
+
+            // Only switch to a replacement once the old file is exhausted and
+            // what was left in it delivered
+            match file.state()? {
+                FileState::Replaced => {
+                    file.finish(config, records);
+                    file.exhausted = true;
+                    if !records.is_empty() {
+                        return Ok(false);
+                    }
+                }
+                _ => return Ok(true),
+            }
+        }
+    }
+
+    fn deliver<T: Transport + ?Sized>(
+        &mut self,
+        transport: &T,
+        records: Vec<LogInfo>,
+    ) -> Result<(), String> {
+        if !records.is_empty() {
+            let size = self.config.max_batch_size.max(1);
+            if transport.borrows_batches() {
+                for chunk in records.chunks(size) {
+                    transport.log_batch_ref(chunk);
+                }
+            } else {
+                let mut records = records.into_iter().peekable();
+                while records.peek().is_some() {
+                    transport.log_batch(records.by_ref().take(size).collect());
+                }
+            }
+            transport.flush()?;
+        }
+        self.save_checkpoint()
+    }
+
+    fn save_checkpoint(&mut self) -> Result<(), String> {
+        let Some(path) = &self.config.checkpoint else {
+            return Ok(());
+        };
+
+        let mut changed = false;
+        for file in self.files.iter().filter(|file| file.file.is_some()) {
+            let position = FilePosition {
+                id: file.id,
+                offset: file.committed(),
+            };
+            if self.checkpoint.files.get(&file.label) != Some(&position) {
+                self.checkpoint.files.insert(file.label.clone(), position);
+                changed = true;
+            }
+        }
+        if !changed {
+            return Ok(());
+        }
+
+        let bytes = serde_json::to_vec(&self.checkpoint)
+            .map_err(|e| format!("Failed to serialize tail checkpoint: {}", e))?;
+        write_atomically(path, &bytes)
+            .map_err(|e| format!("Failed to write tail checkpoint: {}", e))
+    }
+}
+
+/// Follows log files and forwards their lines to a Transport until stopped
+pub struct FileTailSource {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl FileTailSource {
+    /// Starts following the files in `config` on a background thread
+    pub fn start<T: Transport + 'static>(transport: T, config: TailConfig) -> Result<Self, String> {
+        let mut tailer = Tailer::new(config)?;
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let stopped = stop.clone();
+
+        let handle = thread::Builder::new()
+            .name("file-tail".to_string())
+            .spawn(move || {
+                let (lock, signal) = &*stopped;
+                loop {
+                    let forwarded = tailer.poll(&transport).unwrap_or_else(|e| {
+                        report!("FileTailSource: {}", e);
+                        0
+                    });
+
+                    let mut stop = lock.lock().unwrap();
+                    if forwarded == 0 && !*stop {
+                        stop = signal
+                            .wait_timeout(stop, tailer.config.poll_interval)
+                            .unwrap()
+                            .0;
+                    }
+                    if *stop {
+                        break;
+                    }
+                }
+            })
+            .map_err(|e| format!("Failed to spawn tail thread: {}", e))?;
+
+        Ok(Self {
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    /// Stops following, waiting for the current poll to finish
+    pub fn stop(self) {
+        drop(self);
+    }
+}
+
+impl Drop for FileTailSource {
+    fn drop(&mut self) {
+        let (lock, signal) = &*self.stop;
+        *lock.lock().unwrap() = true;
+        signal.notify_all();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn write_atomically(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    fs::write(&temp, bytes)?;
+    fs::rename(&temp, path)
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.ino() ^ metadata.dev().rotate_left(32)
+}
+
+// Without inode numbers rotation is not detected, only truncation
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> u64 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::JsonLinesDecoder;
+    use std::io::Write;
+
+    struct MockTransport {
+        records: Mutex<Vec<LogInfo>>,
+        flushes: Mutex<usize>,
+        fail_flush: Mutex<bool>,
+    }
+
+    impl MockTransport {
+        fn new() -> Self {
+            Self {
+                records: Mutex::new(Vec::new()),
+                flushes: Mutex::new(0),
+                fail_flush: Mutex::new(false),
+            }
+        }
+
+        fn take_messages(&self) -> Vec<String> {
+            let records = std::mem::take(&mut *self.records.lock().unwrap());
+            records.into_iter().map(|info| info.message).collect()
+        }
+    }
+
+    impl Transport for MockTransport {
+        fn log(&self, info: LogInfo) {
+            self.records.lock().unwrap().push(info);
+        }
+
+        fn flush(&self) -> Result<(), String> {
+            *self.flushes.lock().unwrap() += 1;
+            if *self.fail_flush.lock().unwrap() {
+                return Err("sink unavailable".to_string());
+            }
+            Ok(())
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "winston_transport_tail_{}_{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn append(path: &Path, text: &str) {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_partial_lines_and_decoding() {
+        let dir = temp_dir("partial");
+        let path = dir.join("app.log");
+        let transport = MockTransport::new();
+        let mut tailer =
+            Tailer::new(TailConfig::new([&path]).with_decoder(JsonLinesDecoder)).unwrap();
+
+        // A missing file is not an error
+        assert_eq!(tailer.poll(&transport), Ok(0));
+
+        append(
+            &path,
+            "{\"level\":\"warn\",\"message\":\"json\"}\nplain\r\nhalf",
+        );
+        assert_eq!(tailer.poll(&transport), Ok(2));
+        {
+            let records = transport.records.lock().unwrap();
+            assert_eq!(records[0].level, "warn");
+            assert_eq!(records[1].level, "info");
+            assert_eq!(records[1].message, "plain");
+            assert_eq!(
+                records[1].meta.get("file"),
+                Some(&path.display().to_string().into())
+            );
+        }
+        transport.take_messages();
+
+        append(&path, " line\n");
+        tailer.poll(&transport).unwrap();
+        assert_eq!(transport.take_messages(), vec!["half line"]);
+
+        // Starting at the end skips what is already there
+        let mut config = TailConfig::new([&path]);
+        config.start_position = StartPosition::End;
+        let mut tailer = Tailer::new(config).unwrap();
+        assert_eq!(tailer.poll(&transport), Ok(0));
+        append(&path, "new\n");
+        tailer.poll(&transport).unwrap();
+        assert_eq!(transport.take_messages(), vec!["new"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotation_and_truncation() {
+        let dir = temp_dir("rotate");
+        let path = dir.join("app.log");
+        let transport = MockTransport::new();
+        let mut tailer = Tailer::new(TailConfig::new([&path])).unwrap();
+
+        append(&path, "one\n");
+        tailer.poll(&transport).unwrap();
+
+        // Lines written to the old file before the switch are not lost
+        append(&path, "two\nunterminated");
+        fs::rename(&path, dir.join("app.log.1")).unwrap();
+        append(&path, "three\n");
+        tailer.poll(&transport).unwrap();
+        assert_eq!(
+            transport.take_messages(),
+            vec!["one", "two", "unterminated", "three"]
+        );
+
+        // A truncated file is read again from the start
+        fs::write(&path, "").unwrap();
+        tailer.poll(&transport).unwrap();
+        append(&path, "four\n");
+        tailer.poll(&transport).unwrap();
+        assert_eq!(transport.take_messages(), vec!["four"]);
+
+        // So is one truncated and written past the old offset before a poll
+        fs::write(&path, "five, and then some more\n").unwrap();
+        tailer.poll(&transport).unwrap();
+        assert_eq!(transport.take_messages(), vec!["five, and then some more"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_delivery_is_read_again() {
+        let dir = temp_dir("retry");
+        let path = dir.join("app.log");
+        let config = TailConfig::new([&path]).with_checkpoint(dir.join("checkpoint.json"));
+        let transport = MockTransport::new();
+        let mut tailer = Tailer::new(config.clone()).unwrap();
+
+        append(&path, "one\n");
+        tailer.poll(&transport).unwrap();
+        transport.take_messages();
+
+        append(&path, "two\nthree\n");
+        *transport.fail_flush.lock().unwrap() = true;
+        assert!(tailer.poll(&transport).is_err());
+        assert_eq!(transport.take_messages(), vec!["two", "three"]);
+
+        *transport.fail_flush.lock().unwrap() = false;
+        tailer.poll(&transport).unwrap();
+        assert_eq!(transport.take_messages(), vec!["two", "three"]);
+
+        // Nothing more to deliver after a restart
+        Tailer::new(config).unwrap().poll(&transport).unwrap();
+        assert!(transport.take_messages().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoint_resume() {
+        let dir = temp_dir("checkpoint");
+        let path = dir.join("app.log");
+        let config = TailConfig::new([&path]).with_checkpoint(dir.join("checkpoint.json"));
+        let transport = MockTransport::new();
+
+        append(&path, "one\ntwo\npart");
+        Tailer::new(config.clone())
+            .unwrap()
+            .poll(&transport)
+            .unwrap();
+        assert_eq!(transport.take_messages(), vec!["one", "two"]);
+        assert_eq!(*transport.flushes.lock().unwrap(), 1);
+
+        // A new tailer skips what was delivered but not the partial line
+        append(&path, "ial\nthree\n");
+        Tailer::new(config).unwrap().poll(&transport).unwrap();
+        assert_eq!(transport.take_messages(), vec!["partial", "three"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_batching_and_background_source() {
+        let dir = temp_dir("source");
+        let path = dir.join("app.log");
+        append(&path, "old\n");
+
+        let mut config = TailConfig::new([&path]);
+        config.poll_interval = Duration::from_millis(10);
+        config.max_batch_size = 2;
+
+        let transport = Arc::new(MockTransport::new());
+        let source = FileTailSource::start(SharedTransport(transport.clone()), config).unwrap();
+        append(&path, "a\nb\nc\n");
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while transport.records.lock().unwrap().len() < 4 && std::time::Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        source.stop();
+        assert_eq!(transport.take_messages(), vec!["old", "a", "b", "c"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    struct SharedTransport(Arc<MockTransport>);
+
+    impl Transport for SharedTransport {
+        fn log(&self, info: LogInfo) {
+            self.0.log(info);
+        }
+
+        fn flush(&self) -> Result<(), String> {
+            self.0.flush()
+        }
+    }
+}
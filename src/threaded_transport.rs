@@ -1,19 +1,97 @@
-use crate::{log_query::LogQuery, Transport};
+use crate::{
+    executor::{ExecutorTask, SharedExecutor, TaskStatus, WorkerHandle, TASK_BUDGET},
+    log_query::LogQuery,
+    receipt::{self, FlushHandle, Receipt, ReceiptSender},
+    ring_buffer::RingBuffer,
+    stats::{AliveGuard, StatsRecorder, TransportStats},
+    Transport,
+};
 use logform::{Format, LogInfo};
 use std::{
     marker::PhantomData,
     sync::{
-        mpsc::{self, Receiver, Sender},
-        Arc,
+        atomic::{self, AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc, OnceLock,
     },
-    thread::{self, JoinHandle},
+    thread::{self, Thread},
+    time::Instant,
 };
 
+/// What `log` does when the ring buffer of a high-throughput transport is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait for the background thread to free a slot
+    Block,
+    /// Drop the record and count it in `dropped_total`
+    Drop,
+}
+
+/// Configuration for the lock-free high-throughput mode of ThreadedTransport
+#[derive(Debug, Clone)]
+pub struct RingBufferConfig {
+    /// Number of preallocated record slots, rounded up to a power of two
+    pub capacity: usize,
+    /// Maximum number of records handed to `log_batch` at once
+    pub max_batch: usize,
+    /// What to do when all slots are taken
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for RingBufferConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 8192,
+            max_batch: 256,
+            overflow: OverflowPolicy::Block,
+        }
+    }
+}
+
+/// A record in the ring buffer, with the receipt to complete once it is flushed
+type RingEntry = (LogInfo, Option<ReceiptSender>);
+
+/// The ring buffer and parking state shared by producers and the background thread
+struct RingChannel {
+    queue: RingBuffer<RingEntry>,
+    overflow: OverflowPolicy,
+    consumer: OnceLock<Thread>,
+    sleeping: AtomicBool,
+}
+
+impl RingChannel {
+    /// Unparks the background thread
+    fn wake(&self) {
+        if let Some(consumer) = self.consumer.get() {
+            consumer.unpark();
+        }
+    }
+
+    /// Unparks the background thread only if it is about to sleep, keeping the
+    /// hot path free of syscalls
+    fn wake_if_sleeping(&self) {
+        atomic::fence(Ordering::SeqCst);
+        if self.sleeping.load(Ordering::SeqCst) {
+            self.wake();
+        }
+    }
+
+    /// Parks the background thread until a producer pushes or a control message arrives
+    fn park(&self) {
+        self.sleeping.store(true, Ordering::SeqCst);
+        atomic::fence(Ordering::SeqCst);
+        if self.queue.is_empty() {
+            thread::park();
+        }
+        self.sleeping.store(false, Ordering::SeqCst);
+    }
+}
+
 /// Message types for communicating with the background thread
 #[derive(Debug)]
 enum TransportMessage {
-    Log(LogInfo),
-    Flush(Sender<Result<(), String>>),
+    Log(LogInfo, Option<ReceiptSender>),
+    Flush(ReceiptSender),
     Query(LogQuery, Sender<Result<Vec<LogInfo>, String>>),
     Shutdown,
 }
@@ -22,10 +100,14 @@ enum TransportMessage {
 /// for non-blocking, asynchronous logging operations.
 pub struct ThreadedTransport<T: Transport + 'static> {
     sender: Sender<TransportMessage>,
-    thread_handle: Option<JoinHandle<()>>,
+    // Declared after `sender` so a shared task sees the channel disconnect when dropped
+    worker: Option<WorkerHandle>,
     // Store references to the wrapped transport's level and format for immediate access
     level: Option<String>,
     format: Option<Arc<dyn Format<Input = LogInfo> + Send + Sync>>,
+    stats: Arc<StatsRecorder>,
+    // Set in high-throughput mode, where records bypass the channel
+    ring: Option<Arc<RingChannel>>,
     _phantom_data: PhantomData<T>,
 }
 
@@ -37,16 +119,20 @@ impl<T: Transport + 'static> ThreadedTransport<T> {
         let format = transport.get_format();
 
         let (sender, receiver) = mpsc::channel();
+        let stats = Arc::new(StatsRecorder::new());
+        let worker = ThreadedWorker::new(transport, stats.clone());
 
         let thread_handle = thread::spawn(move || {
-            Self::run_transport_thread(transport, receiver);
+            Self::run_transport_thread(worker, receiver);
         });
 
         Self {
             sender,
-            thread_handle: Some(thread_handle),
+            worker: Some(WorkerHandle::Thread(thread_handle)),
             level,
             format,
+            stats,
+            ring: None,
             _phantom_data: PhantomData,
         }
     }
@@ -57,54 +143,243 @@ impl<T: Transport + 'static> ThreadedTransport<T> {
         let format = transport.get_format();
 
         let (sender, receiver) = mpsc::channel();
+        let stats = Arc::new(StatsRecorder::new());
+        let worker = ThreadedWorker::new(transport, stats.clone());
 
         let thread_handle = thread::Builder::new()
             .name(thread_name)
             .spawn(move || {
-                Self::run_transport_thread(transport, receiver);
+                Self::run_transport_thread(worker, receiver);
             })
             .expect("Failed to spawn async transport thread");
 
         Self {
             sender,
-            thread_handle: Some(thread_handle),
+            worker: Some(WorkerHandle::Thread(thread_handle)),
+            level,
+            format,
+            stats,
+            ring: None,
+            _phantom_data: PhantomData,
+        }
+    }
+
+    /// Creates a new ThreadedTransport that runs on a shared executor instead of
+    /// its own thread
+    pub fn with_executor(transport: T, executor: &SharedExecutor) -> Self {
+        let level = transport.get_level().cloned();
+        let format = transport.get_format();
+
+        let (sender, receiver) = mpsc::channel();
+        let stats = Arc::new(StatsRecorder::new());
+
+        let task_handle = executor.spawn(ThreadedTask {
+            worker: ThreadedWorker::new(transport, stats.clone()),
+            receiver,
+        });
+
+        Self {
+            sender,
+            worker: Some(WorkerHandle::Shared(task_handle)),
             level,
             format,
+            stats,
+            ring: None,
+            _phantom_data: PhantomData,
+        }
+    }
+
+    /// Creates a new ThreadedTransport in high-throughput mode
+    ///
+    /// Records are passed through a preallocated lock-free ring buffer instead of
+    /// the message channel, and the background thread dequeues them in batches of
+    /// up to `max_batch`. A wrapped transport that borrows batches gets them
+    /// through `log_batch_ref` from one reused buffer, so the steady state does
+    /// not allocate; any other gets each batch as a new `Vec` in `log_batch`.
+    pub fn with_ring_buffer(transport: T, config: RingBufferConfig) -> Self {
+        let level = transport.get_level().cloned();
+        let format = transport.get_format();
+
+        let (sender, receiver) = mpsc::channel();
+        let stats = Arc::new(StatsRecorder::new());
+        let worker = ThreadedWorker::new(transport, stats.clone());
+        let ring = Arc::new(RingChannel {
+            queue: RingBuffer::new(config.capacity),
+            overflow: config.overflow,
+            consumer: OnceLock::new(),
+            sleeping: AtomicBool::new(false),
+        });
+        let worker_ring = ring.clone();
+        let max_batch = config.max_batch.max(1);
+
+        let thread_handle = thread::Builder::new()
+            .name("threaded-transport-ring".to_string())
+            .spawn(move || {
+                Self::run_ring_thread(worker, worker_ring, receiver, max_batch);
+            })
+            .expect("Failed to spawn async transport thread");
+
+        Self {
+            sender,
+            worker: Some(WorkerHandle::Thread(thread_handle)),
+            level,
+            format,
+            stats,
+            ring: Some(ring),
             _phantom_data: PhantomData,
         }
     }
 
     /// The main loop running on the background thread
-    fn run_transport_thread(transport: T, receiver: Receiver<TransportMessage>) {
+    fn run_transport_thread(mut worker: ThreadedWorker<T>, receiver: Receiver<TransportMessage>) {
         while let Ok(message) = receiver.recv() {
-            match message {
-                TransportMessage::Log(info) => {
-                    transport.log(info);
-                }
-                TransportMessage::Flush(response_sender) => {
-                    let result = transport.flush();
-                    let _ = response_sender.send(result);
+            if !worker.handle_message(message) {
+                return;
+            }
+        }
+        // Disconnected without a shutdown message
+        let _ = worker.flush();
+    }
+
+    /// The main loop of the high-throughput mode
+    fn run_ring_thread(
+        mut worker: ThreadedWorker<T>,
+        ring: Arc<RingChannel>,
+        receiver: Receiver<TransportMessage>,
+        max_batch: usize,
+    ) {
+        let _ = ring.consumer.set(thread::current());
+        let mut drain = RingDrain {
+            entries: Vec::with_capacity(max_batch),
+            batch: Vec::with_capacity(max_batch),
+            receipts: Vec::new(),
+            max_batch,
+        };
+
+        loop {
+            let drained = drain.run(&ring.queue, &mut worker);
+
+            match receiver.try_recv() {
+                Ok(message) => {
+                    // Records logged before this message are already in the ring
+                    while drain.run(&ring.queue, &mut worker) > 0 {}
+                    if !worker.handle_message(message) {
+                        break;
+                    }
                 }
-                TransportMessage::Query(query, response_sender) => {
-                    let result = transport.query(&query);
-                    let _ = response_sender.send(result);
+                Err(TryRecvError::Empty) => {
+                    if drained == 0 {
+                        ring.park();
+                    }
                 }
-                TransportMessage::Shutdown => {
-                    // Perform final flush before shutting down
-                    let _ = transport.flush();
+                Err(TryRecvError::Disconnected) => {
+                    while drain.run(&ring.queue, &mut worker) > 0 {}
+                    let _ = worker.flush();
                     break;
                 }
             }
         }
     }
 
+    /// Wakes the worker after a message was queued
+    fn wake_worker(&self) {
+        if let Some(ring) = &self.ring {
+            ring.wake();
+        }
+        if let Some(worker) = &self.worker {
+            worker.wake();
+        }
+    }
+
+    /// Sends a message to the worker and wakes it if it runs on an executor
+    fn send(&self, message: TransportMessage) -> Result<(), ()> {
+        let is_log = matches!(message, TransportMessage::Log(..));
+        if self.sender.send(message).is_err() {
+            if is_log {
+                self.stats.record_dropped();
+            }
+            return Err(());
+        }
+        if is_log {
+            self.stats.record_enqueued();
+        }
+        self.wake_worker();
+        Ok(())
+    }
+
+    /// Pushes an entry onto the ring buffer, waiting for a free slot unless
+    /// the overflow policy drops it
+    fn push_ring(&self, ring: &RingChannel, mut entry: RingEntry) {
+        loop {
+            match ring.queue.push(entry) {
+                Ok(()) => {
+                    self.stats.record_enqueued();
+                    ring.wake_if_sleeping();
+                    return;
+                }
+                Err(rejected) => {
+                    if ring.overflow == OverflowPolicy::Drop || !self.stats.worker_alive() {
+                        self.stats.record_dropped();
+                        if let (_, Some(receipt)) = rejected {
+                            receipt.complete(Err("Ring buffer is full".to_string()));
+                        }
+                        return;
+                    }
+                    entry = rejected;
+                    ring.wake();
+                    thread::yield_now();
+                }
+            }
+        }
+    }
+
+    /// Logs a record and returns a receipt that completes once the record has
+    /// been delivered and the wrapped transport flushed
+    ///
+    /// In high-throughput mode the record goes through the ring buffer with
+    /// the others, so it keeps its place among the records logged around it.
+    pub fn log_with_receipt(&self, info: LogInfo) -> Receipt {
+        let (receipt_sender, receipt) = receipt::receipt();
+        if let Some(ring) = &self.ring {
+            self.push_ring(ring, (info, Some(receipt_sender)));
+            return receipt;
+        }
+        if self
+            .send(TransportMessage::Log(info, Some(receipt_sender)))
+            .is_err()
+        {
+            return Receipt::ready(Err(
+                "Failed to send log message to background thread".to_string()
+            ));
+        }
+        receipt
+    }
+
+    /// Requests a flush without waiting for it, returning a handle that
+    /// completes with the flush result
+    pub fn flush_async(&self) -> FlushHandle {
+        let (receipt_sender, handle) = receipt::receipt();
+        if self.send(TransportMessage::Flush(receipt_sender)).is_err() {
+            return Receipt::ready(Err(
+                "Failed to send flush message to background thread".to_string()
+            ));
+        }
+        handle
+    }
+
+    /// Returns a snapshot of the queue and delivery statistics
+    pub fn stats(&self) -> TransportStats {
+        self.stats.snapshot()
+    }
+
     /// Gracefully shuts down the background thread
     pub fn shutdown(mut self) -> Result<(), String> {
-        if let Some(handle) = self.thread_handle.take() {
+        if let Some(handle) = self.worker.take() {
             // Send shutdown signal
             self.sender
                 .send(TransportMessage::Shutdown)
                 .map_err(|_| "Failed to send shutdown signal")?;
+            self.wake_worker();
 
             // Wait for thread to finish
             handle
@@ -117,21 +392,18 @@ impl<T: Transport + 'static> ThreadedTransport<T> {
 
 impl<T: Transport + 'static> Transport for ThreadedTransport<T> {
     fn log(&self, info: LogInfo) {
+        if let Some(ring) = &self.ring {
+            self.push_ring(ring, (info, None));
+            return;
+        }
+
         // Non-blocking send - if the channel is full or closed, we drop the message
         // We could also use a bounded channel with blocking send if we prefer backpressure
-        let _ = self.sender.send(TransportMessage::Log(info));
+        let _ = self.send(TransportMessage::Log(info, None));
     }
 
     fn flush(&self) -> Result<(), String> {
-        let (response_sender, response_receiver) = mpsc::channel();
-
-        self.sender
-            .send(TransportMessage::Flush(response_sender))
-            .map_err(|_| "Failed to send flush message to background thread")?;
-
-        response_receiver
-            .recv()
-            .map_err(|_| "Failed to receive flush response from background thread")?
+        self.flush_async().wait()
     }
 
     fn get_level(&self) -> Option<&String> {
@@ -145,8 +417,7 @@ impl<T: Transport + 'static> Transport for ThreadedTransport<T> {
     fn query(&self, options: &LogQuery) -> Result<Vec<LogInfo>, String> {
         let (response_sender, response_receiver) = mpsc::channel();
 
-        self.sender
-            .send(TransportMessage::Query(options.clone(), response_sender))
+        self.send(TransportMessage::Query(options.clone(), response_sender))
             .map_err(|_| "Failed to send query message to background thread")?;
 
         response_receiver
@@ -157,9 +428,10 @@ impl<T: Transport + 'static> Transport for ThreadedTransport<T> {
 
 impl<T: Transport + 'static> Drop for ThreadedTransport<T> {
     fn drop(&mut self) {
-        if let Some(handle) = self.thread_handle.take() {
+        if let Some(handle) = self.worker.take() {
             // Try to send shutdown signal
             let _ = self.sender.send(TransportMessage::Shutdown);
+            self.wake_worker();
 
             // Give the thread a moment to shut down gracefully
             let _ = handle.join();
@@ -178,11 +450,163 @@ pub trait IntoThreadedTransport: Transport + Sized + 'static {
     fn into_threaded_named(self, thread_name: String) -> ThreadedTransport<Self> {
         ThreadedTransport::with_thread_name(self, thread_name)
     }
+
+    /// Wraps this transport in an ThreadedTransport running on a shared executor
+    fn into_threaded_on(self, executor: &SharedExecutor) -> ThreadedTransport<Self> {
+        ThreadedTransport::with_executor(self, executor)
+    }
+
+    /// Wraps this transport in an ThreadedTransport using the lock-free
+    /// high-throughput mode
+    fn into_threaded_ring(self, config: RingBufferConfig) -> ThreadedTransport<Self> {
+        ThreadedTransport::with_ring_buffer(self, config)
+    }
 }
 
 // Implement for all transports
 impl<T: Transport + 'static> IntoThreadedTransport for T {}
 
+/// Delivery state shared by the dedicated thread loop and the executor task
+struct ThreadedWorker<T: Transport> {
+    transport: T,
+    stats: Arc<StatsRecorder>,
+    // Records delivered since the last flush of the inner transport
+    unflushed: usize,
+    _alive: AliveGuard,
+}
+
+impl<T: Transport> ThreadedWorker<T> {
+    fn new(transport: T, stats: Arc<StatsRecorder>) -> Self {
+        Self {
+            transport,
+            stats: stats.clone(),
+            unflushed: 0,
+            _alive: AliveGuard(stats),
+        }
+    }
+
+    /// Flushes the inner transport and records the outcome
+    fn flush(&mut self) -> Result<(), String> {
+        let started = Instant::now();
+        let result = self.transport.flush();
+        self.stats
+            .record_flush(self.unflushed, started.elapsed(), result.is_ok());
+        self.unflushed = 0;
+        result
+    }
+
+    /// Delivers a batch taken off the ring buffer, leaving an empty buffer
+    /// behind: the same one when the transport borrows batches, otherwise a
+    /// new one of the same capacity
+    fn log_batch(&mut self, batch: &mut Vec<LogInfo>) {
+        let records = batch.len();
+        self.stats.record_dequeued(records);
+        self.stats.set_in_flight(records);
+        if self.transport.borrows_batches() {
+            self.transport.log_batch_ref(batch);
+            batch.clear();
+        } else {
+            let full = std::mem::replace(batch, Vec::with_capacity(batch.capacity()));
+            self.transport.log_batch(full);
+        }
+        self.stats.set_in_flight(0);
+        self.unflushed += records;
+    }
+
+    /// Processes a single message, returning false once the worker should stop
+    fn handle_message(&mut self, message: TransportMessage) -> bool {
+        match message {
+            TransportMessage::Log(info, receipt) => {
+                self.stats.record_dequeued(1);
+                self.stats.set_in_flight(1);
+                self.transport.log(info);
+                self.stats.set_in_flight(0);
+                self.unflushed += 1;
+
+                // A receipt confirms the record only once it is flushed
+                if let Some(receipt) = receipt {
+                    receipt.complete(self.flush());
+                }
+            }
+            TransportMessage::Flush(receipt) => {
+                receipt.complete(self.flush());
+            }
+            TransportMessage::Query(query, response_sender) => {
+                let result = self.transport.query(&query);
+                let _ = response_sender.send(result);
+            }
+            TransportMessage::Shutdown => {
+                // Perform final flush before shutting down
+                let _ = self.flush();
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Buffers the high-throughput loop reuses for every batch it dequeues
+struct RingDrain {
+    entries: Vec<RingEntry>,
+    batch: Vec<LogInfo>,
+    receipts: Vec<ReceiptSender>,
+    max_batch: usize,
+}
+
+impl RingDrain {
+    /// Delivers up to `max_batch` records from the ring buffer, flushing after
+    /// them when any carries a receipt, and returns how many were delivered
+    fn run<T: Transport>(
+        &mut self,
+        queue: &RingBuffer<RingEntry>,
+        worker: &mut ThreadedWorker<T>,
+    ) -> usize {
+        let drained = queue.pop_batch(&mut self.entries, self.max_batch);
+        if drained == 0 {
+            return 0;
+        }
+        for (info, receipt) in self.entries.drain(..) {
+            self.batch.push(info);
+            self.receipts.extend(receipt);
+        }
+        worker.log_batch(&mut self.batch);
+
+        if !self.receipts.is_empty() {
+            let result = worker.flush();
+            for receipt in self.receipts.drain(..) {
+                receipt.complete(result.clone());
+            }
+        }
+        drained
+    }
+}
+
+/// Drives a ThreadedTransport's queue on a shared executor
+struct ThreadedTask<T: Transport> {
+    worker: ThreadedWorker<T>,
+    receiver: Receiver<TransportMessage>,
+}
+
+impl<T: Transport> ExecutorTask for ThreadedTask<T> {
+    fn run(&mut self) -> TaskStatus {
+        for _ in 0..TASK_BUDGET {
+            match self.receiver.try_recv() {
+                Ok(message) => {
+                    if !self.worker.handle_message(message) {
+                        return TaskStatus::Finished;
+                    }
+                }
+                Err(TryRecvError::Empty) => return TaskStatus::Idle(None),
+                Err(TryRecvError::Disconnected) => {
+                    let _ = self.worker.flush();
+                    return TaskStatus::Finished;
+                }
+            }
+        }
+        TaskStatus::Busy
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -295,4 +719,182 @@ mod tests {
         assert_eq!(messages.len(), 1);
         assert_eq!(messages[0], "Before shutdown");
     }
+
+    #[test]
+    fn test_threaded_transport_stats() {
+        let mock = MockTransport::new();
+        let threaded_transport = mock.into_threaded();
+
+        threaded_transport.log(LogInfo::new("INFO", "Message 1"));
+        threaded_transport.log(LogInfo::new("INFO", "Message 2"));
+        threaded_transport.flush().unwrap();
+
+        let stats = threaded_transport.stats();
+        assert_eq!(stats.queued, 0);
+        assert_eq!(stats.logged_total, 2);
+        assert_eq!(stats.flushed_total, 2);
+        assert_eq!(stats.dropped_total, 0);
+        assert_eq!(stats.flush_latency.count, 1);
+        assert!(stats.worker_alive);
+    }
+
+    #[test]
+    fn test_ring_buffer_mode_preserves_order() {
+        let mock = MockTransport::new();
+        let mock_clone = mock.clone();
+        let threaded_transport = mock.into_threaded_ring(RingBufferConfig {
+            capacity: 16,
+            max_batch: 4,
+            overflow: OverflowPolicy::Block,
+        });
+
+        for i in 0..100 {
+            threaded_transport.log(LogInfo::new("INFO", format!("Message {}", i)));
+        }
+        threaded_transport.flush().unwrap();
+
+        let messages = mock_clone.get_messages();
+        assert_eq!(messages.len(), 100);
+        for (i, message) in messages.iter().enumerate() {
+            assert_eq!(message, &format!("Message {}", i));
+        }
+
+        let stats = threaded_transport.stats();
+        assert_eq!(stats.queued, 0);
+        assert_eq!(stats.flushed_total, 100);
+    }
+
+    #[test]
+    fn test_ring_buffer_mode_drops_when_full() {
+        let slow_transport = MockTransport::with_delay(Duration::from_millis(20));
+        let slow_transport_clone = slow_transport.clone();
+        let threaded_transport = slow_transport.into_threaded_ring(RingBufferConfig {
+            capacity: 2,
+            max_batch: 1,
+            overflow: OverflowPolicy::Drop,
+        });
+
+        for i in 0..20 {
+            threaded_transport.log(LogInfo::new("INFO", format!("Message {}", i)));
+        }
+        threaded_transport.shutdown().unwrap();
+
+        let delivered = slow_transport_clone.get_messages().len();
+        assert!(delivered < 20);
+        assert!(delivered >= 2);
+    }
+
+    #[test]
+    fn test_log_with_receipt_and_flush_async() {
+        let mock = MockTransport::with_delay(Duration::from_millis(20));
+        let mock_clone = mock.clone();
+        let threaded = mock.into_threaded();
+
+        for i in 0..3 {
+            threaded.log(LogInfo::new("INFO", format!("Message {}", i)));
+        }
+        let receipt = threaded.log_with_receipt(LogInfo::new("INFO", "Audit"));
+        let handle = threaded.flush_async();
+
+        // Neither call waited for the slow transport
+        assert!(!receipt.is_complete());
+
+        assert_eq!(receipt.wait_timeout(Duration::from_secs(5)), Some(Ok(())));
+        assert_eq!(mock_clone.get_messages().last().unwrap(), "Audit");
+        assert_eq!(handle.wait(), Ok(()));
+    }
+
+    #[test]
+    fn test_ring_buffer_mode_orders_receipted_records() {
+        let mock = MockTransport::with_delay(Duration::from_millis(5));
+        let mock_clone = mock.clone();
+        let threaded_transport = mock.into_threaded_ring(RingBufferConfig::default());
+
+        threaded_transport.log(LogInfo::new("INFO", "a"));
+        let receipt = threaded_transport.log_with_receipt(LogInfo::new("INFO", "b"));
+        threaded_transport.log(LogInfo::new("INFO", "c"));
+
+        assert_eq!(receipt.wait_timeout(Duration::from_secs(5)), Some(Ok(())));
+        threaded_transport.flush().unwrap();
+        assert_eq!(mock_clone.get_messages(), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_ring_buffer_mode_reuses_buffers() {
+        /// Reads batches by reference, counting how they were handed over
+        #[derive(Clone, Default)]
+        struct BorrowingTransport {
+            borrowed: Arc<Mutex<Vec<String>>>,
+            owned_batches: Arc<Mutex<usize>>,
+        }
+
+        impl Transport for BorrowingTransport {
+            fn log(&self, info: LogInfo) {
+                self.log_batch(vec![info]);
+            }
+
+            fn log_batch(&self, _logs: Vec<LogInfo>) {
+                *self.owned_batches.lock().unwrap() += 1;
+            }
+
+            fn log_batch_ref(&self, logs: &[LogInfo]) {
+                let mut borrowed = self.borrowed.lock().unwrap();
+                borrowed.extend(logs.iter().map(|info| info.message.clone()));
+            }
+
+            fn borrows_batches(&self) -> bool {
+                true
+            }
+        }
+
+        let sink = BorrowingTransport::default();
+        let threaded_transport = sink.clone().into_threaded_ring(RingBufferConfig {
+            capacity: 16,
+            max_batch: 4,
+            overflow: OverflowPolicy::Block,
+        });
+
+        for i in 0..50 {
+            threaded_transport.log(LogInfo::new("INFO", format!("Message {}", i)));
+        }
+        threaded_transport.flush().unwrap();
+
+        assert_eq!(*sink.owned_batches.lock().unwrap(), 0);
+        let borrowed = sink.borrowed.lock().unwrap();
+        assert_eq!(borrowed.len(), 50);
+        for (i, message) in borrowed.iter().enumerate() {
+            assert_eq!(message, &format!("Message {}", i));
+        }
+    }
+
+    #[test]
+    fn test_task_flushes_when_disconnected() {
+        struct FlushCounter(Arc<Mutex<usize>>);
+
+        impl Transport for FlushCounter {
+            fn log(&self, _info: LogInfo) {}
+
+            fn flush(&self) -> Result<(), String> {
+                *self.0.lock().unwrap() += 1;
+                Ok(())
+            }
+        }
+
+        let flushes = Arc::new(Mutex::new(0));
+        let (sender, receiver) = mpsc::channel();
+        let mut task = ThreadedTask {
+            worker: ThreadedWorker::new(
+                FlushCounter(flushes.clone()),
+                Arc::new(StatsRecorder::new()),
+            ),
+            receiver,
+        };
+        sender
+            .send(TransportMessage::Log(LogInfo::new("info", "last"), None))
+            .unwrap();
+        drop(sender);
+
+        assert!(matches!(task.run(), TaskStatus::Finished));
+        assert_eq!(*flushes.lock().unwrap(), 1);
+    }
 }
@@ -9,6 +9,19 @@ pub trait Transport: Send + Sync {
             self.log(log_info);
         }
     }
+    /// Delivers a batch the caller keeps ownership of, so the buffer can be
+    /// reused for the next batch. Transports that only read the records should
+    /// override this along with `borrows_batches`; the default clones them
+    /// into `log_batch`.
+    fn log_batch_ref(&self, logs: &[LogInfo]) {
+        self.log_batch(logs.to_vec());
+    }
+    /// Whether `log_batch_ref` delivers without cloning the records. Batching
+    /// wrappers only keep their buffers and hand batches over by reference
+    /// when it does, and otherwise hand each batch over with `log_batch`.
+    fn borrows_batches(&self) -> bool {
+        false
+    }
     fn flush(&self) -> Result<(), String> {
         Ok(())
     }
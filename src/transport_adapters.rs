@@ -9,38 +9,177 @@
 //! Extension traits provide convenient `.into_writer()`, `.as_writer()`,
 //! `.into_transport()`, and `.as_transport()` methods.
 
-use crate::Transport;
+use crate::{
+    decoder::{decode_or_plain, InputDecoder},
+    encoder::{Encoder, MessageEncoder},
+    Transport,
+};
 use logform::{Format, LogInfo};
 use std::{
     io::{self, Write},
     sync::{Arc, Mutex},
 };
 
+/// Splits written bytes into lines, buffering partial lines between writes.
+struct LineFramer {
+    buffer: Vec<u8>,
+    max_line_length: usize,
+}
+
+impl LineFramer {
+    fn new(max_line_length: usize) -> Self {
+        Self {
+            buffer: Vec::new(),
+            max_line_length: max_line_length.max(1),
+        }
+    }
+
+    /// Emits every complete line in `buf`, and a piece of `max_line_length`
+    /// bytes whenever a line grows beyond it.
+    fn push(&mut self, buf: &[u8], mut emit: impl FnMut(String)) {
+        for chunk in buf.split_inclusive(|byte| *byte == b'\n') {
+            self.buffer.extend_from_slice(chunk);
+
+            while self.buffer.len() > self.max_line_length
+                && !(self.buffer.len() == self.max_line_length + 1 && self.buffer.ends_with(b"\n"))
+            {
+                // Cut at a character boundary so the piece stays valid UTF-8
+                let mut cut = self.max_line_length;
+                if let Err(e) = std::str::from_utf8(&self.buffer[..cut]) {
+                    if e.error_len().is_none() && e.valid_up_to() > 0 {
+                        cut = e.valid_up_to();
+                    }
+                }
+                let piece: Vec<u8> = self.buffer.drain(..cut).collect();
+                emit(String::from_utf8_lossy(&piece).into_owned());
+            }
+
+            if self.buffer.ends_with(b"\n") {
+                self.take_line(&mut emit);
+            }
+        }
+    }
+
+    /// Emits whatever partial line is still buffered.
+    fn finish(&mut self, mut emit: impl FnMut(String)) {
+        self.take_line(&mut emit);
+    }
+
+    fn take_line(&mut self, emit: &mut impl FnMut(String)) {
+        let mut line = std::mem::take(&mut self.buffer);
+        if line.ends_with(b"\n") {
+            line.pop();
+        }
+        if line.ends_with(b"\r") {
+            line.pop();
+        }
+        // Blank lines carry nothing worth a record
+        if !line.is_empty() {
+            emit(String::from_utf8_lossy(&line).into_owned());
+        }
+    }
+}
+
+/// Turns bytes written to a `TransportWriter` into records.
+struct WriterInput {
+    framer: Option<LineFramer>,
+    decoder: Option<Arc<dyn InputDecoder>>,
+    default_level: String,
+}
+
+impl WriterInput {
+    fn new() -> Self {
+        Self {
+            framer: None,
+            decoder: None,
+            default_level: "INFO".to_string(),
+        }
+    }
+
+    fn write<T: Transport + ?Sized>(&mut self, transport: &T, buf: &[u8]) {
+        let Self {
+            framer,
+            decoder,
+            default_level,
+        } = self;
+        match framer {
+            Some(framer) => framer.push(buf, |line| {
+                transport.log(Self::record(decoder.as_deref(), default_level, line))
+            }),
+            None => {
+                let message = String::from_utf8_lossy(buf).to_string();
+                transport.log(Self::record(decoder.as_deref(), default_level, message));
+            }
+        }
+    }
+
+    fn finish<T: Transport + ?Sized>(&mut self, transport: &T) {
+        let Self {
+            framer,
+            decoder,
+            default_level,
+        } = self;
+        if let Some(framer) = framer {
+            framer.finish(|line| {
+                transport.log(Self::record(decoder.as_deref(), default_level, line))
+            });
+        }
+    }
+
+    fn record(decoder: Option<&dyn InputDecoder>, default_level: &str, text: String) -> LogInfo {
+        match decoder {
+            Some(decoder) => {
+                decode_or_plain(decoder, text.trim_end_matches(['\r', '\n']), default_level)
+            }
+            None => LogInfo::new(default_level, text),
+        }
+    }
+}
+
 /// owned adapter: takes ownership of a Transport and uses it as a Writer.
 pub struct TransportWriter<T: Transport> {
     transport: T,
+    input: WriterInput,
 }
 
 impl<T: Transport> TransportWriter<T> {
     pub fn new(transport: T) -> Self {
-        Self { transport }
+        Self {
+            transport,
+            input: WriterInput::new(),
+        }
+    }
+
+    /// Logs one record per newline-terminated line instead of one per `write`
+    /// call. Partial lines are kept until completed, flushed or dropped, and
+    /// lines longer than `max_line_length` bytes are split.
+    pub fn with_line_framing(mut self, max_line_length: usize) -> Self {
+        self.input.framer = Some(LineFramer::new(max_line_length));
+        self
+    }
+
+    /// Parses each record with `decoder`; text it rejects is logged as a plain
+    /// message at the default level.
+    pub fn with_decoder<D: InputDecoder + 'static>(mut self, decoder: D) -> Self {
+        self.input.decoder = Some(Arc::new(decoder));
+        self
+    }
+
+    /// Sets the level of records whose text carries none (`"INFO"` by default).
+    pub fn with_default_level(mut self, level: impl Into<String>) -> Self {
+        self.input.default_level = level.into();
+        self
     }
 }
 
 impl<T: Transport> Write for TransportWriter<T> {
-    /*fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        let info = LogInfo::from_bytes(buf)?;
-        self.transport.log(info);
-        Ok(buf.len())
-    }*/
-
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        let message = String::from_utf8_lossy(buf).to_string();
-        self.transport.log(LogInfo::new("INFO", message));
+        self.input.write(&self.transport, buf);
         Ok(buf.len())
     }
 
     fn flush(&mut self) -> io::Result<()> {
+        self.input.finish(&self.transport);
         self.transport
             .flush()
             .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
@@ -56,27 +195,47 @@ impl<T: Transport> Drop for TransportWriter<T> {
 /// borrowed adapter: borrows a Transport and uses it as a Writer.
 pub struct TransportWriterRef<'a, T: Transport> {
     transport: &'a T,
+    input: WriterInput,
 }
 
 impl<'a, T: Transport> TransportWriterRef<'a, T> {
     pub fn new(transport: &'a T) -> Self {
-        Self { transport }
+        Self {
+            transport,
+            input: WriterInput::new(),
+        }
+    }
+
+    /// Logs one record per newline-terminated line instead of one per `write`
+    /// call. Partial lines are kept until completed, flushed or dropped, and
+    /// lines longer than `max_line_length` bytes are split.
+    pub fn with_line_framing(mut self, max_line_length: usize) -> Self {
+        self.input.framer = Some(LineFramer::new(max_line_length));
+        self
+    }
+
+    /// Parses each record with `decoder`; text it rejects is logged as a plain
+    /// message at the default level.
+    pub fn with_decoder<D: InputDecoder + 'static>(mut self, decoder: D) -> Self {
+        self.input.decoder = Some(Arc::new(decoder));
+        self
+    }
+
+    /// Sets the level of records whose text carries none (`"INFO"` by default).
+    pub fn with_default_level(mut self, level: impl Into<String>) -> Self {
+        self.input.default_level = level.into();
+        self
     }
 }
 
 impl<'a, T: Transport> Write for TransportWriterRef<'a, T> {
-    /*fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        let info = LogInfo::from_bytes(buf)?;
-        self.transport.log(info);
-        Ok(buf.len())
-    }*/
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        let message = String::from_utf8_lossy(buf).to_string();
-        self.transport.log(LogInfo::new("INFO", message));
+        self.input.write(self.transport, buf);
         Ok(buf.len())
     }
 
     fn flush(&mut self) -> io::Result<()> {
+        self.input.finish(self.transport);
         self.transport
             .flush()
             .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
@@ -89,11 +248,43 @@ impl<'a, T: Transport> Drop for TransportWriterRef<'a, T> {
     }
 }
 
+/// Encodes `infos` into one reused buffer, writing each as a line. A record
+/// that fails to encode or write is reported and skipped.
+fn write_records<W: Write>(
+    writer: &Mutex<W>,
+    encoder: &dyn Encoder,
+    infos: &[LogInfo],
+    name: &str,
+) {
+    if infos.is_empty() {
+        return;
+    }
+
+    let Ok(mut writer) = writer.lock() else {
+        report!("Failed to acquire writer lock for {}", name);
+        return;
+    };
+    let mut buf = Vec::new();
+    for info in infos {
+        buf.clear();
+        if let Err(e) = encoder.encode(info, &mut buf) {
+            report!("Failed to encode log entry for {}: {}", name, e);
+            continue;
+        }
+        buf.push(b'\n');
+        if let Err(e) = writer.write_all(&buf) {
+            report!("Failed to write log entry to {}: {}", name, e);
+            // Continue on error for resilience
+        }
+    }
+}
+
 /// owned adapter to use a Writer as a Transport.
 pub struct WriterTransport<W: Write + Send + Sync> {
     pub writer: Mutex<W>,
     level: Option<String>,
     format: Option<Arc<dyn Format<Input = LogInfo> + Send + Sync>>,
+    encoder: Arc<dyn Encoder>,
 }
 
 impl<W: Write + Send + Sync> WriterTransport<W> {
@@ -102,6 +293,7 @@ impl<W: Write + Send + Sync> WriterTransport<W> {
             writer: Mutex::new(writer),
             level: None,
             format: None,
+            encoder: Arc::new(MessageEncoder),
         }
     }
 
@@ -117,33 +309,34 @@ impl<W: Write + Send + Sync> WriterTransport<W> {
         self.format = Some(Arc::new(format));
         self
     }
+
+    /// Serializes records with `encoder` instead of writing only the message
+    pub fn with_encoder<E: Encoder + 'static>(mut self, encoder: E) -> Self {
+        self.encoder = Arc::new(encoder);
+        self
+    }
 }
 
 impl<W: Write + Send + Sync> Transport for WriterTransport<W> {
     fn log(&self, info: LogInfo) {
-        if let Ok(mut writer) = self.writer.lock() {
-            let _ = writeln!(writer, "{}", info.message);
-        }
+        write_records(
+            &self.writer,
+            &*self.encoder,
+            std::slice::from_ref(&info),
+            "WriterTransport",
+        );
     }
 
     fn log_batch(&self, infos: Vec<LogInfo>) {
-        if infos.is_empty() {
-            return;
-        }
+        self.log_batch_ref(&infos);
+    }
 
-        if let Ok(mut writer) = self.writer.lock() {
-            for info in infos {
-                if let Err(e) = writeln!(writer, "{}", info.message) {
-                    eprintln!(
-                        "Failed to write log entry in batch to WriterTransport: {}",
-                        e
-                    );
-                    // Continue on error for resilience
-                }
-            }
-        } else {
-            eprintln!("Failed to acquire writer lock for WriterTransport batch logging");
-        }
+    fn log_batch_ref(&self, infos: &[LogInfo]) {
+        write_records(&self.writer, &*self.encoder, infos, "WriterTransport");
+    }
+
+    fn borrows_batches(&self) -> bool {
+        true
     }
 
     fn get_level(&self) -> Option<&String> {
@@ -179,6 +372,7 @@ pub struct WriterTransportRef<'a, W: Write + Send + Sync> {
     writer: &'a Mutex<W>,
     level: Option<String>,
     format: Option<Arc<dyn Format<Input = LogInfo> + Send + Sync>>,
+    encoder: Arc<dyn Encoder>,
 }
 
 impl<'a, W: Write + Send + Sync> WriterTransportRef<'a, W> {
@@ -187,6 +381,7 @@ impl<'a, W: Write + Send + Sync> WriterTransportRef<'a, W> {
             writer,
             level: None,
             format: None,
+            encoder: Arc::new(MessageEncoder),
         }
     }
 
@@ -202,33 +397,34 @@ impl<'a, W: Write + Send + Sync> WriterTransportRef<'a, W> {
         self.format = Some(Arc::new(format));
         self
     }
+
+    /// Serializes records with `encoder` instead of writing only the message
+    pub fn with_encoder<E: Encoder + 'static>(mut self, encoder: E) -> Self {
+        self.encoder = Arc::new(encoder);
+        self
+    }
 }
 
 impl<'a, W: Write + Send + Sync> Transport for WriterTransportRef<'a, W> {
     fn log(&self, info: LogInfo) {
-        if let Ok(mut writer) = self.writer.lock() {
-            let _ = writeln!(writer, "{}", info.message);
-        }
+        write_records(
+            self.writer,
+            &*self.encoder,
+            std::slice::from_ref(&info),
+            "WriterTransportRef",
+        );
     }
 
     fn log_batch(&self, infos: Vec<LogInfo>) {
-        if infos.is_empty() {
-            return;
-        }
+        self.log_batch_ref(&infos);
+    }
 
-        if let Ok(mut writer) = self.writer.lock() {
-            for info in infos {
-                if let Err(e) = writeln!(writer, "{}", info.message) {
-                    eprintln!(
-                        "Failed to write log entry in batch to WriterTransportRef: {}",
-                        e
-                    );
-                    // Continue on error for resilience
-                }
-            }
-        } else {
-            eprintln!("Failed to acquire writer lock for WriterTransportRef batch logging");
-        }
+    fn log_batch_ref(&self, infos: &[LogInfo]) {
+        write_records(self.writer, &*self.encoder, infos, "WriterTransportRef");
+    }
+
+    fn borrows_batches(&self) -> bool {
+        true
     }
 
     fn get_level(&self) -> Option<&String> {
@@ -313,28 +509,37 @@ impl<W: Write + Send + Sync> AsWriterTransport for Mutex<W> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::decoder::{DecoderChain, JsonLinesDecoder, PrefixLevelDecoder};
+    use crate::encoder::LogfmtEncoder;
     use std::sync::Arc;
 
     #[derive(Clone)]
     struct MockTransport {
-        messages: Arc<Mutex<Vec<String>>>,
+        infos: Arc<Mutex<Vec<LogInfo>>>,
     }
 
     impl MockTransport {
         fn new() -> Self {
             Self {
-                messages: Arc::new(Mutex::new(Vec::new())),
+                infos: Arc::new(Mutex::new(Vec::new())),
             }
         }
 
         fn get_messages(&self) -> Vec<String> {
-            self.messages.lock().unwrap().clone()
+            self.get_infos()
+                .into_iter()
+                .map(|info| info.message)
+                .collect()
+        }
+
+        fn get_infos(&self) -> Vec<LogInfo> {
+            self.infos.lock().unwrap().clone()
         }
     }
 
     impl Transport for MockTransport {
         fn log(&self, info: LogInfo) {
-            self.messages.lock().unwrap().push(info.message);
+            self.infos.lock().unwrap().push(info);
         }
     }
 
@@ -401,11 +606,14 @@ mod tests {
 
         transport.log(LogInfo::new("INFO", "Test log 1"));
         transport.log(LogInfo::new("INFO", "Test log 2"));
+        assert!(transport.borrows_batches());
+        transport.log_batch_ref(&[LogInfo::new("INFO", "Test log 3")]);
 
         let writer_guard = transport.writer.lock().unwrap();
         let content = writer_guard.contents_as_string();
         assert!(content.contains("Test log 1"));
         assert!(content.contains("Test log 2"));
+        assert!(content.contains("Test log 3"));
     }
 
     #[test]
@@ -415,11 +623,101 @@ mod tests {
 
         transport_ref.log(LogInfo::new("INFO", "Borrowed log 1"));
         transport_ref.log(LogInfo::new("INFO", "Borrowed log 2"));
+        assert!(transport_ref.borrows_batches());
+        transport_ref.log_batch_ref(&[LogInfo::new("INFO", "Borrowed log 3")]);
         transport_ref.flush().unwrap();
 
         let buffer_guard = test_buffer.lock().unwrap();
         let contents = buffer_guard.contents_as_string();
         assert!(contents.contains("Borrowed log 1"));
         assert!(contents.contains("Borrowed log 2"));
+        assert!(contents.contains("Borrowed log 3"));
+    }
+
+    #[test]
+    fn test_line_framing_joins_and_splits_writes() {
+        let transport = MockTransport::new();
+        let transport_clone = transport.clone();
+        let mut writer = transport.into_writer().with_line_framing(1024);
+
+        // One line across several writes, then several lines in one write
+        write!(writer, "Split ").unwrap();
+        write!(writer, "{} ", 42).unwrap();
+        writer
+            .write_all(b"line\nSecond\r\nThird\n\nPartial")
+            .unwrap();
+        assert_eq!(
+            transport_clone.get_messages(),
+            vec!["Split 42 line", "Second", "Third"]
+        );
+
+        // The remainder is emitted on flush
+        writer.flush().unwrap();
+        assert_eq!(transport_clone.get_messages().len(), 4);
+        assert_eq!(transport_clone.get_messages()[3], "Partial");
+    }
+
+    #[test]
+    fn test_line_framing_max_length_and_drop() {
+        let transport = MockTransport::new();
+        {
+            let mut writer = transport.as_writer().with_line_framing(4);
+            writer.write_all(b"abcdefghij\nxyz").unwrap();
+        }
+
+        // Long lines are split, and the partial line is emitted on drop
+        assert_eq!(transport.get_messages(), vec!["abcd", "efgh", "ij", "xyz"]);
+    }
+
+    #[test]
+    fn test_decoded_records() {
+        let transport = MockTransport::new();
+        {
+            let mut writer = transport
+                .as_writer()
+                .with_line_framing(1024)
+                .with_decoder(
+                    DecoderChain::new()
+                        .with(JsonLinesDecoder)
+                        .with(PrefixLevelDecoder::new()),
+                )
+                .with_default_level("notice");
+            writeln!(writer, r#"{{"level":"error","message":"json","code":5}}"#).unwrap();
+            writeln!(writer, "[WARN] prefixed").unwrap();
+            writeln!(writer, "plain text").unwrap();
+        }
+
+        let infos = transport.get_infos();
+        let records: Vec<(&str, &str)> = infos
+            .iter()
+            .map(|info| (info.level.as_str(), info.message.as_str()))
+            .collect();
+        assert_eq!(
+            records,
+            vec![
+                ("error", "json"),
+                ("warn", "prefixed"),
+                ("notice", "plain text")
+            ]
+        );
+        assert_eq!(infos[0].meta.get("code"), Some(&serde_json::json!(5)));
+    }
+
+    #[test]
+    fn test_writer_transport_encoder() {
+        let writer = Mutex::new(TestBuffer::new());
+        {
+            let transport = writer.as_transport().with_encoder(LogfmtEncoder);
+            transport.log(LogInfo::new("warn", "disk low").with_meta("free", 10));
+            transport.log_batch(vec![
+                LogInfo::new("info", "first"),
+                LogInfo::new("error", "second"),
+            ]);
+        }
+
+        assert_eq!(
+            writer.lock().unwrap().contents_as_string(),
+            "level=warn msg=\"disk low\" free=10\nlevel=info msg=first\nlevel=error msg=second\n"
+        );
     }
 }